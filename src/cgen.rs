@@ -0,0 +1,707 @@
+use std::{
+    collections::HashMap,
+    env,
+    fmt::Write as _,
+    fs,
+    io::{self, Write as _},
+    path::Path,
+    process::{Command, Stdio},
+};
+
+use thiserror::Error;
+
+use crate::{
+    erase::*,
+    prelude::*,
+    syntax::*,
+    template::{template, Chunk},
+};
+
+const RUNTIME: &str = include_str!("runtime.c");
+
+/// Runs the whole program, which is the first function, and prints its value.
+const MAIN: &str = "
+int main(void) {
+    char base;
+    stack_base = &base;
+    print(settle(f0(NULL, NULL)), 0);
+    putchar('\\n');
+    return 0;
+}
+";
+
+/// Compiles an erased program to C that prints its value the way the
+/// interpreter does.
+pub fn generate(term: &Erased, names: &Names) -> String {
+    Generator::new(names).generate(term)
+}
+
+/// Writes a compiled program to `output` if it names a C file, and compiles
+/// it to an executable with the system C compiler otherwise. The compiler is
+/// taken from `$CC`, and is `cc` by default.
+pub fn build(source: &str, output: &Path) -> Result<(), BuildError> {
+    if output.extension().is_some_and(|ext| ext == "c") {
+        return fs::write(output, source).map_err(BuildError::NotWritten);
+    }
+    let compiler = env::var("CC").unwrap_or_else(|_| "cc".into());
+    let not_run = |err| BuildError::NotRun(compiler.clone(), err);
+    let mut child = Command::new(&compiler)
+        .args(["-O2", "-x", "c", "-", "-o"])
+        .arg(output)
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(not_run)?;
+    let written = child.stdin.take().unwrap().write_all(source.as_bytes());
+    let status = child.wait().map_err(not_run)?;
+    match written {
+        Ok(()) if status.success() => Ok(()),
+        _ => Err(BuildError::Failed(compiler)),
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum BuildError {
+    #[error("Failed to write the C program: {0}")]
+    NotWritten(io::Error),
+    #[error("Failed to run the C compiler {0}: {1}")]
+    NotRun(String, io::Error),
+    #[error("The C compiler {0} failed.")]
+    Failed(String),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Value,
+    /// Bound by a recursive definition, which is unrolled on every use.
+    Fix,
+}
+
+/// A function being generated: the body of an abstraction, a lazy side of a
+/// pair, a recursive definition or the whole program. Each one becomes a C
+/// function that takes the closure holding its captured values.
+struct Function {
+    index: usize,
+    body: String,
+    indent: usize,
+    /// Variables in scope and the C expressions that hold them.
+    locals: Vec<(Var, Kind, String)>,
+    /// The free variables of the source and where closures take them from.
+    captures: Vec<(Var, Kind, String)>,
+    source: Erased,
+}
+
+enum Task<'t> {
+    Compile(&'t Erased, bool),
+    /// Pushes a C expression as an operand.
+    Push(String),
+    /// Binds the operand on top to the variables of a pattern.
+    Bind(&'t Pattern),
+    Unbind(usize),
+    Tuple(usize),
+    Proj(usize),
+    Record(&'t [(String, Erased)]),
+    Field(&'t str),
+    Update(&'t [(String, Erased)]),
+    Inj(&'t str),
+    Case(&'t [(String, Pattern, Erased)], bool),
+    /// Assigns the operand on top to the result of a `case`.
+    Assign(String),
+    /// Opens a block with the given line.
+    Open(String),
+    Close,
+    Call(bool),
+    Enter(Erased, Option<(Var, Kind)>),
+    Exit,
+    Closure,
+    With,
+    Fix,
+}
+
+/// Generates C with a stack of tasks rather than by recursion, one statement
+/// per node, with operands kept as C expressions.
+struct Generator<'n> {
+    names: &'n Names,
+    functions: Vec<Function>,
+    /// The functions that have been generated, and the expressions that
+    /// closures of them take their captured values from.
+    finished: Vec<(usize, Vec<String>)>,
+    done: Vec<String>,
+    count: usize,
+    temps: usize,
+    labels: HashMap<String, usize>,
+    label_names: Vec<String>,
+    /// Static data: the labels of records, and the templates and code of
+    /// functions.
+    data: String,
+    definitions: String,
+}
+
+impl<'n> Generator<'n> {
+    fn new(names: &'n Names) -> Self {
+        Self {
+            names,
+            functions: vec![],
+            finished: vec![],
+            done: vec![],
+            count: 0,
+            temps: 0,
+            labels: HashMap::new(),
+            label_names: vec![],
+            data: String::new(),
+            definitions: String::new(),
+        }
+    }
+
+    fn generate(mut self, term: &Erased) -> String {
+        let mut tasks = vec![
+            Task::Exit,
+            Task::Compile(term, true),
+            Task::Enter(term.clone(), None),
+        ];
+        while let Some(task) = tasks.pop() {
+            match task {
+                Task::Compile(term, tail) => self.term(term, tail, &mut tasks),
+                Task::Push(operand) => self.done.push(operand),
+                Task::Bind(pattern) => {
+                    let operand = self.pop();
+                    self.bind(pattern, operand)
+                }
+                Task::Unbind(count) => {
+                    let locals = &mut self.function().locals;
+                    locals.truncate(locals.len() - count)
+                }
+                Task::Tuple(len) => {
+                    let items = self.done.split_off(self.done.len() - len);
+                    let tuple = self.make("TUPLE", &items);
+                    self.done.push(tuple)
+                }
+                Task::Proj(index) => {
+                    let x = self.pop();
+                    self.done.push(format!("{}->items[{}]", x, index))
+                }
+                Task::Record(fields) => {
+                    let values =
+                        self.done.split_off(self.done.len() - fields.len());
+                    let labels = self.fields(fields);
+                    let record = self.make("RECORD", &values);
+                    self.line(format!("{}->labels = {};", record, labels));
+                    self.done.push(record)
+                }
+                Task::Field(label) => {
+                    let x = self.pop();
+                    let label = self.label(label);
+                    let field = format!("field({}, {})", x, label);
+                    let field = self.temp(field);
+                    self.done.push(field)
+                }
+                Task::Update(fields) => {
+                    let values =
+                        self.done.split_off(self.done.len() - fields.len());
+                    let x = self.pop();
+                    let record = self.temp(format!("update({})", x));
+                    for ((label, _), value) in fields.iter().zip(values) {
+                        let label = self.label(label);
+                        self.line(format!(
+                            "set_field({}, {}, {});",
+                            record, label, value
+                        ));
+                    }
+                    self.done.push(record)
+                }
+                Task::Inj(label) => {
+                    let x = self.pop();
+                    let label = self.label(label);
+                    let inj = self.temp(format!("inj({}, {})", label, x));
+                    self.done.push(inj)
+                }
+                Task::Case(arms, tail) => self.case(arms, tail, &mut tasks),
+                Task::Assign(result) => {
+                    let y = self.pop();
+                    self.line(format!("{} = {};", result, y));
+                    self.line("break;".into())
+                }
+                Task::Open(line) => {
+                    self.line(line);
+                    self.function().indent += 1
+                }
+                Task::Close => {
+                    self.function().indent -= 1;
+                    self.line("}".into())
+                }
+                Task::Call(tail) => {
+                    let x = self.pop();
+                    let f = self.pop();
+                    let call = if tail { "tail_call" } else { "apply" };
+                    let result = self.temp(format!("{}({}, {})", call, f, x));
+                    self.done.push(result)
+                }
+                Task::Enter(source, param) => self.enter(source, param),
+                Task::Exit => self.exit(),
+                Task::Closure => {
+                    let closure = self.closure("CLOSURE");
+                    self.done.push(closure)
+                }
+                Task::With => {
+                    let r = self.closure("CLOSURE");
+                    let l = self.closure("CLOSURE");
+                    let with = self.make("WITH", &[l, r]);
+                    self.done.push(with)
+                }
+                Task::Fix => {
+                    let fix = self.closure("FIX");
+                    let unrolled = self.temp(format!("unroll({})", fix));
+                    self.done.push(unrolled)
+                }
+            }
+        }
+        self.program()
+    }
+
+    fn term<'t>(
+        &mut self,
+        term: &'t Erased,
+        tail: bool,
+        tasks: &mut Vec<Task<'t>>,
+    ) {
+        let mut then = vec![];
+        match &**term {
+            ETuple(items) => {
+                then.extend(items.iter().map(|x| Task::Compile(x, false)));
+                then.push(Task::Tuple(items.len()))
+            }
+            EProj(x, index) => {
+                then.extend(vec![Task::Compile(x, false), Task::Proj(*index)])
+            }
+            EWith(l, r) => then.extend(vec![
+                Task::Enter(l.clone(), None),
+                Task::Compile(l, true),
+                Task::Exit,
+                Task::Enter(r.clone(), None),
+                Task::Compile(r, true),
+                Task::Exit,
+                Task::With,
+            ]),
+            ERecord(fields) => {
+                then.extend(
+                    fields.iter().map(|(_, x)| Task::Compile(x, false)),
+                );
+                then.push(Task::Record(fields))
+            }
+            EField(x, l) => {
+                then.extend(vec![Task::Compile(x, false), Task::Field(l)])
+            }
+            EUpdate(x, fields) => {
+                then.push(Task::Compile(x, false));
+                then.extend(
+                    fields.iter().map(|(_, y)| Task::Compile(y, false)),
+                );
+                then.push(Task::Update(fields))
+            }
+            EInj(l, x) => {
+                then.extend(vec![Task::Compile(x, false), Task::Inj(l)])
+            }
+            ECase(x, arms) => then
+                .extend(vec![Task::Compile(x, false), Task::Case(arms, tail)]),
+            EVar(var) => {
+                let (operand, kind) = self.resolve(*var);
+                let operand = match kind {
+                    Kind::Value => operand,
+                    Kind::Fix => self.temp(format!("unroll({})", operand)),
+                };
+                self.done.push(operand)
+            }
+            EAbs(v, y) => then.extend(vec![
+                Task::Enter(term.clone(), Some((*v, Kind::Value))),
+                Task::Compile(y, true),
+                Task::Exit,
+                Task::Closure,
+            ]),
+            EApp(f, x) => then.extend(vec![
+                Task::Compile(f, false),
+                Task::Compile(x, false),
+                Task::Call(tail),
+            ]),
+            ELet(p, x, y) => then.extend(vec![
+                Task::Compile(x, false),
+                Task::Bind(p),
+                Task::Compile(y, tail),
+                Task::Unbind(arity(p)),
+            ]),
+            EFix(f, x) => then.extend(vec![
+                Task::Enter(term.clone(), Some((*f, Kind::Fix))),
+                Task::Compile(x, true),
+                Task::Exit,
+                Task::Fix,
+            ]),
+            EHole(name) => {
+                let name = match name {
+                    Some(name) => c_string(name),
+                    None => "NULL".into(),
+                };
+                let hole = self.temp(format!("hole({})", name));
+                self.done.push(hole)
+            }
+        }
+        tasks.extend(then.into_iter().rev())
+    }
+
+    /// Switches on the label of the operand on top, with the result of the
+    /// arm that runs assigned to a new variable.
+    fn case<'t>(
+        &mut self,
+        arms: &'t [(String, Pattern, Erased)],
+        tail: bool,
+        tasks: &mut Vec<Task<'t>>,
+    ) {
+        let x = self.pop();
+        let result = self.fresh("t");
+        self.line(format!("value {};", result));
+        let mut then = vec![Task::Open(format!("switch ({}->label) {{", x))];
+        for (l, p, y) in arms {
+            let label = self.label(l);
+            then.extend(vec![
+                Task::Open(format!("case {}: {{", label)),
+                Task::Push(format!("{}->items[0]", x)),
+                Task::Bind(p),
+                Task::Compile(y, tail),
+                Task::Assign(result.clone()),
+                Task::Close,
+                Task::Unbind(arity(p)),
+            ]);
+        }
+        then.extend(vec![Task::Close, Task::Push(result)]);
+        tasks.extend(then.into_iter().rev())
+    }
+
+    fn bind(&mut self, pattern: &Pattern, operand: String) {
+        match pattern {
+            PtVar(var) => {
+                let local = self.fresh("x");
+                self.line(format!("value {} = {};", local, operand));
+                self.function().locals.push((*var, Kind::Value, local))
+            }
+            PtWild => {}
+            PtTuple(items) => {
+                for (i, item) in items.iter().enumerate() {
+                    self.bind(item, format!("{}->items[{}]", operand, i))
+                }
+            }
+            PtLeft(p) => {
+                let side = self.temp(format!("force({}->items[0])", operand));
+                self.bind(p, side)
+            }
+            PtRight(p) => {
+                let side = self.temp(format!("force({}->items[1])", operand));
+                self.bind(p, side)
+            }
+        }
+    }
+
+    /// Finds where `var` is bound, capturing it in every function between
+    /// the binding and the current one.
+    fn resolve(&mut self, var: Var) -> (String, Kind) {
+        let mut depth = self.functions.len() - 1;
+        let (mut operand, kind) = loop {
+            let function = &self.functions[depth];
+            let mut locals = function.locals.iter().rev();
+            if let Some((_, kind, local)) = locals.find(|(v, _, _)| *v == var) {
+                break (local.clone(), *kind);
+            }
+            let mut captures = function.captures.iter();
+            if let Some(i) = captures.position(|(v, _, _)| *v == var) {
+                break (captured(i), function.captures[i].1);
+            }
+            depth -= 1;
+        };
+        for function in &mut self.functions[depth + 1..] {
+            function.captures.push((var, kind, operand));
+            operand = captured(function.captures.len() - 1);
+        }
+        (operand, kind)
+    }
+
+    fn enter(&mut self, source: Erased, param: Option<(Var, Kind)>) {
+        let mut function = Function {
+            index: self.count,
+            body: String::new(),
+            indent: 1,
+            locals: vec![],
+            captures: vec![],
+            source,
+        };
+        self.count += 1;
+        match param {
+            Some((var, Kind::Value)) => {
+                let local = self.fresh("x");
+                let _ = writeln!(function.body, "    value {} = arg;", local);
+                function.locals.push((var, Kind::Value, local))
+            }
+            Some((var, Kind::Fix)) => {
+                function.locals.push((var, Kind::Fix, "self".into()))
+            }
+            None => {}
+        }
+        self.functions.push(function)
+    }
+
+    fn exit(&mut self) {
+        let function = self.functions.pop().unwrap();
+        let result = self.pop();
+        let index = function.index;
+        let _ = write!(
+            self.definitions,
+            "\nstatic value f{}(value self, value arg) {{\n{}    \
+             return {};\n}}\n",
+            index, function.body, result
+        );
+        if self.functions.is_empty() {
+            return;
+        }
+        let vars: Vec<_> =
+            function.captures.iter().map(|(v, _, _)| *v).collect();
+        let chunks = template(&function.source, &vars, self.names);
+        let chunks: Vec<_> = chunks
+            .iter()
+            .map(|chunk| match chunk {
+                Chunk::Text(text) => format!("{{{}, 0, 0}}", c_string(text)),
+                Chunk::Hole { index, arg } => {
+                    format!("{{NULL, {}, {}}}", index, *arg as u8)
+                }
+            })
+            .collect();
+        let _ = write!(
+            self.data,
+            "static const struct chunk template{}[] = {{{}}};\n\
+             static const struct code code{} = {{f{}, template{}, {}}};\n",
+            index,
+            chunks.join(", "),
+            index,
+            index,
+            index,
+            chunks.len()
+        );
+        let captures = function.captures.into_iter();
+        let operands = captures.map(|(_, _, operand)| operand).collect();
+        self.finished.push((index, operands))
+    }
+
+    /// Builds a closure of the function generated last.
+    fn closure(&mut self, kind: &str) -> String {
+        let (index, operands) = self.finished.pop().unwrap();
+        let closure = self.make(kind, &operands);
+        self.line(format!("{}->code = &code{};", closure, index));
+        closure
+    }
+
+    fn make(&mut self, kind: &str, items: &[String]) -> String {
+        let object = format!("make({}, {})", kind, items.len());
+        let object = self.temp(object);
+        for (i, item) in items.iter().enumerate() {
+            self.line(format!("{}->items[{}] = {};", object, i, item));
+        }
+        object
+    }
+
+    /// The static array of the labels of a record.
+    fn fields(&mut self, fields: &[(String, Erased)]) -> String {
+        if fields.is_empty() {
+            return "NULL".into();
+        }
+        let labels: Vec<_> = fields
+            .iter()
+            .map(|(label, _)| self.label(label).to_string())
+            .collect();
+        let array = self.fresh("fields");
+        let _ = writeln!(
+            self.data,
+            "static const int {}[] = {{{}}};",
+            array,
+            labels.join(", ")
+        );
+        array
+    }
+
+    fn label(&mut self, label: &str) -> usize {
+        if let Some(index) = self.labels.get(label) {
+            return *index;
+        }
+        self.label_names.push(label.into());
+        self.labels.insert(label.into(), self.label_names.len() - 1);
+        self.label_names.len() - 1
+    }
+
+    /// Assigns a C expression to a new variable.
+    fn temp(&mut self, expr: String) -> String {
+        let temp = self.fresh("t");
+        self.line(format!("value {} = {};", temp, expr));
+        temp
+    }
+
+    fn fresh(&mut self, prefix: &str) -> String {
+        self.temps += 1;
+        format!("{}{}", prefix, self.temps)
+    }
+
+    fn line(&mut self, line: String) {
+        let function = self.function();
+        let indent = "    ".repeat(function.indent);
+        let _ = writeln!(function.body, "{}{}", indent, line);
+    }
+
+    fn pop(&mut self) -> String {
+        self.done.pop().unwrap()
+    }
+
+    fn function(&mut self) -> &mut Function {
+        self.functions.last_mut().unwrap()
+    }
+
+    fn program(self) -> String {
+        let mut program = String::from("/* Generated by odlang. */\n\n");
+        program += RUNTIME;
+        let names = self.label_names.iter().map(|name| c_string(name));
+        let names: Vec<_> = names.chain(Some("NULL".into())).collect();
+        let _ = writeln!(
+            program,
+            "\nconst char *const label_names[] = {{{}}};\n",
+            names.join(", ")
+        );
+        for index in 0..self.count {
+            let _ = writeln!(program, "static value f{}(value, value);", index);
+        }
+        program.push('\n');
+        program += &self.data;
+        program += &self.definitions;
+        program += MAIN;
+        program
+    }
+}
+
+fn captured(index: usize) -> String {
+    format!("self->items[{}]", index)
+}
+
+fn arity(pattern: &Pattern) -> usize {
+    match pattern {
+        PtVar(_) => 1,
+        PtWild => 0,
+        PtTuple(items) => items.iter().map(arity).sum(),
+        PtLeft(p) | PtRight(p) => arity(p),
+    }
+}
+
+/// Quotes text as a C string literal. Question marks are escaped so that
+/// they cannot start trigraphs.
+fn c_string(text: &str) -> String {
+    let mut literal = String::from("\"");
+    for byte in text.bytes() {
+        match byte {
+            b'\\' | b'"' | b'?' => {
+                literal.push('\\');
+                literal.push(byte as char)
+            }
+            b' '..=b'~' => literal.push(byte as char),
+            _ => {
+                let _ = write!(literal, "\\{:03o}", byte);
+            }
+        }
+    }
+    literal.push('"');
+    literal
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process;
+
+    use super::*;
+    use crate::{
+        erase::erase,
+        eval::{eval, Budget, CallByValue},
+        testing::{named, programs, CHURCH, DEEP, MILLION},
+    };
+
+    /// Builds and runs C source, or returns `None` if there is no C
+    /// compiler.
+    fn execute(source: &str, id: usize) -> Option<process::Output> {
+        let compiler = env::var("CC").unwrap_or_else(|_| "cc".into());
+        Command::new(compiler).arg("--version").output().ok()?;
+        let name = format!("odlang-c-{}-{}", process::id(), id);
+        let path = env::temp_dir().join(name);
+        build(source, &path).unwrap();
+        let output = Command::new(&path).output().unwrap();
+        fs::remove_file(&path).unwrap();
+        Some(output)
+    }
+
+    /// Builds and runs a program, or returns `None` if there is no C
+    /// compiler.
+    fn run(term: &Term, names: &Names, id: usize) -> Option<String> {
+        let output = execute(&generate(&erase(term), names), id)?;
+        assert!(output.status.success());
+        Some(String::from_utf8(output.stdout).unwrap())
+    }
+
+    #[test]
+    fn agrees_with_eval() {
+        for (id, (term, names)) in programs().into_iter().enumerate() {
+            let value = eval(erase(&term), CallByValue, Budget::default());
+            let expected = value.unwrap().pprint(&names);
+            match run(&term, &names, id) {
+                Some(output) => assert_eq!(output, expected + "\n"),
+                None => return,
+            }
+        }
+    }
+
+    #[test]
+    fn deep_recursion() {
+        for (id, body) in ["length xs", "xs"].iter().enumerate() {
+            let source = format!("{}{}{}", CHURCH, DEEP, body);
+            let (term, names) = named(&source);
+            let value = eval(erase(&term), CallByValue, Budget::default());
+            let expected = value.unwrap().pprint(&names);
+            let program = generate(&erase(&term), &names);
+            let output = match execute(&program, usize::MAX - 2 - id) {
+                Some(output) => output,
+                None => return,
+            };
+            let message = String::from_utf8(output.stderr).unwrap();
+            match output.status.code() {
+                Some(0) => {
+                    assert_eq!(output.stdout, (expected + "\n").as_bytes())
+                }
+                code => assert!(
+                    code == Some(1) && message.starts_with("Stack overflow")
+                ),
+            }
+        }
+    }
+
+    #[test]
+    fn holes() {
+        let (term, names) = named("let f = x: () -> x; f ?later");
+        let program = generate(&erase(&term), &names);
+        assert!(program.contains("hole(\"later\")"));
+    }
+
+    #[test]
+    fn heap_limit() {
+        let (term, names) = named(&format!("{}{}", CHURCH, MILLION));
+        let source = generate(&erase(&term), &names);
+        let limited = format!("#define HEAP_LIMIT (1 << 20)\n{}", source);
+        if let Some(output) = execute(&limited, usize::MAX - 1) {
+            assert!(!output.status.success());
+            let message = String::from_utf8(output.stderr).unwrap();
+            assert!(message.starts_with("Out of memory"));
+        }
+    }
+
+    #[test]
+    fn tail_calls() {
+        let (term, names) = named(&format!("{}{}", CHURCH, MILLION));
+        if let Some(output) = run(&term, &names, usize::MAX) {
+            assert_eq!(output, "()\n")
+        }
+    }
+}
@@ -0,0 +1,518 @@
+use std::{mem, ops::Deref, rc::Rc};
+
+use crate::{prelude::*, subst::binds, syntax::*};
+
+/// An untyped term, which the evaluator, the bytecode machine and the code
+/// generators run after type checking.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Erased(Rc<ErasedData>);
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[allow(clippy::enum_variant_names)]
+pub enum ErasedData {
+    ETuple(Vec<Erased>),
+    EProj(Erased, usize),
+    EWith(Erased, Erased),
+    ERecord(Vec<(String, Erased)>),
+    EField(Erased, String),
+    EUpdate(Erased, Vec<(String, Erased)>),
+    EInj(String, Erased),
+    ECase(Erased, Vec<(String, Pattern, Erased)>),
+    EHole(Option<String>),
+    EVar(Var),
+    EAbs(Var, Erased),
+    EApp(Erased, Erased),
+    ELet(Pattern, Erased, Erased),
+    EFix(Var, Erased),
+}
+
+pub use ErasedData::*;
+
+/// Erases the types from a type-checked term. Annotations are dropped, as are
+/// folds, unfolds and packs, and unpacking becomes a `let`.
+///
+/// A type abstraction becomes a function of its type variable, which its body
+/// never uses, and a type application passes it `()`. Type abstractions stay
+/// values, so call-by-value evaluation still stops at them.
+pub fn erase(term: &Term) -> Erased {
+    enum Task<'t> {
+        Erase(&'t Term),
+        Build(&'t Term),
+    }
+    let mut tasks = vec![Task::Erase(term)];
+    let mut done = vec![];
+    while let Some(task) = tasks.pop() {
+        match task {
+            Task::Erase(term) => match &**term {
+                TmFold(_, x) | TmUnfold(x) | TmPack(_, x, _) => {
+                    tasks.push(Task::Erase(x))
+                }
+                TmVar(var) => done.push(EVar(*var).into()),
+                TmHole(name) => done.push(EHole(name.clone()).into()),
+                _ => {
+                    tasks.push(Task::Build(term));
+                    tasks.extend(
+                        children(term).into_iter().rev().map(Task::Erase),
+                    )
+                }
+            },
+            Task::Build(term) => {
+                let term = build(term, &mut done);
+                done.push(term)
+            }
+        }
+    }
+    pop(&mut done)
+}
+
+fn children(term: &Term) -> Vec<&Term> {
+    match &**term {
+        TmTuple(items) => items.iter().collect(),
+        TmRecord(fields) => fields.iter().map(|(_, x)| x).collect(),
+        TmUpdate(x, fields) => Some(x)
+            .into_iter()
+            .chain(fields.iter().map(|(_, y)| y))
+            .collect(),
+        TmCase(x, arms) => Some(x)
+            .into_iter()
+            .chain(arms.iter().map(|(_, _, y)| y))
+            .collect(),
+        TmProj(x, _)
+        | TmField(x, _)
+        | TmInj(_, _, x)
+        | TmAbs(_, _, x)
+        | TmTyAbs(_, x)
+        | TmTyApp(x, _)
+        | TmFix(_, _, x) => vec![x],
+        TmWith(x, y)
+        | TmApp(x, y)
+        | TmLet(_, _, x, y)
+        | TmUnpack(_, _, x, y) => vec![x, y],
+        TmFold(_, _)
+        | TmUnfold(_)
+        | TmPack(_, _, _)
+        | TmVar(_)
+        | TmHole(_)
+        | TmQualAbs(_, _, _)
+        | TmClass(_, _)
+        | TmInstance(_, _)
+        | TmError => unreachable!(),
+    }
+}
+
+fn build(term: &Term, done: &mut Vec<Erased>) -> Erased {
+    let erased = match &**term {
+        TmTuple(items) => ETuple(done.split_off(done.len() - items.len())),
+        TmProj(_, index) => EProj(pop(done), *index),
+        TmWith(_, _) => {
+            let r = pop(done);
+            EWith(pop(done), r)
+        }
+        TmRecord(fields) => {
+            let values = done.split_off(done.len() - fields.len());
+            ERecord(relabel(fields, values))
+        }
+        TmField(_, l) => EField(pop(done), l.clone()),
+        TmUpdate(_, fields) => {
+            let values = done.split_off(done.len() - fields.len());
+            EUpdate(pop(done), relabel(fields, values))
+        }
+        TmInj(_, l, _) => EInj(l.clone(), pop(done)),
+        TmCase(_, arms) => {
+            let ys = done.split_off(done.len() - arms.len());
+            let arms = arms
+                .iter()
+                .zip(ys)
+                .map(|((l, p, _), y)| (l.clone(), p.clone(), y))
+                .collect();
+            ECase(pop(done), arms)
+        }
+        TmAbs(v, _, _) | TmTyAbs(v, _) => EAbs(*v, pop(done)),
+        TmApp(_, _) => {
+            let x = pop(done);
+            EApp(pop(done), x)
+        }
+        TmTyApp(_, _) => EApp(pop(done), ETuple(vec![]).into()),
+        TmLet(p, _, _, _) | TmUnpack(_, p, _, _) => {
+            let y = pop(done);
+            ELet(p.clone(), pop(done), y)
+        }
+        TmFix(f, _, _) => EFix(*f, pop(done)),
+        TmFold(_, _)
+        | TmUnfold(_)
+        | TmPack(_, _, _)
+        | TmVar(_)
+        | TmHole(_)
+        | TmQualAbs(_, _, _)
+        | TmClass(_, _)
+        | TmInstance(_, _)
+        | TmError => unreachable!(),
+    };
+    erased.into()
+}
+
+fn pop<T>(done: &mut Vec<T>) -> T {
+    done.pop().unwrap()
+}
+
+fn relabel<T, U>(fields: &[(String, T)], values: Vec<U>) -> Vec<(String, U)> {
+    let labels = fields.iter().map(|(label, _)| label.clone());
+    labels.zip(values).collect()
+}
+
+impl Erased {
+    /// The subterms of the term, in the order `rebuild` takes them.
+    pub fn children(&self) -> Vec<&Erased> {
+        match &**self {
+            ETuple(items) => items.iter().collect(),
+            ERecord(fields) => fields.iter().map(|(_, x)| x).collect(),
+            EUpdate(x, fields) => Some(x)
+                .into_iter()
+                .chain(fields.iter().map(|(_, y)| y))
+                .collect(),
+            ECase(x, arms) => Some(x)
+                .into_iter()
+                .chain(arms.iter().map(|(_, _, y)| y))
+                .collect(),
+            EProj(x, _)
+            | EField(x, _)
+            | EInj(_, x)
+            | EAbs(_, x)
+            | EFix(_, x) => vec![x],
+            EWith(x, y) | EApp(x, y) | ELet(_, x, y) => vec![x, y],
+            EHole(_) | EVar(_) => vec![],
+        }
+    }
+
+    /// Rebuilds the term from its new children on top of `done`.
+    pub fn rebuild(&self, done: &mut Vec<Erased>) -> Erased {
+        let erased = match &**self {
+            ETuple(items) => ETuple(done.split_off(done.len() - items.len())),
+            EProj(_, index) => EProj(pop(done), *index),
+            EWith(_, _) => {
+                let r = pop(done);
+                EWith(pop(done), r)
+            }
+            ERecord(fields) => {
+                let values = done.split_off(done.len() - fields.len());
+                ERecord(relabel(fields, values))
+            }
+            EField(_, l) => EField(pop(done), l.clone()),
+            EUpdate(_, fields) => {
+                let values = done.split_off(done.len() - fields.len());
+                EUpdate(pop(done), relabel(fields, values))
+            }
+            EInj(l, _) => EInj(l.clone(), pop(done)),
+            ECase(_, arms) => {
+                let ys = done.split_off(done.len() - arms.len());
+                let arms = arms
+                    .iter()
+                    .zip(ys)
+                    .map(|((l, p, _), y)| (l.clone(), p.clone(), y))
+                    .collect();
+                ECase(pop(done), arms)
+            }
+            EHole(_) | EVar(_) => return self.clone(),
+            EAbs(v, _) => EAbs(*v, pop(done)),
+            EApp(_, _) => {
+                let x = pop(done);
+                EApp(pop(done), x)
+            }
+            ELet(p, _, _) => {
+                let y = pop(done);
+                ELet(p.clone(), pop(done), y)
+            }
+            EFix(f, _) => EFix(*f, pop(done)),
+        };
+        erased.into()
+    }
+
+    fn take_children(&mut self, stack: &mut Vec<Erased>) {
+        let data = match Rc::get_mut(&mut self.0) {
+            Some(data) => mem::replace(data, EHole(None)),
+            None => return,
+        };
+        match data {
+            ETuple(items) => stack.extend(items),
+            ERecord(fields) => stack.extend(fields.into_iter().map(|f| f.1)),
+            EUpdate(x, fields) => {
+                stack.push(x);
+                stack.extend(fields.into_iter().map(|f| f.1));
+            }
+            ECase(x, arms) => {
+                stack.push(x);
+                stack.extend(arms.into_iter().map(|arm| arm.2));
+            }
+            EProj(x, _)
+            | EField(x, _)
+            | EInj(_, x)
+            | EAbs(_, x)
+            | EFix(_, x) => stack.push(x),
+            EWith(x, y) | EApp(x, y) | ELet(_, x, y) => {
+                stack.extend(vec![x, y])
+            }
+            EHole(_) | EVar(_) => {}
+        }
+    }
+}
+
+impl From<ErasedData> for Erased {
+    fn from(data: ErasedData) -> Self {
+        Self(data.into())
+    }
+}
+
+impl Deref for Erased {
+    type Target = ErasedData;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl Drop for Erased {
+    fn drop(&mut self) {
+        let mut stack = vec![];
+        self.take_children(&mut stack);
+        while let Some(mut term) = stack.pop() {
+            term.take_children(&mut stack);
+        }
+    }
+}
+
+/// Substitutes `with` for the variable `what` in `inside`, as `subst::subst`
+/// does for terms.
+pub fn subst(with: Erased, inside: Erased, what: Var) -> Erased {
+    enum Task<'e> {
+        Visit(&'e Erased),
+        Keep(&'e Erased),
+        Build(&'e Erased),
+    }
+    let mut tasks = vec![Task::Visit(&inside)];
+    let mut done = vec![];
+    while let Some(task) = tasks.pop() {
+        match task {
+            Task::Visit(erased) => match &**erased {
+                EVar(var) if *var == what => done.push(with.clone()),
+                EAbs(n, _) | EFix(n, _) if *n == what => {
+                    done.push(erased.clone())
+                }
+                _ => {
+                    let shadowed = |index: usize| match &**erased {
+                        ECase(_, arms) => {
+                            index > 0 && binds(&arms[index - 1].1, what)
+                        }
+                        ELet(p, _, _) => index == 1 && binds(p, what),
+                        _ => false,
+                    };
+                    tasks.push(Task::Build(erased));
+                    let children = erased.children().into_iter().enumerate();
+                    tasks.extend(children.rev().map(|(index, child)| {
+                        match shadowed(index) {
+                            true => Task::Keep(child),
+                            false => Task::Visit(child),
+                        }
+                    }))
+                }
+            },
+            Task::Keep(erased) => done.push(erased.clone()),
+            Task::Build(erased) => {
+                let erased = erased.rebuild(&mut done);
+                done.push(erased)
+            }
+        }
+    }
+    pop(&mut done)
+}
+
+enum Piece<'a> {
+    Text(String),
+    Erased(&'a Erased),
+    Arg(&'a Erased),
+}
+
+use Piece::Text;
+
+/// Prints erased terms the way `Term` prints terms whose types are holes.
+impl Named for Erased {
+    fn pprint(&self, names: &Names) -> String {
+        let mut output = String::new();
+        let mut stack = vec![Piece::Erased(self)];
+        while let Some(piece) = stack.pop() {
+            match piece {
+                Text(text) => output.push_str(&text),
+                Piece::Erased(erased) => {
+                    stack.extend(erased.pieces(names).into_iter().rev())
+                }
+                Piece::Arg(erased) => match **erased {
+                    ETuple(_)
+                    | EProj(_, _)
+                    | EWith(_, _)
+                    | ERecord(_)
+                    | EField(_, _)
+                    | EUpdate(_, _)
+                    | EHole(_)
+                    | EVar(_) => stack.push(Piece::Erased(erased)),
+                    _ => stack.extend(vec![
+                        Text(")".into()),
+                        Piece::Erased(erased),
+                        Text("(".into()),
+                    ]),
+                },
+            }
+        }
+        output
+    }
+}
+
+impl Erased {
+    fn pieces(&self, names: &Names) -> Vec<Piece<'_>> {
+        match &**self {
+            ETuple(items) => {
+                let mut pieces = vec![Text("(".into())];
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        pieces.push(Text(", ".into()));
+                    }
+                    pieces.push(Piece::Erased(item));
+                }
+                let close = if items.len() == 1 { ",)" } else { ")" };
+                pieces.push(Text(close.into()));
+                pieces
+            }
+            EProj(x, i) => match **x {
+                ETuple(_) | EVar(_) | EProj(_, _) => {
+                    vec![Piece::Erased(x), Text(format!(".{}", i))]
+                }
+                _ => vec![
+                    Text("(".into()),
+                    Piece::Erased(x),
+                    Text(format!(").{}", i)),
+                ],
+            },
+            EWith(l, r) => vec![
+                Text("(".into()),
+                Piece::Erased(l),
+                Text(" & ".into()),
+                Piece::Erased(r),
+                Text(")".into()),
+            ],
+            ERecord(fields) => {
+                let mut pieces = vec![Text("{".into())];
+                pieces.extend(field_pieces(fields));
+                pieces.push(Text("}".into()));
+                pieces
+            }
+            EField(x, l) => vec![Piece::Arg(x), Text(format!(".{}", l))],
+            EUpdate(x, fields) => {
+                let mut pieces = vec![Text("{".into()), Piece::Erased(x)];
+                pieces.push(Text(" | ".into()));
+                pieces.extend(field_pieces(fields));
+                pieces.push(Text("}".into()));
+                pieces
+            }
+            EInj(l, x) => vec![Text(format!("inj {} ", l)), Piece::Arg(x)],
+            ECase(x, arms) => {
+                let mut pieces = vec![
+                    Text("case ".into()),
+                    Piece::Arg(x),
+                    Text(" (".into()),
+                ];
+                for (i, (l, p, y)) in arms.iter().enumerate() {
+                    let sep = if i > 0 { "; " } else { "" };
+                    let arm = format!("{}{} {} -> ", sep, l, p.pprint(names));
+                    pieces.extend(vec![Text(arm), Piece::Erased(y)]);
+                }
+                pieces.push(Text(")".into()));
+                pieces
+            }
+            EHole(Some(name)) => vec![Text(format!("?{}", name))],
+            EHole(None) => vec![Text("_".into())],
+            EVar(var) => vec![Text(names[*var].clone())],
+            EAbs(n, y) => {
+                vec![Text(format!("\\{}. ", names[*n])), Piece::Erased(y)]
+            }
+            EApp(f, x) => match **f {
+                EAbs(_, _) | EFix(_, _) | ELet(_, _, _) | EInj(_, _) => vec![
+                    Text("(".into()),
+                    Piece::Erased(f),
+                    Text(") ".into()),
+                    Piece::Erased(x),
+                ],
+                _ => vec![Piece::Erased(f), Text(" ".into()), Piece::Arg(x)],
+            },
+            ELet(p, x, y) => vec![
+                Text(format!("let {} = ", p.pprint(names))),
+                Piece::Erased(x),
+                Text("; ".into()),
+                Piece::Erased(y),
+            ],
+            EFix(n, y) => {
+                vec![Text(format!("fix {}. ", names[*n])), Piece::Erased(y)]
+            }
+        }
+    }
+}
+
+fn field_pieces(fields: &[(String, Erased)]) -> Vec<Piece<'_>> {
+    let mut pieces = vec![];
+    for (i, (l, x)) in fields.iter().enumerate() {
+        let sep = if i > 0 { ", " } else { "" };
+        pieces.extend(vec![Text(format!("{}{} = ", sep, l)), Piece::Erased(x)]);
+    }
+    pieces
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        eval::{eval, Budget, CallByValue},
+        step::step,
+        testing::programs,
+    };
+
+    #[test]
+    fn commutes_with_eval() {
+        for (term, _) in programs() {
+            // Stepping is the typed semantics, and it calls by value.
+            let mut typed = term.clone();
+            while let Some(step) = step(&typed) {
+                typed = step.after;
+            }
+            let erased = eval(erase(&term), CallByValue, Budget::default());
+            assert_eq!(erased.unwrap(), erase(&typed));
+        }
+    }
+
+    #[test]
+    fn deep_terms() {
+        let depth = 1_000_000;
+        let mut names = Names::default();
+        let a = names.push("a".into());
+        let mut term = de::unit();
+        for _ in 0..depth {
+            term = de::ty_app(de::ty_abs(a, de::tuple(vec![term])), ty::unit());
+        }
+        let mut erased = erase(&term);
+        for _ in 0..depth {
+            erased = match &*erased {
+                EApp(f, x) => match (&**f, &**x) {
+                    (EAbs(v, y), ETuple(units))
+                        if *v == a && units.is_empty() =>
+                    {
+                        match &**y {
+                            ETuple(items) if items.len() == 1 => {
+                                items[0].clone()
+                            }
+                            _ => panic!("expected a single-element tuple"),
+                        }
+                    }
+                    _ => panic!("expected a thunk applied to ()"),
+                },
+                _ => panic!("expected an application"),
+            };
+        }
+        assert_eq!(erased, ETuple(vec![]).into());
+    }
+}
@@ -1,47 +1,1421 @@
-use crate::{syntax::*, prelude::*, typeck};
-
-pub fn eval(term: Term) -> Term {
-    match (*term).clone() {
-        TmApp(f, x) => match ((*eval(f)).clone(), eval(x)) {
-            (TmAbs(v, _, y), x) => eval(subst(x, y, v)),
-            (f, x) => de::app(f, x),
-        },
-        TmTyApp(f, t) => match (*eval(f)).clone() {
-            TmTyAbs(v, y) => eval(subst_type(t, y, v)),
-            term => de::ty_app(term, t),
-        },
-        _ => term,
-    }
-}
-
-fn subst_type(with: Type, term: Term, var: Var) -> Term {
-    match (*term).clone() {
-        TmUnit => term,
-        TmVar(_) => term,
-        TmAbs(n, ty, y) => de::abs(n, typeck::subst_type(ty, with, var), y),
-        TmApp(f, x) => {
-            de::app(subst_type(with.clone(), f, var), subst_type(with, x, var))
-        }
-        TmTyAbs(n, body) => de::ty_abs(n, subst_type(with, body, var)),
-        TmTyApp(f, x) => de::ty_app(
-            subst_type(with.clone(), f, var),
-            typeck::subst_type(x, with, var),
-        ),
-        TmError => unreachable!()
-    }
-}
-
-fn subst(with: Term, inside: Term, what: Var) -> Term {
-    match (*inside).clone() {
-        TmUnit => inside,
-        TmVar(var) if var == what => with,
-        TmVar(_) => inside,
-        TmAbs(n, ty, y) => de::abs(n, ty, subst(with, y, what)),
-        TmApp(f, x) => {
-            de::app(subst(with.clone(), f, what), subst(with, x, what))
-        }
-        TmTyAbs(n, y) => de::ty_abs(n, subst(with, y, what)),
-        TmTyApp(f, t) => de::ty_app(subst(with, f, what), t),
-        TmError => unreachable!()
+use std::{
+    cell::OnceCell,
+    error::Error,
+    fmt::Display,
+    mem,
+    rc::Rc,
+    str::FromStr,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use crate::{
+    erase::*,
+    prelude::*,
+    syntax::Pattern::{self, *},
+};
+
+/// Evaluates an erased program to a value, read back as an erased term.
+pub fn eval(
+    term: Erased,
+    strategy: Strategy,
+    budget: Budget,
+) -> Result<Erased, Unfinished> {
+    let mut machine = Machine::new(strategy, budget);
+    match machine.run(term, Env::default()) {
+        Ok(value) => Ok(value.quote()),
+        Err(partial) => Err(machine.unfinished(partial)),
+    }
+}
+
+/// Normalises `term` by evaluation, reducing under binders as well.
+pub fn normalize(
+    term: Erased,
+    names: &mut Names,
+    budget: Budget,
+) -> Result<Erased, Unfinished> {
+    let mut normalizer = Normalizer::new(names, budget);
+    let value = normalizer.evaluate(term);
+    normalizer.normal_form(value)
+}
+
+/// Definitional equality: whether `left` and `right` have the same normal
+/// form, up to the names of bound variables.
+pub fn equal(
+    left: Erased,
+    right: Erased,
+    names: &mut Names,
+    budget: Budget,
+) -> Result<bool, Unfinished> {
+    let mut normalizer = Normalizer::new(names, budget);
+    let (left, right) = (normalizer.evaluate(left), normalizer.evaluate(right));
+    Ok(normalizer.normal_form(left)? == normalizer.normal_form(right)?)
+}
+
+/// How long evaluation may run before it is stopped.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Budget {
+    /// The number of steps of the machine, if limited.
+    pub steps: Option<usize>,
+    /// Stops evaluation once set, for example by a Ctrl-C handler.
+    pub interrupt: Option<&'static AtomicBool>,
+}
+
+impl Budget {
+    pub fn exhausted(&self, steps: usize) -> bool {
+        self.steps.is_some_and(|limit| steps >= limit)
+            || self
+                .interrupt
+                .is_some_and(|flag| flag.load(Ordering::Relaxed))
+    }
+}
+
+/// Evaluation stopped by its budget or at a hole. The partial result is the
+/// term that was left to evaluate.
+#[derive(Debug)]
+pub struct Unfinished {
+    pub steps: usize,
+    pub partial: Erased,
+    /// The hole evaluation reached, if it did not run out of budget.
+    pub hole: Option<Erased>,
+}
+
+impl Error for Unfinished {}
+
+impl Display for Unfinished {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.hole.as_deref() {
+            Some(EHole(Some(name))) => {
+                write!(f, "Evaluation reached the hole ?{}", name)
+            }
+            Some(_) => write!(f, "Evaluation reached a hole"),
+            None => write!(
+                f,
+                "Evaluation did not finish after {} steps",
+                self.steps
+            ),
+        }
+    }
+}
+
+/// How arguments of applications and `let` bindings are passed. Data
+/// constructors are strict under every strategy.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[allow(clippy::enum_variant_names)]
+pub enum Strategy {
+    #[default]
+    CallByValue,
+    CallByName,
+    CallByNeed,
+}
+
+pub use Strategy::*;
+
+impl FromStr for Strategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "value" => Ok(CallByValue),
+            "name" => Ok(CallByName),
+            "need" => Ok(CallByNeed),
+            _ => Err(format!(
+                "Unknown strategy '{}', expected value, name or need",
+                s
+            )),
+        }
+    }
+}
+
+impl Display for Strategy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CallByValue => write!(f, "call-by-value"),
+            CallByName => write!(f, "call-by-name"),
+            CallByNeed => write!(f, "call-by-need"),
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+struct Env(Option<Rc<(Var, Binding, Env)>>);
+
+#[derive(Clone)]
+enum Binding {
+    Value(Value),
+    Fix(Erased, Env),
+    Thunk(Erased, Env),
+    Shared(Rc<Shared>),
+}
+
+struct Shared {
+    term: Erased,
+    env: Env,
+    value: OnceCell<Value>,
+}
+
+type Value = Rc<ValueData>;
+
+#[allow(clippy::enum_variant_names)]
+enum ValueData {
+    VClosure(Var, Erased, Env),
+    VWith(Erased, Erased, Env),
+    VTuple(Vec<Value>),
+    VRecord(Vec<(String, Value)>),
+    VInj(String, Value),
+    VNeutral(Neutral, Vec<Frame>),
+}
+
+use ValueData::*;
+
+/// The head of a value stuck on a variable, followed by the eliminations
+/// waiting on it. Recursive definitions are only unfolded outside of binders,
+/// so under binders they are stuck as well.
+#[derive(Clone)]
+enum Neutral {
+    NVar(Var),
+    NFix(Var, Erased, Env),
+}
+
+use Neutral::*;
+
+impl Drop for Env {
+    fn drop(&mut self) {
+        let mut garbage = Garbage::default();
+        garbage.env(self);
+        garbage.collect();
+    }
+}
+
+impl Drop for ValueData {
+    fn drop(&mut self) {
+        let mut garbage = Garbage::default();
+        garbage.value(self);
+        garbage.collect();
+    }
+}
+
+/// Environments and values unlinked from the ones being dropped, so that long
+/// chains of them are freed in a loop rather than by recursive drops.
+#[derive(Default)]
+struct Garbage {
+    envs: Vec<Env>,
+    values: Vec<Value>,
+}
+
+impl Garbage {
+    fn env(&mut self, env: &mut Env) {
+        if let Some(Ok((_, binding, next))) = env.0.take().map(Rc::try_unwrap) {
+            self.envs.push(next);
+            match binding {
+                Binding::Value(value) => self.values.push(value),
+                Binding::Fix(_, env) | Binding::Thunk(_, env) => {
+                    self.envs.push(env)
+                }
+                Binding::Shared(shared) => {
+                    if let Ok(shared) = Rc::try_unwrap(shared) {
+                        self.envs.push(shared.env);
+                        self.values.extend(shared.value.into_inner());
+                    }
+                }
+            }
+        }
+    }
+
+    fn value(&mut self, value: &mut ValueData) {
+        match value {
+            VClosure(_, _, env) | VWith(_, _, env) => {
+                self.envs.push(mem::take(env))
+            }
+            VTuple(items) => self.values.append(items),
+            VRecord(fields) => {
+                self.values.extend(fields.drain(..).map(|(_, value)| value))
+            }
+            VInj(_, x) => {
+                let unit = Rc::new(VTuple(vec![]));
+                self.values.push(mem::replace(x, unit))
+            }
+            VNeutral(head, spine) => {
+                if let NFix(_, _, env) = head {
+                    self.envs.push(mem::take(env));
+                }
+                for frame in spine.drain(..) {
+                    match frame {
+                        FApp(value) => self.values.push(value),
+                        FBind(_, pending, _, env) => {
+                            self.values
+                                .extend(pending.into_iter().map(|p| p.1));
+                            self.envs.push(env)
+                        }
+                        FUpdate(_, env) | FCase(_, env) => self.envs.push(env),
+                        _ => {}
+                    }
+                }
+            }
+        }
+    }
+
+    fn collect(mut self) {
+        loop {
+            if let Some(mut env) = self.envs.pop() {
+                self.env(&mut env);
+            } else if let Some(value) = self.values.pop() {
+                if let Ok(mut value) = Rc::try_unwrap(value) {
+                    self.value(&mut value);
+                }
+            } else {
+                break;
+            }
+        }
+    }
+}
+
+enum Control {
+    Eval(Erased, Env),
+    Return(Value),
+}
+
+use Control::*;
+
+#[derive(Clone)]
+#[allow(clippy::enum_variant_names)]
+enum Frame {
+    FArg(Erased, Env),
+    FCall(Value),
+    FApp(Value),
+    FTuple(Vec<Value>, Vec<Erased>, Env),
+    FProj(usize),
+    FRecord(Vec<(String, Value)>, Vec<(String, Erased)>, Env),
+    FField(String),
+    FUpdate(Vec<(String, Erased)>, Env),
+    FUpdated(Value),
+    FInj(String),
+    FCase(Vec<(String, Pattern, Erased)>, Env),
+    FLet(Pattern, Erased, Env),
+    FBind(Pattern, Vec<(Pattern, Value)>, Erased, Env),
+    FShare(Rc<Shared>),
+}
+
+use Frame::*;
+
+struct Machine {
+    strategy: Strategy,
+    budget: Budget,
+    stack: Vec<Frame>,
+    steps: usize,
+    /// The hole that stopped evaluation, if any.
+    hole: Option<Erased>,
+    /// Set while evaluating under binders, where recursive definitions are
+    /// not unfolded.
+    open: bool,
+}
+
+impl Machine {
+    fn new(strategy: Strategy, budget: Budget) -> Self {
+        Self {
+            strategy,
+            budget,
+            stack: vec![],
+            steps: 0,
+            hole: None,
+            open: false,
+        }
+    }
+
+    fn run(&mut self, term: Erased, env: Env) -> Result<Value, Erased> {
+        self.resume(Eval(term, env))
+    }
+
+    /// Runs until the stack is empty, or until the budget is exhausted or a
+    /// hole is reached, in which case the term left to evaluate is returned
+    /// instead.
+    fn resume(&mut self, mut control: Control) -> Result<Value, Erased> {
+        loop {
+            if self.hole.is_some() || self.budget.exhausted(self.steps) {
+                return Err(self.residual(control));
+            }
+            self.steps += 1;
+            control = match control {
+                Eval(term, env) => self.eval_term(term, env),
+                Return(value) => match self.stack.pop() {
+                    Some(frame) => self.apply(frame, value),
+                    None => return Ok(value),
+                },
+            }
+        }
+    }
+
+    /// Reads the control back and plugs it into the frames on the stack,
+    /// leaving the stack empty.
+    fn residual(&mut self, control: Control) -> Erased {
+        let mut term = match control {
+            Eval(term, env) => quote_in(&term, &env),
+            Return(value) => value.quote(),
+        };
+        while let Some(frame) = self.stack.pop() {
+            term = frame.plug(term);
+        }
+        term
+    }
+
+    fn unfinished(&self, partial: Erased) -> Unfinished {
+        Unfinished {
+            steps: self.steps,
+            partial,
+            hole: self.hole.clone(),
+        }
+    }
+
+    fn eval_term(&mut self, term: Erased, env: Env) -> Control {
+        match &*term {
+            ETuple(items) if items.is_empty() => {
+                Return(Rc::new(VTuple(vec![])))
+            }
+            ETuple(items) => {
+                let first = items[0].clone();
+                self.stack.push(FTuple(vec![], items.clone(), env.clone()));
+                Eval(first, env)
+            }
+            EProj(tuple, index) => {
+                self.stack.push(FProj(*index));
+                Eval(tuple.clone(), env)
+            }
+            EWith(l, r) => Return(Rc::new(VWith(l.clone(), r.clone(), env))),
+            ERecord(fields) if fields.is_empty() => {
+                Return(Rc::new(VRecord(vec![])))
+            }
+            ERecord(fields) => {
+                let first = fields[0].1.clone();
+                self.stack
+                    .push(FRecord(vec![], fields.clone(), env.clone()));
+                Eval(first, env)
+            }
+            EField(x, l) => {
+                self.stack.push(FField(l.clone()));
+                Eval(x.clone(), env)
+            }
+            EUpdate(x, fields) => {
+                self.stack.push(FUpdate(fields.clone(), env.clone()));
+                Eval(x.clone(), env)
+            }
+            EInj(l, x) => {
+                self.stack.push(FInj(l.clone()));
+                Eval(x.clone(), env)
+            }
+            ECase(x, arms) => {
+                self.stack.push(FCase(arms.clone(), env.clone()));
+                Eval(x.clone(), env)
+            }
+            EVar(var) => match env.lookup(*var) {
+                Some(Binding::Value(value)) => Return(value.clone()),
+                Some(Binding::Fix(x, outer)) if self.open => {
+                    let fix = NFix(*var, x.clone(), outer.clone());
+                    Return(Rc::new(VNeutral(fix, vec![])))
+                }
+                Some(Binding::Fix(x, outer)) => {
+                    let fix = Binding::Fix(x.clone(), outer.clone());
+                    Eval(x.clone(), outer.bind(*var, fix))
+                }
+                Some(Binding::Thunk(x, outer)) => {
+                    Eval(x.clone(), outer.clone())
+                }
+                Some(Binding::Shared(shared)) => match shared.value.get() {
+                    Some(value) => Return(value.clone()),
+                    None => {
+                        self.stack.push(FShare(shared.clone()));
+                        Eval(shared.term.clone(), shared.env.clone())
+                    }
+                },
+                None => Return(Rc::new(VNeutral(NVar(*var), vec![]))),
+            },
+            EAbs(v, y) => Return(Rc::new(VClosure(*v, y.clone(), env))),
+            EApp(f, x) => {
+                self.stack.push(FArg(x.clone(), env.clone()));
+                Eval(f.clone(), env)
+            }
+            ELet(p, x, y) => match (self.strategy, p) {
+                (CallByName, PtWild) | (CallByNeed, PtWild) => {
+                    Eval(y.clone(), env)
+                }
+                (CallByName, PtVar(v)) | (CallByNeed, PtVar(v)) => {
+                    let value = self.delay(x.clone(), env.clone());
+                    Eval(y.clone(), env.bind(*v, value))
+                }
+                _ => {
+                    self.stack.push(FLet(p.clone(), y.clone(), env.clone()));
+                    Eval(x.clone(), env)
+                }
+            },
+            EFix(f, x) if self.open => {
+                let fix = NFix(*f, x.clone(), env);
+                Return(Rc::new(VNeutral(fix, vec![])))
+            }
+            EFix(f, x) => {
+                let fix = Binding::Fix(x.clone(), env.clone());
+                Eval(x.clone(), env.bind(*f, fix))
+            }
+            EHole(_) => {
+                self.hole = Some(term.clone());
+                Eval(term, env)
+            }
+        }
+    }
+
+    fn apply(&mut self, frame: Frame, value: Value) -> Control {
+        match frame {
+            FArg(x, env) => match (self.strategy, &*value) {
+                (CallByValue, _) => {
+                    self.stack.push(FCall(value));
+                    Eval(x, env)
+                }
+                (_, VClosure(v, y, inner)) => {
+                    let arg = self.delay(x, env);
+                    Eval(y.clone(), inner.bind(*v, arg))
+                }
+                _ => unreachable!(),
+            },
+            FCall(f) => call(f, value),
+            FApp(x) => call(value, x),
+            FTuple(mut done, items, env) => {
+                done.push(value);
+                match items.get(done.len()) {
+                    Some(next) => {
+                        let next = next.clone();
+                        self.stack.push(FTuple(done, items, env.clone()));
+                        Eval(next, env)
+                    }
+                    None => Return(Rc::new(VTuple(done))),
+                }
+            }
+            FProj(index) => match &*value {
+                VTuple(items) => Return(items[index].clone()),
+                _ => stuck(&value, FProj(index)),
+            },
+            FRecord(mut done, fields, env) => {
+                done.push((fields[done.len()].0.clone(), value));
+                match fields.get(done.len()) {
+                    Some((_, next)) => {
+                        let next = next.clone();
+                        self.stack.push(FRecord(done, fields, env.clone()));
+                        Eval(next, env)
+                    }
+                    None => Return(Rc::new(VRecord(done))),
+                }
+            }
+            FField(label) => match &*value {
+                VRecord(fields) => Return(field(fields, &label)),
+                _ => stuck(&value, FField(label)),
+            },
+            FUpdate(updates, env) => match &*value {
+                VNeutral(_, _) => stuck(&value, FUpdate(updates, env)),
+                _ => {
+                    self.stack.push(FUpdated(value));
+                    Eval(ERecord(updates).into(), env)
+                }
+            },
+            FUpdated(record) => match (&*record, &*value) {
+                (VRecord(fields), VRecord(updates)) => {
+                    let mut fields = fields.clone();
+                    for (label, value) in updates {
+                        if let Some(field) =
+                            fields.iter_mut().find(|(l, _)| l == label)
+                        {
+                            field.1 = value.clone();
+                        }
+                    }
+                    Return(Rc::new(VRecord(fields)))
+                }
+                _ => unreachable!(),
+            },
+            FInj(l) => Return(Rc::new(VInj(l, value))),
+            FCase(arms, env) => match &*value {
+                VInj(label, x) => {
+                    let (_, p, y) =
+                        arms.into_iter().find(|(l, _, _)| l == label).unwrap();
+                    self.bind(vec![(p, x.clone())], env, y)
+                }
+                _ => stuck(&value, FCase(arms, env)),
+            },
+            FLet(p, y, env) => self.bind(vec![(p, value)], env, y),
+            FBind(p, mut pending, y, env) => {
+                pending.push((p, value));
+                self.bind(pending, env, y)
+            }
+            FShare(shared) => {
+                let _ = shared.value.set(value.clone());
+                Return(value)
+            }
+        }
+    }
+
+    fn delay(&self, term: Erased, env: Env) -> Binding {
+        match self.strategy {
+            CallByValue => unreachable!(),
+            CallByName => Binding::Thunk(term, env),
+            CallByNeed => Binding::Shared(Rc::new(Shared {
+                term,
+                env,
+                value: OnceCell::new(),
+            })),
+        }
+    }
+
+    fn bind(
+        &mut self,
+        mut pending: Vec<(Pattern, Value)>,
+        mut env: Env,
+        body: Erased,
+    ) -> Control {
+        while let Some((pattern, value)) = pending.pop() {
+            match (pattern, &*value) {
+                (PtVar(var), _) => env = env.bind(var, Binding::Value(value)),
+                (PtTuple(items), VTuple(values)) => {
+                    let items = items.into_iter().zip(values.iter().cloned());
+                    pending.extend(items.rev());
+                }
+                (PtLeft(p), VWith(l, _, inner)) => {
+                    let x = l.clone();
+                    let inner = inner.clone();
+                    self.stack.push(FBind(*p, pending, body, env));
+                    return Eval(x, inner);
+                }
+                (PtRight(p), VWith(_, r, inner)) => {
+                    let x = r.clone();
+                    let inner = inner.clone();
+                    self.stack.push(FBind(*p, pending, body, env));
+                    return Eval(x, inner);
+                }
+                (PtWild, _) => {}
+                (pattern, VNeutral(_, _)) => {
+                    return stuck(&value, FBind(pattern, pending, body, env))
+                }
+                _ => unreachable!(),
+            }
+        }
+        Eval(body, env)
+    }
+}
+
+fn call(f: Value, x: Value) -> Control {
+    match &*f {
+        VClosure(v, y, env) => Eval(y.clone(), env.bind(*v, Binding::Value(x))),
+        _ => stuck(&f, FApp(x)),
+    }
+}
+
+fn stuck(value: &Value, frame: Frame) -> Control {
+    match &**value {
+        VNeutral(head, spine) => {
+            let mut spine = spine.clone();
+            spine.push(frame);
+            Return(Rc::new(VNeutral(head.clone(), spine)))
+        }
+        _ => unreachable!(),
+    }
+}
+
+fn field(fields: &[(String, Value)], label: &str) -> Value {
+    let (_, value) = fields.iter().find(|(l, _)| l == label).unwrap();
+    value.clone()
+}
+
+impl Frame {
+    /// The term that this frame continues with once `hole` is evaluated.
+    fn plug(self, hole: Erased) -> Erased {
+        let quote_fields = |fields: &[(String, Erased)], env: &Env| {
+            fields
+                .iter()
+                .map(|(label, x)| (label.clone(), quote_in(x, env)))
+                .collect::<Vec<_>>()
+        };
+        let plugged = match self {
+            FArg(x, env) => EApp(hole, quote_in(&x, &env)),
+            FCall(f) => EApp(f.quote(), hole),
+            FApp(x) => EApp(hole, x.quote()),
+            FTuple(done, items, env) => {
+                let rest = items[done.len() + 1..].iter();
+                let done = done.iter().map(Quote::quote);
+                let rest = rest.map(|x| quote_in(x, &env));
+                ETuple(done.chain(Some(hole)).chain(rest).collect())
+            }
+            FProj(index) => EProj(hole, index),
+            FRecord(done, fields, env) => {
+                let (label, _) = &fields[done.len()];
+                let rest = quote_fields(&fields[done.len() + 1..], &env);
+                let done = done.iter().map(|(l, x)| (l.clone(), x.quote()));
+                let hole = (label.clone(), hole);
+                ERecord(done.chain(Some(hole)).chain(rest).collect())
+            }
+            FField(label) => EField(hole, label),
+            FUpdate(fields, env) => EUpdate(hole, quote_fields(&fields, &env)),
+            FUpdated(record) => match &*hole {
+                ERecord(updates) => EUpdate(record.quote(), updates.clone()),
+                _ => unreachable!(),
+            },
+            FInj(l) => EInj(l, hole),
+            FCase(arms, env) => {
+                let arms = arms
+                    .into_iter()
+                    .map(|(l, p, y)| (l, p, quote_in(&y, &env)))
+                    .collect();
+                ECase(hole, arms)
+            }
+            FLet(p, y, env) => ELet(p, hole, quote_in(&y, &env)),
+            FBind(p, pending, y, env) => {
+                let body = pending
+                    .into_iter()
+                    .fold(quote_in(&y, &env), |y, (p, x)| {
+                        ELet(p, x.quote(), y).into()
+                    });
+                ELet(p, hole, body)
+            }
+            FShare(_) => return hole,
+        };
+        plugged.into()
+    }
+}
+
+impl Env {
+    fn bind(&self, var: Var, binding: Binding) -> Self {
+        Self(Some(Rc::new((var, binding, self.clone()))))
+    }
+
+    fn lookup(&self, var: Var) -> Option<&Binding> {
+        let mut env = self;
+        while let Some((key, binding, next)) = env.0.as_deref() {
+            if *key == var {
+                return Some(binding);
+            }
+            env = next;
+        }
+        None
+    }
+}
+
+/// Reads values back into terms in normal form, evaluating under binders with
+/// their variables bound to neutral values.
+struct Normalizer<'n> {
+    names: &'n mut Names,
+    machine: Machine,
+    binders: Vec<Var>,
+    fresh: usize,
+}
+
+enum Task {
+    Value(Value),
+    Eval(Erased, Env),
+    Bind(Vec<(Pattern, Value)>, Erased, Env),
+    Build(usize, Box<dyn FnOnce(Vec<Erased>) -> Erased>),
+}
+
+impl<'n> Normalizer<'n> {
+    fn new(names: &'n mut Names, budget: Budget) -> Self {
+        Self {
+            names,
+            machine: Machine::new(CallByValue, budget),
+            binders: vec![],
+            fresh: 0,
+        }
+    }
+
+    fn evaluate(&mut self, term: Erased) -> Result<Value, Erased> {
+        self.machine.open = false;
+        self.machine.run(term, Env::default())
+    }
+
+    fn normal_form(
+        &mut self,
+        value: Result<Value, Erased>,
+    ) -> Result<Erased, Unfinished> {
+        value
+            .and_then(|value| self.readback(value))
+            .map_err(|partial| self.machine.unfinished(partial))
+    }
+
+    /// Binders are renamed in the order they are read back, reusing the same
+    /// variables for every value, so that values with the same normal form
+    /// are read back to equal terms. Once the budget is exhausted, the rest
+    /// is read back without evaluating it and returned as an error.
+    fn readback(&mut self, value: Value) -> Result<Erased, Erased> {
+        self.machine.open = true;
+        self.fresh = 0;
+        let mut tasks = vec![Task::Value(value)];
+        let mut done = vec![];
+        let mut finished = true;
+        while let Some(task) = tasks.pop() {
+            let result = match task {
+                Task::Value(value) => {
+                    self.value(&value, &mut tasks);
+                    continue;
+                }
+                Task::Eval(term, env) => self.machine.run(term, env),
+                Task::Bind(pending, body, env) => {
+                    let control = self.machine.bind(pending, env, body);
+                    self.machine.resume(control)
+                }
+                Task::Build(arity, build) => {
+                    let children = done.split_off(done.len() - arity);
+                    done.push(build(children));
+                    continue;
+                }
+            };
+            match result {
+                Ok(value) => tasks.push(Task::Value(value)),
+                Err(partial) => {
+                    finished = false;
+                    done.push(partial)
+                }
+            }
+        }
+        let term = pop(&mut done);
+        if finished {
+            Ok(term)
+        } else {
+            Err(term)
+        }
+    }
+
+    fn value(&mut self, value: &Value, tasks: &mut Vec<Task>) {
+        match &**value {
+            VClosure(v, y, env) => {
+                let var = self.fresh(*v);
+                tasks.push(unary(move |y| EAbs(var, y)));
+                tasks.push(Task::Eval(y.clone(), env.bind(*v, variable(var))))
+            }
+            VWith(l, r, env) => tasks.extend(vec![
+                binary(EWith),
+                Task::Eval(r.clone(), env.clone()),
+                Task::Eval(l.clone(), env.clone()),
+            ]),
+            VTuple(items) => {
+                tasks.push(Task::Build(
+                    items.len(),
+                    Box::new(|xs| ETuple(xs).into()),
+                ));
+                tasks.extend(items.iter().rev().cloned().map(Task::Value))
+            }
+            VRecord(fields) => {
+                let labels = fields.iter().map(|(l, _)| l.clone()).collect();
+                tasks.push(Task::Build(
+                    fields.len(),
+                    Box::new(|xs| ERecord(zip_fields(labels, xs)).into()),
+                ));
+                tasks.extend(
+                    fields.iter().rev().map(|(_, x)| Task::Value(x.clone())),
+                )
+            }
+            VInj(l, x) => {
+                let l = l.clone();
+                tasks.push(unary(move |x| EInj(l, x)));
+                tasks.push(Task::Value(x.clone()))
+            }
+            VNeutral(head, spine) => {
+                for frame in spine.iter().rev() {
+                    self.frame(frame, tasks);
+                }
+                match head {
+                    NVar(var) => {
+                        let var = *var;
+                        tasks.push(Task::Build(
+                            0,
+                            Box::new(move |_| EVar(var).into()),
+                        ))
+                    }
+                    NFix(f, x, env) => {
+                        let var = self.fresh(*f);
+                        tasks.push(unary(move |x| EFix(var, x)));
+                        tasks.push(Task::Eval(
+                            x.clone(),
+                            env.bind(*f, variable(var)),
+                        ))
+                    }
+                }
+            }
+        }
+    }
+
+    /// Pushes the tasks reading back an elimination of the neutral term that
+    /// is read back before them.
+    fn frame(&mut self, frame: &Frame, tasks: &mut Vec<Task>) {
+        match frame.clone() {
+            FApp(x) => {
+                tasks.push(binary(EApp));
+                tasks.push(Task::Value(x))
+            }
+            FProj(index) => tasks.push(unary(move |x| EProj(x, index))),
+            FField(l) => tasks.push(unary(move |x| EField(x, l))),
+            FUpdate(fields, env) => {
+                let (labels, ys): (Vec<_>, Vec<_>) = fields.into_iter().unzip();
+                tasks.push(Task::Build(
+                    1 + ys.len(),
+                    Box::new(|mut xs| {
+                        let ys = xs.split_off(1);
+                        EUpdate(pop(&mut xs), zip_fields(labels, ys)).into()
+                    }),
+                ));
+                tasks.extend(
+                    ys.into_iter().rev().map(|y| Task::Eval(y, env.clone())),
+                )
+            }
+            FCase(arms, env) => {
+                let mut bodies = vec![];
+                let mut heads = vec![];
+                for (l, p, y) in arms {
+                    let mut env = env.clone();
+                    heads.push((l, self.fresh_pattern(&p, &mut env)));
+                    bodies.push(Task::Eval(y, env));
+                }
+                tasks.push(Task::Build(
+                    1 + heads.len(),
+                    Box::new(|mut xs| {
+                        let ys = xs.split_off(1);
+                        let arms = heads
+                            .into_iter()
+                            .zip(ys)
+                            .map(|((l, p), y)| (l, p, y))
+                            .collect();
+                        ECase(pop(&mut xs), arms).into()
+                    }),
+                ));
+                tasks.extend(bodies.into_iter().rev())
+            }
+            FBind(p, pending, y, env) => {
+                let mut env = env;
+                let p = self.fresh_pattern(&p, &mut env);
+                tasks.push(binary(move |x, y| ELet(p, x, y)));
+                tasks.push(Task::Bind(pending, y, env))
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn fresh(&mut self, var: Var) -> Var {
+        if self.fresh == self.binders.len() {
+            let name = self.names[var].clone();
+            self.binders.push(self.names.push(name));
+        }
+        self.fresh += 1;
+        self.binders[self.fresh - 1]
+    }
+
+    fn fresh_pattern(&mut self, pattern: &Pattern, env: &mut Env) -> Pattern {
+        match pattern {
+            PtVar(v) => {
+                let var = self.fresh(*v);
+                *env = env.bind(*v, variable(var));
+                PtVar(var)
+            }
+            PtTuple(items) => PtTuple(
+                items.iter().map(|p| self.fresh_pattern(p, env)).collect(),
+            ),
+            PtLeft(p) => PtLeft(Box::new(self.fresh_pattern(p, env))),
+            PtRight(p) => PtRight(Box::new(self.fresh_pattern(p, env))),
+            PtWild => PtWild,
+        }
+    }
+}
+
+fn variable(var: Var) -> Binding {
+    Binding::Value(Rc::new(VNeutral(NVar(var), vec![])))
+}
+
+fn unary(build: impl FnOnce(Erased) -> ErasedData + 'static) -> Task {
+    Task::Build(1, Box::new(|mut xs| build(pop(&mut xs)).into()))
+}
+
+fn binary(build: impl FnOnce(Erased, Erased) -> ErasedData + 'static) -> Task {
+    Task::Build(
+        2,
+        Box::new(|mut xs| {
+            let y = pop(&mut xs);
+            build(pop(&mut xs), y).into()
+        }),
+    )
+}
+
+fn zip_fields(labels: Vec<String>, xs: Vec<Erased>) -> Vec<(String, Erased)> {
+    labels.into_iter().zip(xs).collect()
+}
+
+enum Readback<'v> {
+    Value(&'v Value),
+    Term(&'v Erased, &'v Env),
+    Fix(Var),
+    BuildValue(&'v Value),
+    BuildTerm(&'v Erased),
+}
+
+trait Quote {
+    fn quote(&self) -> Erased;
+}
+
+impl Quote for Value {
+    fn quote(&self) -> Erased {
+        readback(Readback::Value(self))
+    }
+}
+
+impl Quote for Neutral {
+    fn quote(&self) -> Erased {
+        match self {
+            NVar(var) => EVar(*var).into(),
+            NFix(f, x, env) => EFix(*f, quote_in(x, env)).into(),
+        }
+    }
+}
+
+fn quote_in(term: &Erased, env: &Env) -> Erased {
+    readback(Readback::Term(term, env))
+}
+
+fn readback(first: Readback) -> Erased {
+    let mut tasks = vec![first];
+    let mut done = vec![];
+    while let Some(task) = tasks.pop() {
+        match task {
+            Readback::Value(value) => {
+                tasks.push(Readback::BuildValue(value));
+                match &**value {
+                    VClosure(_, y, env) => tasks.push(Readback::Term(y, env)),
+                    VWith(l, r, env) => tasks.extend(vec![
+                        Readback::Term(r, env),
+                        Readback::Term(l, env),
+                    ]),
+                    VTuple(items) => {
+                        tasks.extend(items.iter().rev().map(Readback::Value))
+                    }
+                    VRecord(fields) => tasks.extend(
+                        fields.iter().rev().map(|(_, x)| Readback::Value(x)),
+                    ),
+                    VInj(_, x) => tasks.push(Readback::Value(x)),
+                    VNeutral(_, _) => {}
+                }
+            }
+            Readback::Term(term, env) => match &**term {
+                EVar(var) => match env.lookup(*var) {
+                    Some(Binding::Value(value)) => {
+                        tasks.push(Readback::Value(value))
+                    }
+                    Some(Binding::Fix(x, env)) => tasks.extend(vec![
+                        Readback::Fix(*var),
+                        Readback::Term(x, env),
+                    ]),
+                    Some(Binding::Thunk(x, env)) => {
+                        tasks.push(Readback::Term(x, env))
+                    }
+                    Some(Binding::Shared(shared)) => {
+                        tasks.push(match shared.value.get() {
+                            Some(value) => Readback::Value(value),
+                            None => Readback::Term(&shared.term, &shared.env),
+                        })
+                    }
+                    None => done.push(term.clone()),
+                },
+                _ => {
+                    tasks.push(Readback::BuildTerm(term));
+                    let children = term.children();
+                    tasks.extend(
+                        children
+                            .into_iter()
+                            .rev()
+                            .map(|x| Readback::Term(x, env)),
+                    );
+                }
+            },
+            Readback::Fix(var) => {
+                let x = pop(&mut done);
+                done.push(EFix(var, x).into());
+            }
+            Readback::BuildValue(value) => {
+                let term = build_value(value, &mut done);
+                done.push(term);
+            }
+            Readback::BuildTerm(term) => {
+                let term = term.rebuild(&mut done);
+                done.push(term);
+            }
+        }
+    }
+    done.pop().unwrap()
+}
+
+fn pop(done: &mut Vec<Erased>) -> Erased {
+    done.pop().unwrap()
+}
+
+fn build_value(value: &Value, done: &mut Vec<Erased>) -> Erased {
+    let built = match &**value {
+        VClosure(v, _, _) => EAbs(*v, pop(done)),
+        VWith(_, _, _) => {
+            let r = pop(done);
+            EWith(pop(done), r)
+        }
+        VTuple(items) => ETuple(done.split_off(done.len() - items.len())),
+        VRecord(fields) => {
+            let values = done.split_off(done.len() - fields.len());
+            let labels = fields.iter().map(|(label, _)| label.clone());
+            ERecord(labels.zip(values).collect())
+        }
+        VInj(l, _) => EInj(l.clone(), pop(done)),
+        VNeutral(head, spine) => {
+            return spine
+                .iter()
+                .fold(head.quote(), |x, frame| frame.clone().plug(x))
+        }
+    };
+    built.into()
+}
+
+#[cfg(test)]
+mod substitution {
+    use crate::{
+        subst::{subst, subst_type},
+        syntax::*,
+    };
+
+    pub fn eval(term: Term) -> Term {
+        match (*term).clone() {
+            TmApp(f, x) => match ((*eval(f)).clone(), eval(x)) {
+                (TmAbs(v, _, y), x) => eval(subst(x, y, v)),
+                (f, x) => de::app(f, x),
+            },
+            TmTyApp(f, t) => match (*eval(f)).clone() {
+                TmTyAbs(v, y) => eval(subst_type(t, y, v)),
+                term => de::ty_app(term, t),
+            },
+            TmTuple(items) => de::tuple(items.into_iter().map(eval).collect()),
+            TmProj(tuple, index) => match (*eval(tuple)).clone() {
+                TmTuple(items) => items[index].clone(),
+                tuple => de::proj(tuple, index),
+            },
+            TmRecord(fields) => de::record(map_fields(fields, eval)),
+            TmField(x, l) => match (*eval(x)).clone() {
+                TmRecord(fields) => field(&fields, &l),
+                x => de::field(x, l),
+            },
+            TmUpdate(x, updates) => match (*eval(x)).clone() {
+                TmRecord(mut fields) => {
+                    for (label, value) in map_fields(updates, eval) {
+                        if let Some(field) =
+                            fields.iter_mut().find(|(l, _)| *l == label)
+                        {
+                            field.1 = value;
+                        }
+                    }
+                    de::record(fields)
+                }
+                x => de::update(x, updates),
+            },
+            TmInj(t, l, x) => de::inj(t, l, eval(x)),
+            TmCase(x, arms) => match (*eval(x)).clone() {
+                TmInj(_, label, x) => {
+                    let (_, p, y) =
+                        arms.into_iter().find(|(l, _, _)| *l == label).unwrap();
+                    eval(bind(&p, x, y))
+                }
+                x => de::case(x, arms),
+            },
+            TmLet(p, _, x, y) => eval(bind(&p, eval(x), y)),
+            TmFix(f, _, x) => eval(subst(term, x, f)),
+            TmFold(t, x) => de::fold(t, eval(x)),
+            TmUnfold(x) => match (*eval(x)).clone() {
+                TmFold(_, x) => x,
+                x => de::unfold(x),
+            },
+            TmPack(t, x, u) => de::pack(t, eval(x), u),
+            TmUnpack(a, p, x, y) => match (*eval(x)).clone() {
+                TmPack(t, x, _) => eval(bind(&p, x, subst_type(t, y, a))),
+                _ => unreachable!(),
+            },
+            TmHole(_) => unreachable!(),
+            _ => term,
+        }
+    }
+
+    fn field(fields: &[(String, Term)], label: &str) -> Term {
+        let (_, value) = fields.iter().find(|(l, _)| l == label).unwrap();
+        value.clone()
+    }
+
+    fn bind(pattern: &Pattern, value: Term, body: Term) -> Term {
+        match pattern {
+            PtVar(var) => subst(value, body, *var),
+            PtTuple(items) => match &*value {
+                TmTuple(values) => items
+                    .iter()
+                    .zip(values)
+                    .rev()
+                    .fold(body, |body, (item, value)| {
+                        bind(item, value.clone(), body)
+                    }),
+                _ => unreachable!(),
+            },
+            PtLeft(p) => match &*value {
+                TmWith(l, _) => bind(p, eval(l.clone()), body),
+                _ => unreachable!(),
+            },
+            PtRight(p) => match &*value {
+                TmWith(_, r) => bind(p, eval(r.clone()), body),
+                _ => unreachable!(),
+            },
+            PtWild => body,
+        }
+    }
+
+    fn map_fields(
+        fields: Vec<(String, Term)>,
+        f: impl Fn(Term) -> Term,
+    ) -> Vec<(String, Term)> {
+        fields
+            .into_iter()
+            .map(|(label, value)| (label, f(value)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+    use crate::{
+        syntax::{de, ty, Term},
+        testing::{checked, church, examples, named, programs, CHURCH},
+    };
+
+    #[test]
+    fn agrees_with_substitution() {
+        for (term, _) in programs() {
+            assert_eq!(
+                eval(erase(&term), CallByValue, Budget::default()).unwrap(),
+                erase(&substitution::eval(term))
+            );
+        }
+    }
+
+    #[test]
+    #[ignore]
+    fn bench_church_numerals() {
+        for n in [10, 40, 160] {
+            let term = church(n, "mul n n [()] (x: () -> x) ()");
+            let start = Instant::now();
+            let machine =
+                eval(erase(&term), CallByValue, Budget::default()).unwrap();
+            let machine_time = start.elapsed();
+            let start = Instant::now();
+            let substitution = erase(&substitution::eval(term));
+            let substitution_time = start.elapsed();
+            assert_eq!(machine, substitution);
+            println!(
+                "church {:>3}^2: machine {:>10.2?}, substitution {:>10.2?}",
+                n, machine_time, substitution_time
+            );
+        }
+    }
+
+    #[test]
+    fn deep_terms() {
+        let depth = 1_000_000;
+        let mut names = Names::default();
+        let (f, x) = (names.push("f".into()), names.push("x".into()));
+        let mut body = de::unit();
+        for _ in 0..depth {
+            body = de::app(de::var(f), body);
+        }
+        let apply = de::app(
+            de::abs(f, ty::arr(ty::unit(), ty::unit()), body),
+            de::abs(x, ty::unit(), de::var(x)),
+        );
+        assert_eq!(
+            eval(erase(&apply), CallByValue, Budget::default()).unwrap(),
+            erase(&de::unit())
+        );
+
+        let vars = (0..=depth)
+            .map(|i| names.push(format!("x{}", i)))
+            .collect::<Vec<_>>();
+        let mut nested = de::var(vars[depth]);
+        for i in (1..=depth).rev() {
+            let value = de::tuple(vec![de::var(vars[i - 1])]);
+            nested = de::r#let(PtVar(vars[i]), ty::hole(), value, nested);
+        }
+        let nested = de::r#let(PtVar(vars[0]), ty::hole(), de::unit(), nested);
+        let nested = erase(&nested);
+        let mut value = eval(nested, CallByValue, Budget::default()).unwrap();
+        for _ in 0..depth {
+            value = match &*value {
+                ETuple(items) if items.len() == 1 => items[0].clone(),
+                _ => panic!("expected a single-element tuple"),
+            };
+        }
+        assert_eq!(value, erase(&de::unit()));
+    }
+
+    fn steps(term: &Term, strategy: Strategy) -> (Erased, usize) {
+        let mut machine = Machine::new(strategy, Budget::default());
+        let value = machine.run(erase(term), Env::default()).unwrap();
+        (value.quote(), machine.steps)
+    }
+
+    #[test]
+    fn strategies() {
+        let unit = erase(&de::unit());
+        let diverging = erase(&checked(
+            "let rec loop: () -> () = x: () -> loop x; (x: () -> ()) (loop ())",
+        ));
+        for strategy in [CallByName, CallByNeed] {
+            assert_eq!(
+                eval(diverging.clone(), strategy, Budget::default()).unwrap(),
+                unit
+            );
+        }
+
+        let work = "mul n n [()] (x: () -> x) ()";
+        let unused = church(10, &format!("(x: () -> ()) ({})", work));
+        let used = church(10, &format!("(x: () -> (x, x, x)) ({})", work));
+        let tuple =
+            ETuple(vec![unit.clone(), unit.clone(), unit.clone()]).into();
+        let cost = |strategy| {
+            let (result, unused) = steps(&unused, strategy);
+            assert_eq!(result, unit);
+            let (result, used) = steps(&used, strategy);
+            assert_eq!(result, tuple);
+            (unused, used)
+        };
+        let (value, name, need) =
+            (cost(CallByValue), cost(CallByName), cost(CallByNeed));
+        assert!(value.0 > 1000 && name.0 < 100 && need.0 < 100);
+        assert!(name.1 > 3 * need.1 && need.1 <= value.1);
+    }
+
+    #[test]
+    fn holes() {
+        let term = erase(&checked("let f = x: () -> x; (f (), f ?later)"));
+        let err = eval(term, CallByValue, Budget::default()).unwrap_err();
+        assert_eq!(err.to_string(), "Evaluation reached the hole ?later");
+        assert!(matches!(err.hole.as_deref(), Some(EHole(_))));
+    }
+
+    #[test]
+    fn budgets() {
+        let omega = erase(&checked(
+            "let f = x: (rec a => a -> ()) -> (unfold x) x
+f (fold [rec a => a -> ()] f)",
+        ));
+        let budget = Budget {
+            steps: Some(1000),
+            interrupt: None,
+        };
+        let err = eval(omega.clone(), CallByValue, budget).unwrap_err();
+        assert_eq!(err.steps, 1000);
+        assert!(eval(err.partial, CallByValue, budget).is_err());
+
+        static INTERRUPT: AtomicBool = AtomicBool::new(true);
+        let interrupted = Budget {
+            steps: None,
+            interrupt: Some(&INTERRUPT),
+        };
+        let err = eval(omega.clone(), CallByValue, interrupted).unwrap_err();
+        assert_eq!((err.steps, err.partial), (0, omega));
+
+        for (term, mut names) in examples() {
+            let term = erase(&term);
+            for strategy in [CallByValue, CallByName, CallByNeed] {
+                let unlimited = Budget::default();
+                let value = eval(term.clone(), strategy, unlimited).unwrap();
+                let normal =
+                    normalize(term.clone(), &mut names, unlimited).unwrap();
+                for steps in [1, 10, 100] {
+                    let budget = Budget {
+                        steps: Some(steps),
+                        interrupt: None,
+                    };
+                    let resumed = match eval(term.clone(), strategy, budget) {
+                        Ok(value) => value,
+                        Err(err) => {
+                            eval(err.partial, strategy, unlimited).unwrap()
+                        }
+                    };
+                    assert_eq!(resumed, value);
+                    let resumed =
+                        match normalize(term.clone(), &mut names, budget) {
+                            Ok(normal) => normal,
+                            Err(err) => {
+                                normalize(err.partial, &mut names, unlimited)
+                                    .unwrap()
+                            }
+                        };
+                    // Normalizing renames binders, so compare up to their
+                    // names.
+                    assert_eq!(resumed.pprint(&names), normal.pprint(&names));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn normal_forms() {
+        let normal = |input: &str| {
+            let (term, mut names) = named(input);
+            normalize(erase(&term), &mut names, Budget::default())
+                .unwrap()
+                .pprint(&names)
+        };
+        assert_eq!(normal("x: () -> (y: () -> y) x"), r"\x. x");
+        // Values print the same whether they are evaluated or normalised.
+        let (term, names) = named("a => x: a -> x");
+        let value = eval(erase(&term), CallByValue, Budget::default());
+        assert_eq!(value.unwrap().pprint(&names), r"\a. \x. x");
+        assert_eq!(normal("a => x: a -> x"), r"\a. \x. x");
+        assert_eq!(
+            normal(&format!("{}succ (succ zero)", CHURCH)),
+            r"\r. \s. \z. s (s z)"
+        );
+        assert_eq!(
+            normal("let rec f: () -> () = x: () -> f x\ny: () -> f y"),
+            r"\y. (fix f. \x. f x) y"
+        );
+        let stuck = "
+p: (() & ()) -> y: ((), ()) -> c: (exists a => (a, a -> ())) ->
+    let (x &) = p
+    let (_, b) = (x: ((), ()) -> x) y
+    unpack [t, (z, f)] = c
+    (x, b, f z, unfold (fold [rec n => ()] ()))
+";
+        assert_eq!(
+            normal(stuck),
+            [
+                r"\p. \y. \c. let (x &) = p; let (_, b) = y; ",
+                "let (z, f) = c; (x, b, f z, ())",
+            ]
+            .concat()
+        );
+    }
+
+    #[test]
+    fn definitional_equality() {
+        let equal = |pair: &str| {
+            let (term, mut names) = named(&format!("{}{}", CHURCH, pair));
+            let (left, right) = (de::proj(term.clone(), 0), de::proj(term, 1));
+            let (left, right) = (erase(&left), erase(&right));
+            super::equal(left, right, &mut names, Budget::default()).unwrap()
+        };
+        assert!(equal("(x: () -> x, y: () -> (z: () -> z) y)"));
+        assert!(!equal("(x: () -> x, y: () -> ())"));
+        let two = "succ (succ zero)";
+        assert!(equal(&format!("(add ({0}) ({0}), mul ({0}) ({0}))", two)));
+        assert!(!equal(&format!("(add ({0}) ({0}), succ ({0}))", two)));
+        assert!(equal(
+            "let rec f: () -> () = x: () -> f x
+let rec g: () -> () = y: () -> g y
+(f, g)"
+        ));
+
+        for (term, mut names) in examples() {
+            let term = erase(&term);
+            let value =
+                eval(term.clone(), CallByValue, Budget::default()).unwrap();
+            assert!(super::equal(term, value, &mut names, Budget::default())
+                .unwrap());
+        }
     }
 }
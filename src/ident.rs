@@ -1,137 +1,1033 @@
-use std::{collections::HashSet, error::Error, fmt::Display};
+use std::{
+    cell::Cell,
+    collections::{HashMap, HashSet},
+    error::Error,
+    fmt::Display,
+    mem,
+    rc::Rc,
+    vec,
+};
+
+use Namespace::*;
 
 use crate::{
     input::*,
     prelude::*,
-    syntax::{de, ty, Term, Type},
+    syntax::{de, ty, Class, Instance, Pattern, Term, Type},
+    typeck::subst_type,
 };
 
-pub type IdResult = Result<(Term, Names), Unbound>;
+pub type IdResult = MultiResult<(Term, Names, NameWarnings), NameErrors>;
 
 pub fn identify(term: InputTerm) -> IdResult {
-    let mut names = Names::default();
-    let MultiResult { result, collect } =
-        names.rename_term(&Stack::default(), term);
-    if collect.0.is_empty() {
-        Ok((result, names))
-    } else {
-        Err(collect)
-    }
+    let mut context = Context::default();
+    let term = context.rename_term(&Stack::default(), term);
+    term.map(|term| {
+        let (names, warnings) = context.finish();
+        (term, names, warnings)
+    })
+}
+
+type CtxResult<T> = MultiResult<T, NameErrors>;
+
+type Cont = Box<dyn FnOnce(&mut Context, CtxResult<Term>) -> Step>;
+
+/// A suspended renaming: `Context::rename_term` drives these with an explicit
+/// stack of continuations instead of recursing into subterms.
+enum Step {
+    Done(CtxResult<Term>),
+    Rename(Stack, Box<InputTerm>, Cont),
 }
 
-type CtxResult<T> = MultiResult<T, Unbound>;
+impl Step {
+    fn rename(
+        stack: &Stack,
+        term: InputTerm,
+        cont: impl FnOnce(&mut Context, CtxResult<Term>) -> Step + 'static,
+    ) -> Self {
+        Step::Rename(stack.clone(), Box::new(term), Box::new(cont))
+    }
+
+    fn with<T: 'static>(
+        first: CtxResult<T>,
+        stack: &Stack,
+        term: InputTerm,
+        f: impl FnOnce(T, Term) -> Term + 'static,
+    ) -> Self {
+        Step::rename(stack, term, move |_, term| {
+            Step::Done((first + term).map(|(first, term)| f(first, term)))
+        })
+    }
+}
 
 #[derive(Default)]
-struct Stack<'a>(Option<(&'a Self, String, Var)>);
+struct Context {
+    names: Names,
+    binders: Vec<Rc<Entry>>,
+    warnings: NameWarnings,
+    imported: bool,
+    datatypes: HashMap<Var, Datatype>,
+}
+
+/// A `data` declaration, keyed by the variable of its name. Applying it to
+/// types expands to its definition, except inside the definition itself,
+/// where it stands for the variable of the recursive type.
+#[derive(Clone)]
+enum Datatype {
+    Defining(Vec<Var>, Var),
+    Defined(Vec<Var>, Type),
+}
+
+#[derive(Clone, Default)]
+struct Stack(Option<Rc<Entry>>);
+
+struct Entry {
+    prev: Stack,
+    namespace: Namespace,
+    name: String,
+    var: Var,
+    binder: Option<Range>,
+    used: Cell<bool>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Namespace {
+    Terms,
+    Types,
+    Constructors,
+    Modules,
+}
+
+impl Context {
+    fn finish(self) -> (Names, NameWarnings) {
+        let Context {
+            names,
+            binders,
+            warnings: NameWarnings(mut warnings),
+            ..
+        } = self;
+        let unused = binders
+            .iter()
+            .filter(|entry| !entry.used.get() && !entry.name.starts_with('_'));
+        warnings.extend(unused.map(|entry| {
+            let warning =
+                NameWarning::Unused(entry.name.clone(), entry.namespace);
+            (warning, entry.binder.unwrap_or_default())
+        }));
+        warnings.sort_by_key(|(_, range)| range.from);
+        (names, NameWarnings(warnings))
+    }
 
-impl Names {
     fn rename_term(
         &mut self,
         stack: &Stack,
         term: InputTerm,
     ) -> CtxResult<Term> {
+        let mut conts = vec![];
+        let mut step = self.rename_step(stack, term);
+        loop {
+            step = match step {
+                Step::Rename(stack, term, cont) => {
+                    conts.push(cont);
+                    self.rename_step(&stack, *term)
+                }
+                Step::Done(result) => match conts.pop() {
+                    Some(cont) => cont(self, result),
+                    None => return result,
+                },
+            }
+        }
+    }
+
+    fn rename_step(
+        &mut self,
+        stack: &Stack,
+        InputTerm(term, range): InputTerm,
+    ) -> Step {
         match term {
-            TmUnit(_) => de::unit().into(),
-            TmVar(_, name) => stack.find_var(name),
-            TmAbs(_, name, ty, term) => {
-                let (var, ref stack) = self.new_var(stack, name);
-                (self.rename_type(stack, ty) + self.rename_term(stack, *term))
-                    .map(|(ty, term)| de::abs(var, ty, term))
+            TmTuple(items) => {
+                let items =
+                    items.into_iter().map(|item| (stack.clone(), *item));
+                self.sequence(items.collect(), |items| {
+                    Step::Done(items.map(de::tuple))
+                })
+            }
+            TmProj(tuple, index) => {
+                Step::rename(stack, *tuple, move |_, tuple| {
+                    Step::Done(tuple.map(|tuple| de::proj(tuple, index)))
+                })
+            }
+            TmWith(left, right) => {
+                let outer = stack.clone();
+                Step::rename(stack, *left, move |_, left| {
+                    Step::with(left, &outer, *right, de::with)
+                })
+            }
+            TmRecord(fields) => self.rename_fields(stack, fields, |fields| {
+                Step::Done(fields.map(de::record))
+            }),
+            TmField(record, label) => {
+                if let TmVar(module) = &record.0 {
+                    let name = format!("{}.{}", module, label);
+                    if let Some(var) = stack.map(Terms, &name) {
+                        return Step::Done(de::var(var).into());
+                    }
+                    if stack.entry(Modules, module).is_some() {
+                        let error =
+                            NameError::NotExported(module.clone(), label);
+                        return Step::Done(CtxResult::item((error, range)));
+                    }
+                }
+                Step::rename(stack, *record, move |_, record| {
+                    Step::Done(record.map(|record| de::field(record, label)))
+                })
+            }
+            TmUpdate(record, fields) => {
+                let outer = stack.clone();
+                Step::rename(stack, *record, move |this, record| {
+                    this.rename_fields(&outer, fields, |fields| {
+                        Step::Done(
+                            (record + fields).map(|(record, fields)| {
+                                de::update(record, fields)
+                            }),
+                        )
+                    })
+                })
+            }
+            TmInj(ty, label, term) => {
+                let ty = self.rename_type(stack, ty);
+                Step::with(ty, stack, *term, |ty, term| {
+                    de::inj(ty, label, term)
+                })
+            }
+            TmCase(term, arms) => {
+                let (arms, terms): (Vec<_>, _) = arms
+                    .into_iter()
+                    .map(|(label, pattern, arm)| {
+                        let (pattern, inner) =
+                            self.rename_pattern(stack, pattern);
+                        ((label, pattern), (inner, *arm))
+                    })
+                    .unzip();
+                Step::rename(stack, *term, move |this, term| {
+                    this.sequence(terms, |terms| {
+                        Step::Done((term + terms).map(|(term, terms)| {
+                            let arms = arms.into_iter().zip(terms);
+                            let arms = arms
+                                .map(|((label, pattern), arm)| {
+                                    (label, pattern, arm)
+                                })
+                                .collect();
+                            de::case(term, arms)
+                        }))
+                    })
+                })
+            }
+            TmHole(name) => {
+                stack.use_visible();
+                Step::Done(de::hole(name).into())
+            }
+            TmVar(name) => Step::Done(stack.find_var(Terms, name, range)),
+            TmAbs(PtVar(binder), ty, term) => {
+                let (var, ref inner) = self.new_binder(stack, Terms, binder);
+                let ty = self.rename_type(stack, ty);
+                Step::with(ty, inner, *term, move |ty, term| {
+                    de::abs(var, ty, term)
+                })
+            }
+            TmAbs(pattern, ty, term) => {
+                let var = self.names.push("_".into());
+                let (pattern, ref inner) = self.rename_pattern(stack, pattern);
+                let ty = self.rename_type(stack, ty);
+                Step::with(ty, inner, *term, move |ty, term| {
+                    let body =
+                        de::r#let(pattern, ty::hole(), de::var(var), term);
+                    de::abs(var, ty, body)
+                })
+            }
+            TmApp(f, x) => {
+                let outer = stack.clone();
+                Step::rename(stack, *f, move |_, f| {
+                    Step::with(f, &outer, *x, de::app)
+                })
+            }
+            TmTyAbs(binder, term) => {
+                let (var, ref stack) = self.new_binder(stack, Types, binder);
+                Step::rename(stack, *term, move |_, term| {
+                    Step::Done(term.map(|term| de::ty_abs(var, term)))
+                })
+            }
+            TmTyApp(f, x) => {
+                let outer = stack.clone();
+                Step::rename(stack, *f, move |this, f| {
+                    let x = this.rename_type(&outer, x);
+                    Step::Done((f + x).map(|(f, x)| de::ty_app(f, x)))
+                })
+            }
+            TmThunk(term) => {
+                let var = self.names.push("_".into());
+                Step::rename(stack, *term, move |_, term| {
+                    Step::Done(term.map(|term| de::abs(var, ty::unit(), term)))
+                })
+            }
+            TmLet(pattern, ty, value, body) => {
+                let (pattern, inner) = self.rename_pattern(stack, pattern);
+                let ty = self.rename_type(stack, ty);
+                Step::rename(stack, *value, move |_, value| {
+                    Step::with(
+                        ty + value,
+                        &inner,
+                        *body,
+                        |(ty, value), body| de::r#let(pattern, ty, value, body),
+                    )
+                })
+            }
+            TmLetRec(binder, ty, value, body) => {
+                let name = binder.0.clone();
+                let (fix, ref inner) = self.new_var(stack, Terms, name);
+                let (var, rest) = self.new_binder(stack, Terms, binder);
+                let ty = self.rename_type(stack, ty);
+                Step::rename(inner, *value, move |_, value| {
+                    Step::with(
+                        ty + value,
+                        &rest,
+                        *body,
+                        move |(ty, value), body| {
+                            let value = de::fix(fix, ty.clone(), value);
+                            de::r#let(Pattern::PtVar(var), ty, value, body)
+                        },
+                    )
+                })
+            }
+            TmFold(ty, term) => {
+                let ty = self.rename_type(stack, ty);
+                Step::with(ty, stack, *term, de::fold)
+            }
+            TmUnfold(term) => Step::rename(stack, *term, |_, term| {
+                Step::Done(term.map(de::unfold))
+            }),
+            TmPack(witness, term, r#type) => {
+                let outer = stack.clone();
+                let witness = self.rename_type(stack, witness);
+                Step::rename(stack, *term, move |this, term| {
+                    let r#type = this.rename_type(&outer, r#type);
+                    Step::Done((witness + term + r#type).map(
+                        |((witness, term), r#type)| {
+                            de::pack(witness, term, r#type)
+                        },
+                    ))
+                })
+            }
+            TmUnpack(param, pattern, value, body) => {
+                let (param, ref inner) = self.new_binder(stack, Types, param);
+                let (pattern, inner) = self.rename_pattern(inner, pattern);
+                Step::rename(stack, *value, move |_, value| {
+                    Step::with(value, &inner, *body, move |value, body| {
+                        de::unpack(param, pattern, value, body)
+                    })
+                })
+            }
+            TmSeq(statement, rest) => {
+                let outer = stack.clone();
+                Step::rename(stack, *statement, move |_, statement| {
+                    Step::with(statement, &outer, *rest, |statement, rest| {
+                        let unit = Pattern::PtTuple(vec![]);
+                        de::r#let(unit, ty::hole(), statement, rest)
+                    })
+                })
+            }
+            TmQualAbs(class, ty, term) => {
+                let class = stack.find_var(Constructors, class, range)
+                    + self.rename_type(stack, ty);
+                Step::with(class, stack, *term, |(class, ty), term| match class
+                {
+                    Some(class) => de::qual_abs(class, ty, term),
+                    None => term,
+                })
+            }
+            TmClass(name, param, methods, body) => {
+                let (name, ref stack) = self.new_var(stack, Constructors, name);
+                let (param, ref inner) = self.new_var(stack, Types, param);
+                let (methods, ref stack) = self.new_vars(stack, Terms, methods);
+                let methods = methods.into_iter().map(|(var, ty)| {
+                    self.rename_type(inner, ty).map(move |ty| (var, ty))
+                });
+                Step::with(
+                    collect(methods),
+                    stack,
+                    *body,
+                    move |methods, body| {
+                        let class = Class {
+                            name,
+                            param,
+                            methods,
+                        };
+                        de::class(class, body)
+                    },
+                )
+            }
+            TmData(name, params, variants, body) => {
+                self.rename_data(stack, name, params, variants, *body)
             }
-            TmApp(_, f, x) => (self.rename_term(stack, *f)
-                + self.rename_term(stack, *x))
-            .map(|(f, x)| de::app(f, x)),
-            TmTyAbs(_, name, term) => {
-                let (var, ref stack) = self.new_var(stack, name);
-                self.rename_term(stack, *term)
-                    .map(move |term| de::ty_abs(var, term))
+            TmInstance(instance, body) => {
+                let InputInstance {
+                    params,
+                    context,
+                    class,
+                    head,
+                    methods,
+                } = instance;
+                let params = params.into_iter().map(|param| (param, ()));
+                let (params, inner) = self.new_vars(stack, Types, params);
+                let params = params.into_iter().map(|(param, _)| param);
+                let context =
+                    collect(context.into_iter().map(|(class, ty)| {
+                        inner.find_var(Constructors, class, range)
+                            + self.rename_type(&inner, ty)
+                    }));
+                let class =
+                    stack.find_var::<Option<Var>>(Constructors, class, range);
+                // The methods of a class that is not in scope cannot be
+                // found either, so they are not reported.
+                let known = class.result.is_some();
+                let instance = context + class + self.rename_type(&inner, head);
+                let (names, methods): (Vec<_>, _) = methods
+                    .into_iter()
+                    .map(|(name, method)| {
+                        let name = match known {
+                            true => stack.find_var(Terms, name, range),
+                            false => None.into(),
+                        };
+                        (name, (inner.clone(), *method))
+                    })
+                    .unzip();
+                let names = collect(names.into_iter());
+                let stack = stack.clone();
+                self.sequence(methods, move |methods| {
+                    let methods = (names + methods).map(|(names, methods)| {
+                        names.into_iter().zip(methods).collect()
+                    });
+                    let instance = (instance + methods).map(
+                        |(((context, class), head), methods)| {
+                            Some(Instance {
+                                params: params.collect(),
+                                context: resolved(context)?,
+                                class: class?,
+                                head,
+                                methods: resolved(methods)?,
+                            })
+                        },
+                    );
+                    Step::with(instance, &stack, *body, |instance, body| {
+                        match instance {
+                            Some(instance) => de::instance(instance, body),
+                            None => body,
+                        }
+                    })
+                })
             }
-            TmTyApp(_, f, x) => (self.rename_term(stack, *f)
-                + self.rename_type(stack, x))
-            .map(|(f, x)| de::ty_app(f, x)),
-            TmError => unreachable!(),
+            TmImport(import, Some(module), body) => {
+                self.rename_import(stack, import, *module, *body, range)
+            }
+            TmImport(import, None, body) => {
+                let error = NameError::NotLoaded(import.module);
+                Step::rename(stack, *body, move |_, body| {
+                    Step::Done(body << CtxResult::new((), (error, range)))
+                })
+            }
+            TmModule(_, _, body) => {
+                Step::rename(stack, *body, |_, body| Step::Done(body))
+            }
+            TmError => Step::Done(de::error().into()),
+        }
+    }
+
+    fn sequence(
+        &mut self,
+        terms: Vec<(Stack, InputTerm)>,
+        finish: impl FnOnce(CtxResult<Vec<Term>>) -> Step + 'static,
+    ) -> Step {
+        self.sequence_from(terms.into_iter(), Vec::new().into(), finish)
+    }
+
+    fn sequence_from(
+        &mut self,
+        mut terms: vec::IntoIter<(Stack, InputTerm)>,
+        renamed: CtxResult<Vec<Term>>,
+        finish: impl FnOnce(CtxResult<Vec<Term>>) -> Step + 'static,
+    ) -> Step {
+        match terms.next() {
+            Some((stack, term)) => {
+                Step::rename(&stack, term, move |this, term| {
+                    let renamed = (renamed + term).map(|(mut items, item)| {
+                        items.push(item);
+                        items
+                    });
+                    this.sequence_from(terms, renamed, finish)
+                })
+            }
+            None => finish(renamed),
         }
     }
 
+    fn rename_import(
+        &mut self,
+        stack: &Stack,
+        import: InputImport,
+        module: InputTerm,
+        body: InputTerm,
+        range: Range,
+    ) -> Step {
+        let (exports, module) = match module {
+            InputTerm(TmModule(_, exports, module), _) => (exports, *module),
+            module => (vec![], module),
+        };
+        let imported = mem::replace(&mut self.imported, true);
+        let stack = stack.clone();
+        Step::rename(&Stack::default(), module, move |this, module| {
+            this.imported = imported;
+            this.rename_imported(&stack, import, exports, module, body, range)
+        })
+    }
+
+    fn rename_imported(
+        &mut self,
+        stack: &Stack,
+        import: InputImport,
+        exports: Vec<String>,
+        module: CtxResult<Term>,
+        body: InputTerm,
+        range: Range,
+    ) -> Step {
+        let InputImport {
+            module: name,
+            qualified,
+            names,
+        } = import;
+        let alias = qualified.clone().unwrap_or_else(|| name.clone());
+        let (record, ref inner) = self.new_var(stack, Modules, alias.clone());
+        let fields = exports
+            .iter()
+            .map(|field| (format!("{}.{}", alias, field), field.clone()));
+        let (fields, mut inner) = self.new_vars(inner, Terms, fields);
+        let mut result = CtxResult::from(());
+        let imported = match (qualified, names) {
+            (Some(_), _) => vec![],
+            (None, Some(names)) => names,
+            (None, None) => exports,
+        };
+        for import in imported {
+            match fields.iter().find(|(_, field)| *field == import) {
+                Some((var, _)) => inner = inner.push(Terms, import, *var),
+                None => {
+                    let error = NameError::NotExported(name.clone(), import);
+                    result += (error, range);
+                }
+            }
+        }
+        Step::rename(&inner, body, move |_, body| {
+            Step::Done(((module + body) << result).map(|(module, body)| {
+                let body = fields.into_iter().rev().fold(
+                    body,
+                    |body, (var, field)| {
+                        let value = de::field(de::var(record), field);
+                        de::r#let(Pattern::PtVar(var), ty::hole(), value, body)
+                    },
+                );
+                de::r#let(Pattern::PtVar(record), ty::hole(), module, body)
+            }))
+        })
+    }
+
+    fn rename_data(
+        &mut self,
+        stack: &Stack,
+        name: Binder,
+        params: Vec<Binder>,
+        variants: Vec<(String, InputType)>,
+        body: InputTerm,
+    ) -> Step {
+        let (var, ref outer) = self.new_binder(stack, Types, name.clone());
+        // A declared type is part of the program's interface, like its
+        // constructors, so it is not reported when nothing names it.
+        if let Some(entry) = &outer.0 {
+            entry.used.set(true);
+        }
+        let this = self.names.push(name.0);
+        let mut inner = outer.clone();
+        let params: Vec<_> = params
+            .into_iter()
+            .map(|param| {
+                let (param, next) = self.new_binder(&inner, Types, param);
+                inner = next;
+                param
+            })
+            .collect();
+        self.datatypes
+            .insert(var, Datatype::Defining(params.clone(), this));
+        let (labels, variants): (Vec<_>, _) = variants
+            .into_iter()
+            .map(|(label, ty)| ((label.clone(), ()), (label, Box::new(ty))))
+            .unzip();
+        let constructors = self.rename_row(&inner, variants).map(|row| {
+            let variant = ty::variant(row.clone(), None);
+            let rec = ty::rec(this, variant.clone());
+            self.datatypes
+                .insert(var, Datatype::Defined(params.clone(), rec));
+            row.into_iter()
+                .map(|(label, payload)| {
+                    self.constructor(&params, this, &variant, label, payload)
+                })
+                .collect::<Vec<_>>()
+        });
+        let (vars, ref inner) = self.new_vars(outer, Terms, labels);
+        Step::with(constructors, inner, body, move |constructors, body| {
+            vars.into_iter().zip(constructors).rev().fold(
+                body,
+                |body, ((var, ()), constructor)| {
+                    let var = Pattern::PtVar(var);
+                    de::r#let(var, ty::hole(), constructor, body)
+                },
+            )
+        })
+    }
+
+    /// Builds `a => x: payload -> fold [rec] (inj [[...]] label x)`, with
+    /// fresh type parameters for each constructor.
+    fn constructor(
+        &mut self,
+        params: &[Var],
+        this: Var,
+        variant: &Type,
+        label: String,
+        payload: Type,
+    ) -> Term {
+        let fresh: Vec<_> = params
+            .iter()
+            .map(|param| self.names.push(self.names[*param].clone()))
+            .collect();
+        let rename = |ty: Type| {
+            params.iter().zip(&fresh).fold(ty, |ty, (param, fresh)| {
+                subst_type(ty, ty::var(*fresh), *param)
+            })
+        };
+        let variant = rename(variant.clone());
+        let rec = ty::rec(this, variant.clone());
+        let payload = subst_type(rename(payload), rec.clone(), this);
+        let unrolled = subst_type(variant, rec.clone(), this);
+        let x = self.names.push("x".into());
+        let inj = de::inj(unrolled, label, de::var(x));
+        let term = de::abs(x, payload, de::fold(rec, inj));
+        fresh
+            .into_iter()
+            .rev()
+            .fold(term, |term, param| de::ty_abs(param, term))
+    }
+
     fn rename_type(
         &mut self,
         stack: &Stack,
-        input_type: InputType,
+        InputType(input_type, range): InputType,
     ) -> CtxResult<Type> {
         match input_type {
-            TyUnit(_) => ty::unit().into(),
-            TyHole(_) => ty::hole().into(),
-            TyVar(_, name) => stack.find_var(name),
-            TyArrow(_, from, to) => (self.rename_type(stack, *from)
+            TyTuple(items) => {
+                let items = items
+                    .into_iter()
+                    .map(|item| self.rename_type(stack, *item));
+                collect(items).map(ty::tuple)
+            }
+            TyWith(left, right) => (self.rename_type(stack, *left)
+                + self.rename_type(stack, *right))
+            .map(|(left, right)| ty::with(left, right)),
+            TyRecord(fields, tail) => (self.rename_row(stack, fields)
+                + self.rename_tail(stack, tail))
+            .map(|(fields, tail)| ty::record(fields, tail)),
+            TyVariant(fields, tail) => (self.rename_row(stack, fields)
+                + self.rename_tail(stack, tail))
+            .map(|(fields, tail)| ty::variant(fields, tail)),
+            TyHole => ty::hole().into(),
+            TyVar(name) => self.rename_applied(stack, name, vec![], range),
+            TyApp(name, args) => {
+                let args = args.into_iter().map(|arg| *arg).collect();
+                self.rename_applied(stack, name, args, range)
+            }
+            TyArrow(from, to) => (self.rename_type(stack, *from)
                 + self.rename_type(stack, *to))
             .map(|(from, to)| ty::arr(from, to)),
-            TyForall(_, name, ty) => {
-                let (var, ref stack) = self.new_var(stack, name);
+            TyForall(binder, ty) => {
+                let (var, ref stack) = self.new_binder(stack, Types, binder);
                 self.rename_type(stack, *ty)
                     .map(move |ty| ty::forall(var, ty))
             }
-            TyError => unreachable!(),
+            TyRec(binder, ty) => {
+                let (var, ref stack) = self.new_binder(stack, Types, binder);
+                self.rename_type(stack, *ty).map(move |ty| ty::rec(var, ty))
+            }
+            TyExists(binder, ty) => {
+                let (var, ref stack) = self.new_binder(stack, Types, binder);
+                self.rename_type(stack, *ty)
+                    .map(move |ty| ty::exists(var, ty))
+            }
+            TyQual(class, arg, ty) => {
+                (stack.find_var(Constructors, class, range)
+                    + self.rename_type(stack, *arg)
+                    + self.rename_type(stack, *ty))
+                .map(|((class, arg), ty)| match class {
+                    Some(class) => ty::qual(class, arg, ty),
+                    None => ty,
+                })
+            }
+            TyError => ty::error().into(),
+        }
+    }
+
+    fn rename_applied(
+        &mut self,
+        stack: &Stack,
+        name: String,
+        args: Vec<InputType>,
+        range: Range,
+    ) -> CtxResult<Type> {
+        let args = args.into_iter().map(|arg| self.rename_type(stack, arg));
+        let args = collect(args);
+        let var = match stack.map(Types, &name) {
+            Some(var) => var,
+            None => return stack.find_var(Types, name, range) << args,
+        };
+        let datatype = self.datatypes.get(&var).cloned();
+        args.then(|args| {
+            let params = match &datatype {
+                Some(Datatype::Defining(params, _))
+                | Some(Datatype::Defined(params, _)) => params.len(),
+                None => 0,
+            };
+            if args.len() != params {
+                let error = NameError::WrongArity(name, params, args.len());
+                return CtxResult::new(ty::error(), (error, range));
+            }
+            match datatype {
+                Some(Datatype::Defining(params, this)) => {
+                    let uniform = params
+                        .into_iter()
+                        .zip(args)
+                        .all(|(param, arg)| arg == ty::var(param));
+                    if uniform {
+                        ty::var(this).into()
+                    } else {
+                        let error = NameError::NotUniform(name);
+                        CtxResult::new(ty::error(), (error, range))
+                    }
+                }
+                Some(Datatype::Defined(params, rec)) => params
+                    .into_iter()
+                    .zip(args)
+                    .fold(rec, |rec, (param, arg)| subst_type(rec, arg, param))
+                    .into(),
+                None => ty::var(var).into(),
+            }
+        })
+    }
+
+    fn rename_fields(
+        &mut self,
+        stack: &Stack,
+        fields: Vec<(String, Box<InputTerm>)>,
+        finish: impl FnOnce(CtxResult<Vec<(String, Term)>>) -> Step + 'static,
+    ) -> Step {
+        let (labels, terms): (Vec<_>, Vec<_>) = fields
+            .into_iter()
+            .map(|(label, term)| (label, (stack.clone(), *term)))
+            .unzip();
+        self.sequence(terms, move |terms| {
+            finish(terms.map(|terms| labels.into_iter().zip(terms).collect()))
+        })
+    }
+
+    fn rename_row(
+        &mut self,
+        stack: &Stack,
+        fields: Vec<(String, Box<InputType>)>,
+    ) -> CtxResult<Vec<(String, Type)>> {
+        let fields = fields.into_iter().map(|(label, ty)| {
+            self.rename_type(stack, *ty).map(|ty| (label, ty))
+        });
+        collect(fields)
+    }
+
+    fn rename_tail(
+        &mut self,
+        stack: &Stack,
+        tail: Option<Box<InputType>>,
+    ) -> CtxResult<Option<Type>> {
+        match tail {
+            Some(tail) => self.rename_type(stack, *tail).map(Some),
+            None => None.into(),
+        }
+    }
+
+    fn rename_pattern(
+        &mut self,
+        stack: &Stack,
+        pattern: InputPattern,
+    ) -> (Pattern, Stack) {
+        match pattern {
+            PtWild => (Pattern::PtWild, stack.clone()),
+            PtTuple(items) => {
+                let mut stack = stack.clone();
+                let items = items
+                    .into_iter()
+                    .map(|item| {
+                        let (item, next) = self.rename_pattern(&stack, item);
+                        stack = next;
+                        item
+                    })
+                    .collect();
+                (Pattern::PtTuple(items), stack)
+            }
+            PtLeft(pattern) => {
+                let (pattern, stack) = self.rename_pattern(stack, *pattern);
+                (Pattern::PtLeft(Box::new(pattern)), stack)
+            }
+            PtRight(pattern) => {
+                let (pattern, stack) = self.rename_pattern(stack, *pattern);
+                (Pattern::PtRight(Box::new(pattern)), stack)
+            }
+            PtVar(binder) => {
+                let (var, stack) = self.new_binder(stack, Terms, binder);
+                (Pattern::PtVar(var), stack)
+            }
         }
     }
 
-    fn new_var<'a>(
+    fn new_var(
         &mut self,
-        stack: &'a Stack,
+        stack: &Stack,
+        namespace: Namespace,
         name: String,
-    ) -> (Var, Stack<'a>) {
-        let var = self.push(name.clone());
-        let stack = stack.push(name, var);
+    ) -> (Var, Stack) {
+        let var = self.names.push(name.clone());
+        let stack = stack.push(namespace, name, var);
         (var, stack)
     }
+
+    fn new_binder(
+        &mut self,
+        stack: &Stack,
+        namespace: Namespace,
+        Binder(name, range): Binder,
+    ) -> (Var, Stack) {
+        let var = self.names.push(name.clone());
+        if self.imported {
+            return (var, stack.push(namespace, name, var));
+        }
+        if !name.starts_with('_') {
+            if let Some(outer) = stack.entry(namespace, &name) {
+                if let Some(outer) = outer.binder {
+                    let warning = NameWarning::Shadows(name.clone(), outer);
+                    self.warnings.0.push((warning, range));
+                }
+            }
+        }
+        let entry = Rc::new(Entry {
+            prev: stack.clone(),
+            namespace,
+            name,
+            var,
+            binder: Some(range),
+            used: Cell::new(false),
+        });
+        self.binders.push(entry.clone());
+        (var, Stack(Some(entry)))
+    }
+
+    fn new_vars<T>(
+        &mut self,
+        stack: &Stack,
+        namespace: Namespace,
+        items: impl IntoIterator<Item = (String, T)>,
+    ) -> (Vec<(Var, T)>, Stack) {
+        let mut stack = stack.clone();
+        let items = items
+            .into_iter()
+            .map(|(name, item)| {
+                let (var, next) = self.new_var(&stack, namespace, name);
+                stack = next;
+                (var, item)
+            })
+            .collect();
+        (items, stack)
+    }
+}
+
+/// The pairs whose variables were all found, or `None` if one was not. Names
+/// that are not found are reported, and drop the declaration that uses them
+/// instead of standing for some other variable.
+fn resolved<T>(pairs: Vec<(Option<Var>, T)>) -> Option<Vec<(Var, T)>> {
+    pairs.into_iter().map(|(var, x)| Some((var?, x))).collect()
 }
 
-impl<'a> Stack<'a> {
-    fn push(&'a self, name: String, var: Var) -> Self {
-        Self(Some((self, name, var)))
+fn collect<T>(items: impl Iterator<Item = CtxResult<T>>) -> CtxResult<Vec<T>> {
+    items.fold(Vec::new().into(), |items, item| {
+        (items + item).map(|(mut items, item)| {
+            items.push(item);
+            items
+        })
+    })
+}
+
+impl Stack {
+    fn push(&self, namespace: Namespace, name: String, var: Var) -> Self {
+        Self(Some(Rc::new(Entry {
+            prev: self.clone(),
+            namespace,
+            name,
+            var,
+            binder: None,
+            used: Cell::new(false),
+        })))
     }
 
-    fn map(&self, name: String) -> Result<Var, String> {
-        match self.0 {
-            Some((_, ref key, var)) if *key == name => Ok(var),
-            Some((prev, _, _)) => prev.map(name),
-            None => Err(name),
+    fn entry(&self, namespace: Namespace, name: &str) -> Option<&Entry> {
+        let mut stack = self;
+        while let Some(entry) = stack.0.as_deref() {
+            if entry.namespace == namespace && entry.name == name {
+                return Some(entry);
+            }
+            stack = &entry.prev;
+        }
+        None
+    }
+
+    /// Marks the term and type names in scope as used, since a hole shows
+    /// them in its context.
+    fn use_visible(&self) {
+        let mut seen = HashSet::new();
+        let mut stack = self;
+        while let Some(entry) = stack.0.as_deref() {
+            let visible = seen.insert((entry.namespace, entry.name.as_str()));
+            if visible && matches!(entry.namespace, Terms | Types) {
+                entry.used.set(true);
+            }
+            stack = &entry.prev;
         }
     }
 
-    fn find_var<T>(&self, name: String) -> CtxResult<T>
+    fn map(&self, namespace: Namespace, name: &str) -> Option<Var> {
+        let entry = self.entry(namespace, name)?;
+        entry.used.set(true);
+        Some(entry.var)
+    }
+
+    fn find_var<T>(
+        &self,
+        namespace: Namespace,
+        name: String,
+        range: Range,
+    ) -> CtxResult<T>
     where
         T: From<Var> + Default,
     {
-        match self.map(name) {
-            Ok(var) => T::from(var).into(),
-            Err(error) => CtxResult::item(error),
+        if let Some(var) = self.map(namespace, &name) {
+            return T::from(var).into();
         }
+        let found = [Terms, Types, Constructors, Modules]
+            .iter()
+            .find(|ns| self.entry(**ns, &name).is_some());
+        let error = match found {
+            Some(found) => NameError::WrongSort(name, *found, namespace),
+            None => NameError::Unbound(name, namespace),
+        };
+        CtxResult::item((error, range))
     }
 }
 
-#[derive(Default, Debug)]
-pub struct Unbound(HashSet<String>);
+impl Drop for Entry {
+    fn drop(&mut self) {
+        let mut prev = self.prev.0.take();
+        while let Some(entry) = prev {
+            prev = match Rc::try_unwrap(entry) {
+                Ok(mut entry) => entry.prev.0.take(),
+                Err(_) => None,
+            };
+        }
+    }
+}
 
-impl Error for Unbound {}
+impl Display for Namespace {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Terms => write!(f, "term"),
+            Types => write!(f, "type"),
+            Constructors => write!(f, "constructor"),
+            Modules => write!(f, "module"),
+        }
+    }
+}
 
-impl Display for Unbound {
+#[derive(Debug, PartialEq, Eq, Hash)]
+pub enum NameError {
+    Unbound(String, Namespace),
+    WrongSort(String, Namespace, Namespace),
+    NotExported(String, String),
+    NotLoaded(String),
+    WrongArity(String, usize, usize),
+    NotUniform(String),
+}
+
+impl Display for NameError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        for name in &self.0 {
-            writeln!(f, "Unbound name: {}", name)?;
+        match self {
+            NameError::Unbound(name, ns) => {
+                write!(f, "Unbound {} name: {}", ns, name)
+            }
+            NameError::WrongSort(name, found, expected) => write!(
+                f,
+                "Expected a {} name, but '{}' is a {}",
+                expected, name, found
+            ),
+            NameError::NotExported(module, name) => {
+                write!(f, "Module '{}' does not export '{}'", module, name)
+            }
+            NameError::NotLoaded(module) => {
+                write!(f, "Module '{}' is not loaded", module)
+            }
+            NameError::WrongArity(name, expected, found) => write!(
+                f,
+                "'{}' takes {} type arguments, but was given {}",
+                name, expected, found
+            ),
+            NameError::NotUniform(name) => write!(
+                f,
+                "'{}' must be applied to its own parameters in its definition",
+                name
+            ),
+        }
+    }
+}
+
+#[derive(Default, Debug, PartialEq)]
+pub struct NameErrors(HashSet<(NameError, Range)>);
+
+impl Error for NameErrors {}
+
+impl Display for NameErrors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (error, range) in &self.0 {
+            writeln!(f, "[{}] {}", range, error)?;
         }
         Ok(())
     }
 }
 
-impl Semigroup for Unbound {
+impl Semigroup for NameErrors {
     fn app(self, other: Self) -> Self {
         Self(self.0.app(other.0))
     }
 }
 
-impl<T> Singleton<T> for Unbound
+impl<T> Singleton<T> for NameErrors
 where
-    HashSet<String>: Singleton<T>,
+    HashSet<(NameError, Range)>: Singleton<T>,
 {
     fn single(elem: T) -> Self {
         Self(HashSet::single(elem))
@@ -142,16 +1038,60 @@ where
     }
 }
 
+#[derive(Debug, PartialEq, Eq)]
+pub enum NameWarning {
+    Unused(String, Namespace),
+    Shadows(String, Range),
+}
+
+impl Display for NameWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NameWarning::Unused(name, ns) => {
+                write!(f, "Unused {} name: {}", ns, name)
+            }
+            NameWarning::Shadows(name, outer) => {
+                write!(f, "'{}' shadows the binding at [{}]", name, outer)
+            }
+        }
+    }
+}
+
+#[derive(Default, Debug, PartialEq)]
+pub struct NameWarnings(Vec<(NameWarning, Range)>);
+
+impl Display for NameWarnings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (warning, range) in &self.0 {
+            writeln!(f, "[{}] Warning: {}", range, warning)?;
+        }
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::{
         parser::parse,
-        syntax::{de, ty},
+        syntax::{de, ty, TermData},
     };
 
-    fn parsed(input: &str) -> IdResult {
-        identify(parse(input).unwrap())
+    fn parsed(input: &str) -> Result<(Term, Names), NameErrors> {
+        identify(parse(input).into_result().unwrap())
+            .into_result()
+            .map(|(term, names, _)| (term, names))
+    }
+
+    fn warnings(input: &str) -> Vec<String> {
+        let (_, _, NameWarnings(warnings)) =
+            identify(parse(input).into_result().unwrap())
+                .into_result()
+                .unwrap();
+        warnings
+            .into_iter()
+            .map(|(warning, range)| format!("[{}] {}", range, warning))
+            .collect()
     }
 
     #[test]
@@ -176,4 +1116,115 @@ mod tests {
             )
         );
     }
+
+    #[test]
+    fn let_scope() {
+        assert!(parsed("let x = x; x").is_err());
+        assert_eq!(
+            parsed("let x = (); let _ = x; x").unwrap().0,
+            de::r#let(
+                Pattern::PtVar(0.into()),
+                ty::hole(),
+                de::unit(),
+                de::r#let(Pattern::PtWild, ty::hole(), de::var(0), de::var(0))
+            )
+        );
+    }
+
+    #[test]
+    fn namespaces() {
+        assert_eq!(
+            parsed("x => x: x -> x").unwrap().0,
+            de::ty_abs(0, de::abs(1, ty::var(0), de::var(1)))
+        );
+        let errors = identify(parse("x: () -> y: x -> y").result).collect;
+        let error = NameError::WrongSort("x".into(), Terms, Types);
+        assert!(errors.0.iter().any(|(err, _)| *err == error));
+        let errors =
+            identify(parse("class Show a = show : a -> (); Show").result)
+                .collect;
+        let error = NameError::WrongSort("Show".into(), Constructors, Terms);
+        assert!(errors.0.iter().any(|(err, _)| *err == error));
+    }
+
+    #[test]
+    fn data_arity() {
+        let errors = |input| identify(parse(input).result).collect.0;
+        let error = NameError::WrongArity("T".into(), 1, 0);
+        let wrong = errors("data T a = [A : a]; let x: T = (); x");
+        assert!(wrong.iter().any(|(err, _)| *err == error));
+        let error = NameError::NotUniform("T".into());
+        let nested = errors("data T a = [A : T (a, a), B : ()]; ()");
+        assert!(nested.iter().any(|(err, _)| *err == error));
+    }
+
+    #[test]
+    fn warnings_report_binders() {
+        assert_eq!(
+            warnings(r"y: () -> (x: () -> y: () -> x) y"),
+            vec![
+                "[0:19-0:20] 'y' shadows the binding at [0:0-0:1]",
+                "[0:19-0:20] Unused term name: y",
+            ]
+        );
+        assert_eq!(
+            warnings("let f = a => b => x: a -> (); let (y, _z) = ((), ()); f"),
+            vec![
+                "[0:13-0:14] Unused type name: b",
+                "[0:18-0:19] Unused term name: x",
+                "[0:35-0:36] Unused term name: y",
+            ]
+        );
+        assert_eq!(
+            warnings("let rec f: () = f; ()"),
+            vec!["[0:8-0:9] Unused term name: f"]
+        );
+        assert!(warnings("x => x: x -> x").is_empty());
+        assert!(warnings("a => x: a -> y: () -> ?h").is_empty());
+        assert!(warnings("data T = [A : ()]; ()").is_empty());
+        assert_eq!(
+            warnings("data T = [A : ()]; data T = [B : ()]; ()"),
+            vec!["[0:24-0:25] 'T' shadows the binding at [0:5-0:6]"]
+        );
+        assert_eq!(
+            warnings("x: () -> x: () -> ?h"),
+            vec![
+                "[0:0-0:1] Unused term name: x",
+                "[0:9-0:10] 'x' shadows the binding at [0:0-0:1]",
+            ]
+        );
+    }
+
+    #[test]
+    fn deep_terms() {
+        let depth = 1_000_000;
+        let input = |term| InputTerm(term, Range::default());
+        let mut body = input(TmTuple(vec![]));
+        for _ in 0..depth {
+            let f = Box::new(input(TmVar("f".into())));
+            body = input(TmApp(f, Box::new(body)));
+        }
+        let binder = PtVar(Binder("f".into(), Range::default()));
+        let unit = InputType(TyTuple(vec![]), Range::default());
+        let term = input(TmAbs(binder, unit, Box::new(body)));
+        let (term, _) = identify(term)
+            .into_result()
+            .map(|(term, names, _)| (term, names))
+            .unwrap();
+        let mut spine = match &*term {
+            TermData::TmAbs(_, _, body) => body.clone(),
+            _ => panic!("expected an abstraction"),
+        };
+        for _ in 0..depth {
+            spine = match &*spine {
+                TermData::TmApp(f, x) if **f == TermData::TmVar(0.into()) => {
+                    x.clone()
+                }
+                _ => panic!("expected an application of f"),
+            };
+        }
+        assert!(
+            matches!(&*spine, TermData::TmTuple(items) if items.is_empty())
+        );
+    }
 }
@@ -1,27 +1,91 @@
 use crate::prelude::*;
 
 #[derive(Debug, Clone)]
+#[allow(clippy::enum_variant_names)]
 pub enum InputTermRec<Rec, Type> {
-    TmUnit,
+    TmTuple(Vec<Rec>),
+    TmProj(Rec, usize),
+    TmWith(Rec, Rec),
+    TmRecord(Vec<(String, Rec)>),
+    TmField(Rec, String),
+    TmUpdate(Rec, Vec<(String, Rec)>),
+    TmInj(Type, String, Rec),
+    TmCase(Rec, Vec<(String, InputPattern, Rec)>),
+    TmHole(Option<String>),
     TmVar(String),
-    TmAbs(String, Type, Rec),
+    TmAbs(InputPattern, Type, Rec),
     TmApp(Rec, Rec),
-    TmTyAbs(String, Rec),
+    TmTyAbs(Binder, Rec),
     TmTyApp(Rec, Type),
+    TmThunk(Rec),
+    TmLet(InputPattern, Type, Rec, Rec),
+    TmLetRec(Binder, Type, Rec, Rec),
+    TmFold(Type, Rec),
+    TmUnfold(Rec),
+    TmPack(Type, Rec, Type),
+    TmUnpack(Binder, InputPattern, Rec, Rec),
+    TmSeq(Rec, Rec),
+    TmQualAbs(String, Type, Rec),
+    TmClass(String, String, Vec<(String, Type)>, Rec),
+    TmData(Binder, Vec<Binder>, Vec<(String, Type)>, Rec),
+    TmInstance(InputInstance<Rec, Type>, Rec),
+    TmImport(InputImport, Option<Rec>, Rec),
+    TmModule(String, Vec<String>, Rec),
+    TmError,
 }
 
 pub use InputTermRec::*;
 
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Binder(pub String, pub Range);
+
+#[derive(Debug, Clone)]
+#[allow(clippy::enum_variant_names)]
+pub enum InputPattern {
+    PtWild,
+    PtTuple(Vec<InputPattern>),
+    PtLeft(Box<InputPattern>),
+    PtRight(Box<InputPattern>),
+    PtVar(Binder),
+}
+
+pub use InputPattern::*;
+
+#[derive(Debug, Clone)]
+pub struct InputInstance<Rec, Type> {
+    pub params: Vec<String>,
+    pub context: Vec<(String, Type)>,
+    pub class: String,
+    pub head: Type,
+    pub methods: Vec<(String, Rec)>,
+}
+
+#[derive(Debug, Clone)]
+pub struct InputImport {
+    pub module: String,
+    pub qualified: Option<String>,
+    pub names: Option<Vec<String>>,
+}
+
 #[derive(Debug, Clone)]
 pub struct InputTerm(pub InputTermRec<Box<InputTerm>, InputType>, pub Range);
 
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[allow(clippy::enum_variant_names)]
 pub enum InputTypeRec<Rec> {
-    TyUnit,
+    TyTuple(Vec<Rec>),
+    TyWith(Rec, Rec),
+    TyRecord(Vec<(String, Rec)>, Option<Rec>),
+    TyVariant(Vec<(String, Rec)>, Option<Rec>),
     TyHole,
     TyVar(String),
+    TyApp(String, Vec<Rec>),
     TyArrow(Rec, Rec),
-    TyForall(String, Rec),
+    TyForall(Binder, Rec),
+    TyRec(Binder, Rec),
+    TyExists(Binder, Rec),
+    TyQual(String, Rec, Rec),
+    TyError,
 }
 
 pub use InputTypeRec::*;
@@ -0,0 +1,552 @@
+use std::fmt::Write as _;
+
+use crate::{
+    erase::*,
+    prelude::*,
+    syntax::*,
+    template::{template, Chunk},
+};
+
+const RUNTIME: &str = include_str!("runtime.js");
+
+/// Compiles an erased program to JavaScript that prints its value the way
+/// the interpreter does. Functions become JavaScript closures, and tail calls
+/// are trampolined. There are no effects to compile, so the code is in direct
+/// style.
+pub fn generate(term: &Erased, names: &Names) -> String {
+    Generator::new(names).generate(term)
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Value,
+    /// Bound by a recursive definition, which is unrolled on every use.
+    Fix,
+}
+
+/// A function being generated, or the whole program.
+struct Function {
+    /// The variable holding the closure.
+    closure: String,
+    /// Variables in scope and the JavaScript variables that hold them.
+    locals: Vec<(Var, Kind, String)>,
+    /// The free variables of the source and the variables holding them.
+    captures: Vec<(Var, String)>,
+    source: Erased,
+}
+
+enum Task<'t> {
+    Compile(&'t Erased, bool),
+    /// Pushes a JavaScript expression as an operand.
+    Push(String),
+    /// Binds the operand on top to the variables of a pattern.
+    Bind(&'t Pattern),
+    Unbind(usize),
+    Tuple(usize),
+    Proj(usize),
+    Record(&'t [(String, Erased)]),
+    Field(&'t str),
+    Update(&'t [(String, Erased)]),
+    Inj(&'t str),
+    Case(&'t [(String, Pattern, Erased)], bool),
+    /// Assigns the operand on top to the result of a `case`.
+    Assign(String),
+    /// Opens a block with the given line.
+    Open(String),
+    Close,
+    Call(bool),
+    /// Starts a closure built with the given runtime function.
+    Enter(Erased, Option<(Var, Kind)>, &'static str),
+    Exit,
+    With,
+    Fix,
+}
+
+/// Generates JavaScript with a stack of tasks rather than by recursion, one
+/// statement per node, with operands kept as JavaScript expressions.
+struct Generator<'n> {
+    names: &'n Names,
+    functions: Vec<Function>,
+    done: Vec<String>,
+    temps: usize,
+    indent: usize,
+    /// The templates of closures.
+    data: String,
+    code: String,
+}
+
+impl<'n> Generator<'n> {
+    fn new(names: &'n Names) -> Self {
+        Self {
+            names,
+            functions: vec![],
+            done: vec![],
+            temps: 0,
+            indent: 0,
+            data: String::new(),
+            code: String::new(),
+        }
+    }
+
+    fn generate(mut self, term: &Erased) -> String {
+        self.functions.push(Function {
+            closure: String::new(),
+            locals: vec![],
+            captures: vec![],
+            source: term.clone(),
+        });
+        self.line("main(() => {".into());
+        self.indent += 1;
+        let mut tasks = vec![Task::Compile(term, false)];
+        while let Some(task) = tasks.pop() {
+            match task {
+                Task::Compile(term, tail) => self.term(term, tail, &mut tasks),
+                Task::Push(operand) => self.done.push(operand),
+                Task::Bind(pattern) => {
+                    let operand = self.pop();
+                    self.bind(pattern, operand)
+                }
+                Task::Unbind(count) => {
+                    let locals = &mut self.function().locals;
+                    locals.truncate(locals.len() - count)
+                }
+                Task::Tuple(len) => {
+                    let items = self.done.split_off(self.done.len() - len);
+                    let tuple = self.temp(format!("[{}]", items.join(", ")));
+                    self.done.push(tuple)
+                }
+                Task::Proj(index) => {
+                    let x = self.pop();
+                    self.done.push(format!("{}[{}]", x, index))
+                }
+                Task::Record(fields) => {
+                    let values =
+                        self.done.split_off(self.done.len() - fields.len());
+                    let fields = entries(fields, values);
+                    let record = self.temp(format!("record([{}])", fields));
+                    self.done.push(record)
+                }
+                Task::Field(label) => {
+                    let x = self.pop();
+                    self.done.push(format!("{}[{}]", x, js_string(label)))
+                }
+                Task::Update(fields) => {
+                    let values =
+                        self.done.split_off(self.done.len() - fields.len());
+                    let x = self.pop();
+                    let fields = entries(fields, values);
+                    let record = format!("update({}, [{}])", x, fields);
+                    let record = self.temp(record);
+                    self.done.push(record)
+                }
+                Task::Inj(label) => {
+                    let x = self.pop();
+                    let inj = format!("new Inj({}, {})", js_string(label), x);
+                    let inj = self.temp(inj);
+                    self.done.push(inj)
+                }
+                Task::Case(arms, tail) => self.case(arms, tail, &mut tasks),
+                Task::Assign(result) => {
+                    let y = self.pop();
+                    self.line(format!("{} = {};", result, y));
+                    self.line("break;".into())
+                }
+                Task::Open(line) => {
+                    self.line(line);
+                    self.indent += 1
+                }
+                Task::Close => {
+                    self.indent -= 1;
+                    self.line("}".into())
+                }
+                Task::Call(tail) => {
+                    let x = self.pop();
+                    let f = self.pop();
+                    let call = if tail { "tailCall" } else { "apply" };
+                    let result = self.temp(format!("{}({}, {})", call, f, x));
+                    self.done.push(result)
+                }
+                Task::Enter(source, param, build) => {
+                    self.enter(source, param, build)
+                }
+                Task::Exit => self.exit(),
+                Task::With => {
+                    let r = self.pop();
+                    let l = self.pop();
+                    let with = self.temp(format!("new With({}, {})", l, r));
+                    self.done.push(with)
+                }
+                Task::Fix => {
+                    let fix = self.pop();
+                    let unrolled = self.temp(format!("unroll({})", fix));
+                    self.done.push(unrolled)
+                }
+            }
+        }
+        let value = self.pop();
+        self.line(format!("return {};", value));
+        self.indent -= 1;
+        self.line("});".into());
+        format!("{}\n{}\n{}", RUNTIME, self.data, self.code)
+    }
+
+    fn term<'t>(
+        &mut self,
+        term: &'t Erased,
+        tail: bool,
+        tasks: &mut Vec<Task<'t>>,
+    ) {
+        let mut then = vec![];
+        match &**term {
+            ETuple(items) => {
+                then.extend(items.iter().map(|x| Task::Compile(x, false)));
+                then.push(Task::Tuple(items.len()))
+            }
+            EProj(x, index) => {
+                then.extend(vec![Task::Compile(x, false), Task::Proj(*index)])
+            }
+            EWith(l, r) => then.extend(vec![
+                Task::Enter(l.clone(), None, "closure"),
+                Task::Compile(l, true),
+                Task::Exit,
+                Task::Enter(r.clone(), None, "closure"),
+                Task::Compile(r, true),
+                Task::Exit,
+                Task::With,
+            ]),
+            ERecord(fields) => {
+                then.extend(
+                    fields.iter().map(|(_, x)| Task::Compile(x, false)),
+                );
+                then.push(Task::Record(fields))
+            }
+            EField(x, l) => {
+                then.extend(vec![Task::Compile(x, false), Task::Field(l)])
+            }
+            EUpdate(x, fields) => {
+                then.push(Task::Compile(x, false));
+                then.extend(
+                    fields.iter().map(|(_, y)| Task::Compile(y, false)),
+                );
+                then.push(Task::Update(fields))
+            }
+            EInj(l, x) => {
+                then.extend(vec![Task::Compile(x, false), Task::Inj(l)])
+            }
+            ECase(x, arms) => then
+                .extend(vec![Task::Compile(x, false), Task::Case(arms, tail)]),
+            EVar(var) => {
+                let (operand, kind) = self.resolve(*var);
+                let operand = match kind {
+                    Kind::Value => operand,
+                    Kind::Fix => self.temp(format!("unroll({})", operand)),
+                };
+                self.done.push(operand)
+            }
+            EAbs(v, y) => then.extend(vec![
+                Task::Enter(term.clone(), Some((*v, Kind::Value)), "closure"),
+                Task::Compile(y, true),
+                Task::Exit,
+            ]),
+            EApp(f, x) => then.extend(vec![
+                Task::Compile(f, false),
+                Task::Compile(x, false),
+                Task::Call(tail),
+            ]),
+            ELet(p, x, y) => then.extend(vec![
+                Task::Compile(x, false),
+                Task::Bind(p),
+                Task::Compile(y, tail),
+                Task::Unbind(arity(p)),
+            ]),
+            EFix(f, x) => then.extend(vec![
+                Task::Enter(term.clone(), Some((*f, Kind::Fix)), "fix"),
+                Task::Compile(x, true),
+                Task::Exit,
+                Task::Fix,
+            ]),
+            EHole(name) => {
+                let name = match name {
+                    Some(name) => js_string(name),
+                    None => "null".into(),
+                };
+                let hole = self.temp(format!("hole({})", name));
+                self.done.push(hole)
+            }
+        }
+        tasks.extend(then.into_iter().rev())
+    }
+
+    /// Switches on the label of the operand on top, with the result of the
+    /// arm that runs assigned to a new variable.
+    fn case<'t>(
+        &mut self,
+        arms: &'t [(String, Pattern, Erased)],
+        tail: bool,
+        tasks: &mut Vec<Task<'t>>,
+    ) {
+        let x = self.pop();
+        let result = self.fresh("t");
+        self.line(format!("let {};", result));
+        let mut then = vec![Task::Open(format!("switch ({}.label) {{", x))];
+        for (l, p, y) in arms {
+            then.extend(vec![
+                Task::Open(format!("case {}: {{", js_string(l))),
+                Task::Push(format!("{}.value", x)),
+                Task::Bind(p),
+                Task::Compile(y, tail),
+                Task::Assign(result.clone()),
+                Task::Close,
+                Task::Unbind(arity(p)),
+            ]);
+        }
+        then.extend(vec![Task::Close, Task::Push(result)]);
+        tasks.extend(then.into_iter().rev())
+    }
+
+    fn bind(&mut self, pattern: &Pattern, operand: String) {
+        match pattern {
+            PtVar(var) => {
+                let local = self.fresh("x");
+                self.line(format!("const {} = {};", local, operand));
+                self.function().locals.push((*var, Kind::Value, local))
+            }
+            PtWild => {}
+            PtTuple(items) => {
+                for (i, item) in items.iter().enumerate() {
+                    self.bind(item, format!("{}[{}]", operand, i))
+                }
+            }
+            PtLeft(p) => {
+                let side = self.temp(format!("force({}.left)", operand));
+                self.bind(p, side)
+            }
+            PtRight(p) => {
+                let side = self.temp(format!("force({}.right)", operand));
+                self.bind(p, side)
+            }
+        }
+    }
+
+    /// Finds the variable holding `var`, which every function between the
+    /// binding and the current one captures.
+    fn resolve(&mut self, var: Var) -> (String, Kind) {
+        let mut depth = self.functions.len() - 1;
+        let (local, kind) = loop {
+            let function = &self.functions[depth];
+            let mut locals = function.locals.iter().rev();
+            if let Some((_, kind, local)) = locals.find(|(v, _, _)| *v == var) {
+                break (local.clone(), *kind);
+            }
+            depth -= 1;
+        };
+        for function in &mut self.functions[depth + 1..] {
+            if !function.captures.iter().any(|(v, _)| *v == var) {
+                function.captures.push((var, local.clone()))
+            }
+        }
+        (local, kind)
+    }
+
+    fn enter(
+        &mut self,
+        source: Erased,
+        param: Option<(Var, Kind)>,
+        build: &str,
+    ) {
+        let closure = self.fresh("t");
+        let mut locals = vec![];
+        let param = match param {
+            Some((var, kind)) => {
+                let local = self.fresh("x");
+                locals.push((var, kind, local.clone()));
+                local
+            }
+            None => String::new(),
+        };
+        self.line(format!("const {} = {}(({}) => {{", closure, build, param));
+        self.indent += 1;
+        self.functions.push(Function {
+            closure,
+            locals,
+            captures: vec![],
+            source,
+        })
+    }
+
+    fn exit(&mut self) {
+        let result = self.pop();
+        self.line(format!("return {};", result));
+        self.indent -= 1;
+        let function = self.functions.pop().unwrap();
+        let vars: Vec<_> = function.captures.iter().map(|(v, _)| *v).collect();
+        let chunks = template(&function.source, &vars, self.names);
+        let chunks: Vec<_> = chunks
+            .iter()
+            .map(|chunk| match chunk {
+                Chunk::Text(text) => js_string(text),
+                Chunk::Hole { index, arg } => format!("[{}, {}]", index, arg),
+            })
+            .collect();
+        let template = self.fresh("template");
+        let _ = writeln!(
+            self.data,
+            "const {} = [{}];",
+            template,
+            chunks.join(", ")
+        );
+        let captures = function.captures.into_iter();
+        let captured: Vec<_> = captures.map(|(_, local)| local).collect();
+        self.line(format!("}}, {}, [{}]);", template, captured.join(", ")));
+        self.done.push(function.closure)
+    }
+
+    /// Assigns a JavaScript expression to a new variable.
+    fn temp(&mut self, expr: String) -> String {
+        let temp = self.fresh("t");
+        self.line(format!("const {} = {};", temp, expr));
+        temp
+    }
+
+    fn fresh(&mut self, prefix: &str) -> String {
+        self.temps += 1;
+        format!("{}{}", prefix, self.temps)
+    }
+
+    fn line(&mut self, line: String) {
+        let indent = "    ".repeat(self.indent);
+        let _ = writeln!(self.code, "{}{}", indent, line);
+    }
+
+    fn pop(&mut self) -> String {
+        self.done.pop().unwrap()
+    }
+
+    fn function(&mut self) -> &mut Function {
+        self.functions.last_mut().unwrap()
+    }
+}
+
+fn entries(fields: &[(String, Erased)], values: Vec<String>) -> String {
+    let entries = fields.iter().zip(values).map(|((label, _), value)| {
+        format!("[{}, {}]", js_string(label), value)
+    });
+    entries.collect::<Vec<_>>().join(", ")
+}
+
+fn arity(pattern: &Pattern) -> usize {
+    match pattern {
+        PtVar(_) => 1,
+        PtWild => 0,
+        PtTuple(items) => items.iter().map(arity).sum(),
+        PtLeft(p) | PtRight(p) => arity(p),
+    }
+}
+
+/// Quotes text as a JavaScript string literal.
+fn js_string(text: &str) -> String {
+    let mut literal = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '\\' | '"' => {
+                literal.push('\\');
+                literal.push(c)
+            }
+            ' '..='~' => literal.push(c),
+            _ if c.is_control() => {
+                let _ = write!(literal, "\\u{:04x}", c as u32);
+            }
+            _ => literal.push(c),
+        }
+    }
+    literal.push('"');
+    literal
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::Write,
+        process::{self, Command, Stdio},
+    };
+
+    use super::*;
+    use crate::{
+        erase::erase,
+        eval::{eval, Budget, CallByValue},
+        testing::{named, programs, CHURCH, DEEP, MILLION},
+    };
+
+    /// Runs a program with Node.js, or returns `None` if it is not
+    /// installed.
+    fn execute(term: &Term, names: &Names) -> Option<process::Output> {
+        let mut node = Command::new("node")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .ok()?;
+        let program = generate(&erase(term), names);
+        let mut stdin = node.stdin.take().unwrap();
+        stdin.write_all(program.as_bytes()).unwrap();
+        drop(stdin);
+        Some(node.wait_with_output().unwrap())
+    }
+
+    /// Runs a program that should succeed with Node.js, or returns `None` if
+    /// it is not installed.
+    fn run(term: &Term, names: &Names) -> Option<String> {
+        let output = execute(term, names)?;
+        assert!(output.status.success());
+        Some(String::from_utf8(output.stdout).unwrap())
+    }
+
+    #[test]
+    fn agrees_with_eval() {
+        for (term, names) in programs() {
+            let value = eval(erase(&term), CallByValue, Budget::default());
+            let expected = value.unwrap().pprint(&names);
+            match run(&term, &names) {
+                Some(output) => assert_eq!(output, expected + "\n"),
+                None => return,
+            }
+        }
+    }
+
+    #[test]
+    fn deep_recursion() {
+        for body in ["length xs", "xs"].iter() {
+            let (term, names) = named(&format!("{}{}{}", CHURCH, DEEP, body));
+            let value = eval(erase(&term), CallByValue, Budget::default());
+            let expected = value.unwrap().pprint(&names);
+            let output = match execute(&term, &names) {
+                Some(output) => output,
+                None => return,
+            };
+            let message = String::from_utf8(output.stderr).unwrap();
+            match output.status.code() {
+                Some(0) => {
+                    assert_eq!(output.stdout, (expected + "\n").as_bytes())
+                }
+                code => assert!(
+                    code == Some(1) && message.starts_with("Stack overflow")
+                ),
+            }
+        }
+    }
+
+    #[test]
+    fn holes() {
+        let (term, names) = named("let f = x: () -> x; f ?later");
+        let program = generate(&erase(&term), &names);
+        assert!(program.contains("hole(\"later\")"));
+    }
+
+    #[test]
+    fn tail_calls() {
+        let (term, names) = named(&format!("{}{}", CHURCH, MILLION));
+        if let Some(output) = run(&term, &names) {
+            assert_eq!(output, "()\n")
+        }
+    }
+}
@@ -5,12 +5,149 @@ mod repl;
 mod parser;
 mod syntax;
 mod multi_result;
-mod alpha;
 mod names;
 mod prelude;
 mod input;
+mod modules;
 mod coordinates;
+mod step;
+mod subst;
+mod erase;
+mod vm;
+mod template;
+mod cgen;
+mod jsgen;
+#[cfg(test)]
+mod testing;
+
+use std::{env, fs, path::Path, process};
+
+use eval::{Budget, Strategy};
+use repl::{Mode, Options};
+
+const USAGE: &str = concat!(
+    "Usage: odlang [--no-prelude] [--normalize] [--bytecode] ",
+    "[--strategy=value|name|need] [--steps=N] [FILE]\n",
+    "       odlang build [--no-prelude] FILE -o OUTPUT",
+);
 
 fn main() {
-    println!("{:?}", repl::repl());
+    let mut options = Options {
+        prelude: true,
+        strategy: Strategy::default(),
+        mode: Mode::default(),
+        budget: Budget::default(),
+    };
+    let mut args = env::args().skip(1).peekable();
+    if args.next_if_eq("build").is_some() {
+        return build(args, options);
+    }
+    let mut file = None;
+    for arg in args {
+        match arg.as_str() {
+            "--no-prelude" => options.prelude = false,
+            "--normalize" => options.mode = Mode::Normalize,
+            "--bytecode" => options.mode = Mode::Bytecode,
+            _ if arg.starts_with("--strategy=") => {
+                match arg["--strategy=".len()..].parse() {
+                    Ok(strategy) => options.strategy = strategy,
+                    Err(err) => {
+                        eprintln!("{}\n{}", err, USAGE);
+                        process::exit(2);
+                    }
+                }
+            }
+            _ if arg.starts_with("--steps=") => {
+                match arg["--steps=".len()..].parse() {
+                    Ok(steps) => options.budget.steps = Some(steps),
+                    Err(_) => {
+                        eprintln!("{}", USAGE);
+                        process::exit(2);
+                    }
+                }
+            }
+            "-h" | "--help" => return println!("{}", USAGE),
+            _ if arg.starts_with('-') || file.is_some() => {
+                eprintln!("{}", USAGE);
+                process::exit(2);
+            }
+            _ => file = Some(arg),
+        }
+    }
+    match file {
+        Some(file) => run(&file, options),
+        None => println!("{:?}", repl::repl(options)),
+    }
+}
+
+fn run(file: &str, options: Options) {
+    let text = read(file);
+    let report = repl::process_line(&text, options);
+    eprint!("{}", report.collect);
+    match report.result.map_err(|err| err.to_string()) {
+        Ok(value) => println!("{}", value),
+        Err(err) => {
+            eprintln!("{}", err);
+            process::exit(1);
+        }
+    }
+}
+
+/// Compiles a program to JavaScript if the output is a JavaScript file, and
+/// otherwise to C, and the C to an executable unless the output is a C file.
+fn build(mut args: impl Iterator<Item = String>, mut options: Options) {
+    let (mut file, mut output) = (None, None);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--no-prelude" => options.prelude = false,
+            "-o" if output.is_none() => output = args.next(),
+            _ if arg.starts_with('-') || file.is_some() => {
+                eprintln!("{}", USAGE);
+                process::exit(2);
+            }
+            _ => file = Some(arg),
+        }
+    }
+    let (file, output) = match (file, output) {
+        (Some(file), Some(output)) => (file, output),
+        _ => {
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    };
+    let text = read(&file);
+    let output = Path::new(&output);
+    let built = if output.extension().is_some_and(|ext| ext == "js") {
+        compiled(repl::compile_line(&text, options, jsgen::generate))
+            .and_then(|source| {
+                fs::write(output, source).map_err(|err| {
+                    format!("Failed to write the JavaScript program: {}", err)
+                })
+            })
+    } else {
+        compiled(repl::compile_line(&text, options, cgen::generate))
+            .and_then(|source| {
+                cgen::build(&source, output).map_err(|err| err.to_string())
+            })
+    };
+    if let Err(err) = built {
+        eprintln!("{}", err);
+        process::exit(1);
+    }
+}
+
+/// Shows the warnings about a compiled program and returns the program.
+fn compiled(report: repl::Report<'_>) -> Result<String, String> {
+    eprint!("{}", report.collect);
+    report.result.map_err(|err| err.to_string())
+}
+
+fn read(file: &str) -> String {
+    match fs::read_to_string(file) {
+        Ok(text) => text,
+        Err(err) => {
+            eprintln!("Cannot read {}: {}", file, err);
+            process::exit(1);
+        }
+    }
 }
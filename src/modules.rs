@@ -0,0 +1,352 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    env,
+    error::Error,
+    fmt::Display,
+    fs,
+    path::PathBuf,
+};
+
+use crate::{
+    input::*,
+    parser::{parse, ParseErrors},
+    prelude::*,
+};
+
+const SEARCH_PATH: &str = "ODLANG_PATH";
+
+pub const PRELUDE: &str = "Prelude";
+
+const BUILTINS: [(&str, &str); 1] =
+    [(PRELUDE, include_str!("../lib/Prelude.od"))];
+
+pub struct Loader {
+    path: Vec<PathBuf>,
+    prelude: bool,
+    modules: HashMap<String, InputTerm>,
+    loading: Vec<String>,
+}
+
+pub type LoadResult = MultiResult<InputTerm, LoadErrors>;
+
+impl Loader {
+    pub fn new(path: Vec<PathBuf>, prelude: bool) -> Self {
+        Self {
+            path,
+            prelude,
+            modules: HashMap::new(),
+            loading: vec![],
+        }
+    }
+
+    pub fn from_env(prelude: bool) -> Self {
+        let mut path = vec![PathBuf::from(".")];
+        if let Some(paths) = env::var_os(SEARCH_PATH) {
+            path.extend(env::split_paths(&paths));
+        }
+        Self::new(path, prelude)
+    }
+
+    pub fn link(&mut self, term: InputTerm) -> LoadResult {
+        let term = self.implicit(term);
+        self.link_term(term)
+    }
+
+    fn implicit(&self, InputTerm(term, range): InputTerm) -> InputTerm {
+        let import = |body| {
+            let import = InputImport {
+                module: PRELUDE.to_owned(),
+                qualified: None,
+                names: None,
+            };
+            TmImport(import, None, Box::new(InputTerm(body, range)))
+        };
+        let term = match term {
+            TmModule(name, exports, body)
+                if name != PRELUDE && self.prelude =>
+            {
+                let InputTerm(body, range) = *body;
+                let body = InputTerm(import(body), range);
+                TmModule(name, exports, Box::new(body))
+            }
+            term @ TmModule(..) => term,
+            term if self.prelude => import(term),
+            term => term,
+        };
+        InputTerm(term, range)
+    }
+
+    fn link_term(&mut self, InputTerm(term, range): InputTerm) -> LoadResult {
+        let term = match term {
+            TmImport(import, None, body) => (self.load(&import, range)
+                + self.link_term(*body))
+            .map(|(module, body)| {
+                TmImport(import, Some(Box::new(module)), Box::new(body))
+            }),
+            TmImport(import, Some(module), body) => self
+                .link_term(*body)
+                .map(|body| TmImport(import, Some(module), Box::new(body))),
+            TmModule(name, exports, body) => self
+                .link_term(*body)
+                .map(|body| TmModule(name, exports, Box::new(body))),
+            TmLet(pattern, ty, value, body) => self
+                .link_term(*body)
+                .map(|body| TmLet(pattern, ty, value, Box::new(body))),
+            TmLetRec(name, ty, value, body) => self
+                .link_term(*body)
+                .map(|body| TmLetRec(name, ty, value, Box::new(body))),
+            TmUnpack(param, pattern, value, body) => self
+                .link_term(*body)
+                .map(|body| TmUnpack(param, pattern, value, Box::new(body))),
+            TmSeq(statement, body) => self
+                .link_term(*body)
+                .map(|body| TmSeq(statement, Box::new(body))),
+            TmClass(name, param, methods, body) => self
+                .link_term(*body)
+                .map(|body| TmClass(name, param, methods, Box::new(body))),
+            TmData(name, params, variants, body) => self
+                .link_term(*body)
+                .map(|body| TmData(name, params, variants, Box::new(body))),
+            TmInstance(instance, body) => self
+                .link_term(*body)
+                .map(|body| TmInstance(instance, Box::new(body))),
+            term => term.into(),
+        };
+        term.map(|term| InputTerm(term, range))
+    }
+
+    fn load(&mut self, import: &InputImport, range: Range) -> LoadResult {
+        let name = &import.module;
+        let stub = || {
+            let exports = import.names.clone().unwrap_or_default();
+            let body = Box::new(InputTerm(TmError, range));
+            InputTerm(TmModule(name.clone(), exports, body), range)
+        };
+        if let Some(module) = self.modules.get(name) {
+            return module.clone().into();
+        }
+        if self.loading.contains(name) {
+            let mut cycle = self.loading.clone();
+            cycle.push(name.clone());
+            return LoadResult::new(stub(), (LoadError::Cycle(cycle), range));
+        }
+        let builtin = BUILTINS.iter().find(|(builtin, _)| builtin == name);
+        let (file, text) = match builtin {
+            Some((_, text)) => {
+                (PathBuf::from(format!("<{}>", name)), text.to_string())
+            }
+            None => match self.find(name) {
+                Ok(found) => found,
+                Err(error) => return LoadResult::new(stub(), (error, range)),
+            },
+        };
+        let MultiResult { result, collect } = parse(&text);
+        if collect != ParseErrors::default() {
+            let error = LoadError::Parse(file, collect);
+            return LoadResult::new(stub(), (error, range));
+        }
+        match &result.0 {
+            TmModule(module, _, _) if module == name => {}
+            _ => {
+                let error = LoadError::NotAModule(file, name.clone());
+                return LoadResult::new(stub(), (error, range));
+            }
+        }
+        self.loading.push(name.clone());
+        let module = self.link(result);
+        self.loading.pop();
+        self.modules.insert(name.clone(), module.result.clone());
+        module
+    }
+
+    fn find(&self, name: &str) -> Result<(PathBuf, String), LoadError> {
+        let file = self
+            .path
+            .iter()
+            .map(|dir| dir.join(format!("{}.od", name)))
+            .find(|file| file.is_file())
+            .ok_or_else(|| LoadError::NotFound(name.to_owned()))?;
+        match fs::read_to_string(&file) {
+            Ok(text) => Ok((file, text)),
+            Err(err) => Err(LoadError::Unreadable(file, err.to_string())),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum LoadError {
+    NotFound(String),
+    Cycle(Vec<String>),
+    Unreadable(PathBuf, String),
+    Parse(PathBuf, ParseErrors),
+    NotAModule(PathBuf, String),
+}
+
+impl Display for LoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LoadError::NotFound(name) => {
+                write!(f, "Module '{}' is not on the search path", name)
+            }
+            LoadError::Cycle(cycle) => {
+                write!(f, "Import cycle: {}", cycle.join(" -> "))
+            }
+            LoadError::Unreadable(file, err) => {
+                write!(f, "Cannot read {}: {}", file.display(), err)
+            }
+            LoadError::Parse(file, errors) => {
+                write!(f, "In {}:\n{}", file.display(), errors)
+            }
+            LoadError::NotAModule(file, name) => {
+                write!(
+                    f,
+                    "{} does not declare module '{}'",
+                    file.display(),
+                    name
+                )
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct LoadErrors(VecDeque<(LoadError, Range)>);
+
+impl Error for LoadErrors {}
+
+impl Display for LoadErrors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (error, range) in &self.0 {
+            writeln!(f, "[{}] {}", range, error)?;
+        }
+        Ok(())
+    }
+}
+
+impl Semigroup for LoadErrors {
+    fn app(self, other: Self) -> Self {
+        Self(self.0.app(other.0))
+    }
+}
+
+impl<T> Singleton<T> for LoadErrors
+where
+    VecDeque<(LoadError, Range)>: Singleton<T>,
+{
+    fn single(elem: T) -> Self {
+        Self(VecDeque::single(elem))
+    }
+
+    fn push(&mut self, elem: T) {
+        self.0.push(elem)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        erase::erase,
+        eval::{eval, Budget, CallByValue},
+        ident::identify,
+        typeck::typeck,
+    };
+
+    fn run(loader: &mut Loader, input: &str) -> Result<String, String> {
+        let linked = loader.link(parse(input).into_result().unwrap());
+        let linked = linked.into_result().map_err(|err| err.to_string())?;
+        let (term, mut names, _) = identify(linked)
+            .into_result()
+            .map_err(|err| err.to_string())?;
+        let (term, _) =
+            typeck(term, &mut names).map_err(|err| err.pprint(&names))?;
+        Ok(eval(erase(&term), CallByValue, Budget::default())
+            .unwrap()
+            .pprint(&names))
+    }
+
+    #[test]
+    fn imports() {
+        let mut loader = Loader::new(vec!["examples".into()], false);
+        let program = include_str!("../examples/modules.od");
+        assert_eq!(run(&mut loader, program), Ok("()".into()));
+        let qualified = "import qualified Prelude as P\nP.id [()] ()";
+        assert_eq!(run(&mut loader, qualified), Ok("()".into()));
+        let hidden = run(&mut loader, "import qualified Prelude as P\nid");
+        assert!(hidden.unwrap_err().contains("Unbound term name: id"));
+        let missing = run(&mut loader, "import Prelude (nope)\n()");
+        assert!(missing.unwrap_err().contains("does not export 'nope'"));
+        let unknown = run(&mut loader, "import Nowhere\n()");
+        assert!(unknown.unwrap_err().contains("not on the search path"));
+    }
+
+    #[test]
+    fn prelude() {
+        let list = "(cons [()] () (cons [()] () (nil [()])))";
+        let cases = [
+            ("id [()] ()", "()"),
+            ("const [()] [((),)] () ((),)", "()"),
+            (
+                "flip [()] [((),)] [()] (x: () -> y: ((),) -> x) ((),) ()",
+                "()",
+            ),
+            ("swap [()] [((),)] ((), ((),))", "(((),), ())"),
+            (
+                "compose [()] [()] [((),)] (x: () -> (x,)) (id [()]) ()",
+                "((),)",
+            ),
+            ("apply [()] [((),)] (x: () -> (x,)) ()", "((),)"),
+            ("pipe [()] [((),)] () (x: () -> (x,))", "((),)"),
+            ("pure [()] { () }", "()"),
+            ("if [((),)] true ({ ((),) } & { (((),),).0 })", "((),)"),
+            ("if [()] (not true) ({ ((),).0 } & { () })", "()"),
+            ("if [()] false ({ ((),).0 } & { () })", "()"),
+            (
+                "maybe [()] [((),)] ((),) (x: () -> (x,)) (just [()] ())",
+                "((),)",
+            ),
+            ("maybe [()] [()] () (id [()]) (nothing [()])", "()"),
+            (
+                "maybe [()] [()] () (id [()]) \
+                    (bind [()] [()] (just [()] ()) (x: () -> just [()] x))",
+                "()",
+            ),
+            ("foldr [()] [()] (x: () -> y: () -> y) () (nil [()])", "()"),
+            (
+                &format!(
+                    "foldr [((),)] [((),)] (x: ((),) -> y: ((),) -> x) ((),) \
+                        (map [()] [((),)] (x: () -> (x,)) {})",
+                    list
+                ),
+                "((),)",
+            ),
+            (&format!("for [()] {} (id [()])", list), "()"),
+            ("loop [()] { just [()] () }", "()"),
+            ("while { false }", "()"),
+        ];
+        for (term, value) in cases.iter() {
+            let mut loader = Loader::new(vec![], true);
+            assert_eq!(
+                run(&mut loader, term),
+                Ok(value.to_string()),
+                "{}",
+                term
+            );
+        }
+        let mut loader = Loader::new(vec![], false);
+        assert!(run(&mut loader, "id [()] ()").is_err());
+    }
+
+    #[test]
+    fn cycles() {
+        let dir =
+            env::temp_dir().join(format!("odlang-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("A.od"), "module A\nimport B\nlet a = ()").unwrap();
+        fs::write(dir.join("B.od"), "module B\nimport A\nlet b = ()").unwrap();
+        let mut loader = Loader::new(vec![dir.clone()], false);
+        let result = run(&mut loader, "import A\na");
+        fs::remove_dir_all(dir).unwrap();
+        assert!(result.unwrap_err().contains("Import cycle: A -> B -> A"));
+    }
+}
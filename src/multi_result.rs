@@ -40,6 +40,7 @@ where
 {
     type Output = Self;
 
+    #[allow(clippy::suspicious_arithmetic_impl)]
     fn shl(self, rhs: MultiResult<T, C>) -> Self::Output {
         (self + rhs).map(|(lhs, _)| lhs)
     }
@@ -84,6 +85,19 @@ impl<R, C> MultiResult<R, C> {
     }
 }
 
+impl<R, C> MultiResult<R, C>
+where
+    C: Default + PartialEq,
+{
+    pub fn into_result(self) -> Result<R, C> {
+        if self.collect == C::default() {
+            Ok(self.result)
+        } else {
+            Err(self.collect)
+        }
+    }
+}
+
 impl<R, C> From<R> for MultiResult<R, C>
 where
     C: Default,
@@ -3,7 +3,7 @@ use std::ops::Index;
 #[derive(Default)]
 pub struct Names(Vec<String>);
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
 pub struct Var(usize);
 
 pub trait Named {
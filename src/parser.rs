@@ -1,18 +1,21 @@
 use std::{
     borrow::Cow,
     collections::VecDeque,
-    convert::{TryFrom, TryInto},
     error::Error,
     fmt::{Debug, Display},
     iter::Peekable,
+    ops::Add,
 };
 
-use itertools::Itertools;
-
 use crate::{input::*, prelude::*};
 
-pub fn parse(text: &str) -> Result<InputTerm, ParseErrors> {
-    parse_term(build_token_tree(tokenize(text)?)?).try_into()
+/// Parses a program, recovering from errors: tokens that do not form a term
+/// or type become `TmError` or `TyError` where they are, and the rest of the
+/// program is parsed as usual.
+pub fn parse(text: &str) -> MultiResult<InputTerm, ParseErrors> {
+    tokenize(text)
+        .then(build_token_tree)
+        .then(|tree| parse_term(tree).recover())
 }
 
 #[derive(Clone, Copy)]
@@ -45,13 +48,21 @@ pub enum TokenData<'a> {
     Colon,
     ThinArrow,
     FatArrow,
+    Equals,
+    Amp,
+    Bar,
+    Comma,
+    Dot,
     Semicolon,
+    /// An unknown token, which has been reported already.
+    Invalid(Indent),
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum ParenKind {
     Paren,
     Bracket,
+    Brace,
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -101,24 +112,40 @@ impl<'a> Display for TokenData<'a> {
                 CloseParen(Paren) => "')'",
                 OpenParen(Bracket, _, _) => "'['",
                 CloseParen(Bracket) => "']'",
+                OpenParen(Brace, _, _) => "'{'",
+                CloseParen(Brace) => "'}'",
                 Tifier(name, _) => name,
                 Colon => "':'",
                 ThinArrow => "'->'",
                 FatArrow => "'=>'",
+                Equals => "'='",
+                Amp => "'&'",
+                Bar => "'|'",
+                Comma => "','",
+                Dot => "'.'",
                 Semicolon => "';'",
+                Invalid(_) => "an invalid token",
             }
         )
     }
 }
 
-fn tokenize(text: &str) -> Result<Vec<Token>, ParseErrors> {
-    let (tokens, errors): (Vec<_>, VecDeque<_>) =
-        Tokenizer::from(text).partition_result();
-    if errors.is_empty() {
-        Ok(tokens)
-    } else {
-        Err(ParseErrors(errors))
+fn tokenize(text: &str) -> MultiResult<Vec<Token<'_>>, ParseErrors> {
+    let mut tokens = MultiResult::from(vec![]);
+    for token in Tokenizer::from(text) {
+        match token {
+            Ok(token) => tokens.result.push(token),
+            Err((err, indent)) => {
+                let range = err.range;
+                tokens.result.push(Tok {
+                    data: Invalid(indent),
+                    range,
+                });
+                tokens += err
+            }
+        }
     }
+    tokens
 }
 
 struct Tokenizer<'a> {
@@ -136,7 +163,7 @@ impl<'a> From<&'a str> for Tokenizer<'a> {
 }
 
 impl<'a> Iterator for Tokenizer<'a> {
-    type Item = Result<Token<'a>, ParseError>;
+    type Item = Result<Token<'a>, (ParseError, Indent)>;
 
     fn next(&mut self) -> Option<Self::Item> {
         let ws = self.take_while(|c| c == b' ' || c == b'\n').range.until;
@@ -147,9 +174,16 @@ impl<'a> Iterator for Tokenizer<'a> {
             (")", CloseParen(Paren)),
             ("[", OpenParen(Bracket, skip_ws, indent)),
             ("]", CloseParen(Bracket)),
+            ("{", OpenParen(Brace, skip_ws, indent)),
+            ("}", CloseParen(Brace)),
             (":", Colon),
             ("->", ThinArrow),
             ("=>", FatArrow),
+            ("=", Equals),
+            ("&", Amp),
+            ("|", Bar),
+            (",", Comma),
+            (".", Dot),
             (";", Semicolon),
         ];
         for (pref, data) in options {
@@ -158,13 +192,25 @@ impl<'a> Iterator for Tokenizer<'a> {
             }
         }
         self.stream.chars().next().map(|c| {
-            if c.is_ascii_alphabetic() || c == '_' {
+            if c == '?' {
+                let name = &self.stream[1..];
+                let len = name
+                    .bytes()
+                    .take_while(|c| c.is_ascii_alphanumeric() || *c == b'_')
+                    .count();
+                let hole = self.commit(len + 1);
+                if len == 0 {
+                    Err((error("Expected a hole name", hole.range), indent))
+                } else {
+                    Ok(hole.map(|name| Tifier(name, indent)))
+                }
+            } else if c.is_ascii_alphanumeric() || c == '_' {
                 Ok(self
                     .take_while(|c| c.is_ascii_alphanumeric() || c == b'_')
                     .map(|name| Tifier(name, indent)))
             } else {
                 let range = self.commit(c.len_utf8()).range;
-                Err(error("Unknown token", range))
+                Err((error("Unknown token", range), indent))
             }
         })
     }
@@ -194,29 +240,54 @@ struct Operator<'a> {
 
 impl<'a> From<TokenData<'a>> for Operator<'a> {
     fn from(at: TokenData<'a>) -> Self {
-        let repr = match at {
-            OpenParen(_, _, _) | Tifier(_, _) => false,
-            _ => true,
-        };
+        let repr =
+            !matches!(at, OpenParen(_, _, _) | Tifier(_, _) | Invalid(_));
         Self { at, repr }
     }
 }
 
 impl Operator<'_> {
     fn powers(self) -> (Power, Power) {
-        todo!()
+        match self.at {
+            OpenParen(_, NoSkipWS, _) => (Power::NoSpace, Power::NoSpace),
+            OpenParen(_, DoSkipWS, _) | Tifier(_, _) | Invalid(_) => {
+                (Power::Space, Power::Space)
+            }
+            Dot => (Power::NoSpace, Power::NoSpace),
+            Colon => (Power::Colon, Power::Colon),
+            Amp => (Power::With, Power::With),
+            ThinArrow | FatArrow => (Power::Begin, Power::Define),
+            Equals => (Power::Define, Power::End),
+            Bar => (Power::Bar, Power::Bar),
+            CloseParen(_) | Comma | Semicolon => (Power::End, Power::End),
+        }
     }
 }
 
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 enum Power {
     End,
+    Bar,
+    Define,
     Begin,
     Colon,
+    With,
     Space,
     NoSpace,
 }
 
+const KEYWORDS: [&str; 14] = [
+    "class", "instance", "data", "let", "rec", "fold", "unfold", "exists",
+    "pack", "unpack", "inj", "case", "import", "module",
+];
+
+fn keyword_power(name: &str) -> Power {
+    match name {
+        "fold" | "unfold" | "inj" => Power::Space,
+        _ => Power::Define,
+    }
+}
+
 struct TokenTreeRec<'a, T> {
     operator: Operator<'a>,
     operands: Vec<T>,
@@ -235,41 +306,170 @@ impl<'a, T> TokenTreeRec<'a, T> {
 
 struct PreTokenTree<'a>(Result<TokenTreeRec<'a, PreTokenTree<'a>>, ParseError>);
 
-struct TokenTree<'a>(TokenTreeRec<'a, TokenTree<'a>>);
-
-impl<'a> TryFrom<PreTokenTree<'a>> for TokenTree<'a> {
-    type Error = ParseErrors;
-
-    fn try_from(tree: PreTokenTree<'a>) -> Result<Self, Self::Error> {
-        let TokenTreeRec {
+impl<'a> PreTokenTree<'a> {
+    fn node(operator: Operator<'a>, operands: Vec<Self>) -> Self {
+        let range = operands[0].range() + operands.last().unwrap().range();
+        Self(Ok(TokenTreeRec {
             operator,
             operands,
             range,
-        } = tree.0?;
-        let (operands, errors): (Vec<_>, Vec<_>) = operands
-            .into_iter()
-            .map(TokenTree::try_from)
-            .partition_result();
-        if errors.is_empty() {
-            Ok(TokenTree(TokenTreeRec {
+        }))
+    }
+
+    fn end_of_input(after: Range) -> Self {
+        Self(Err(error("Unexpected end of input", after.to())))
+    }
+
+    fn range(&self) -> Range {
+        match &self.0 {
+            Ok(tree) => tree.range,
+            Err(err) => err.range,
+        }
+    }
+}
+
+struct TokenTree<'a>(TokenTreeRec<'a, TokenTree<'a>>);
+
+/// Subtrees that failed to build become invalid atoms, so that the rest of
+/// the tree is still parsed.
+impl<'a> Recover for PreTokenTree<'a> {
+    type Output = TokenTree<'a>;
+
+    fn recover(self) -> MultiResult<TokenTree<'a>, ParseErrors> {
+        match self.0 {
+            Ok(TokenTreeRec {
                 operator,
                 operands,
                 range,
-            }))
+            }) => convert(operands).map(|operands| {
+                TokenTree(TokenTreeRec {
+                    operator,
+                    operands,
+                    range,
+                })
+            }),
+            Err(err) => {
+                let invalid = TokenTreeRec::atom(Invalid(NoIndent), err.range);
+                MultiResult::new(TokenTree(invalid), err)
+            }
+        }
+    }
+}
+
+impl<'a> TokenTree<'a> {
+    fn node(operator: Operator<'a>, operands: Vec<Self>) -> Self {
+        let range = operands[0].0.range + operands.last().unwrap().0.range;
+        Self(TokenTreeRec {
+            operator,
+            operands,
+            range,
+        })
+    }
+
+    fn name(&self) -> Option<&'a str> {
+        match (self.0.operator.at, self.0.operands.len()) {
+            (Tifier(name, _), 0)
+                if !KEYWORDS.contains(&name) && !name.starts_with('?') =>
+            {
+                Some(name)
+            }
+            _ => None,
+        }
+    }
+
+    fn hole(&self) -> Option<&'a str> {
+        match (self.0.operator.at, self.0.operands.len()) {
+            (Tifier(name, _), 0) => name.strip_prefix('?'),
+            _ => None,
+        }
+    }
+
+    fn is_group(&self, kind: ParenKind) -> bool {
+        matches!(self.0.operator.at, OpenParen(k, _, _) if k == kind)
+            && self.0.operands.len() < 2
+    }
+
+    fn is_invalid(&self) -> bool {
+        matches!(self.0.operator.at, Invalid(_))
+    }
+
+    fn is_missing(&self) -> bool {
+        self.0.operator.at == Amp && self.0.operands.is_empty()
+    }
+
+    fn is_application(&self) -> bool {
+        !self.0.operator.repr && self.0.operands.len() == 2
+    }
+
+    fn keyword(&self) -> Option<&'a str> {
+        match (self.0.operator.at, self.0.operands.len()) {
+            (Tifier(name, _), 1) => Some(name),
+            _ => None,
+        }
+    }
+
+    fn split(self) -> (Self, Self) {
+        let mut operands = self.0.operands.into_iter();
+        let lhs = operands.next().unwrap();
+        (lhs, operands.next().unwrap())
+    }
+
+    fn elements(self) -> Result<Vec<Self>, Self> {
+        if self.0.operands.is_empty() {
+            return Ok(vec![]);
+        }
+        let inner = self.inner();
+        match inner.0.operator.at {
+            Comma => Ok(inner.0.operands),
+            _ => Err(inner),
+        }
+    }
+
+    fn inner(self) -> Self {
+        self.0.operands.into_iter().next().unwrap()
+    }
+
+    fn items(self) -> Vec<Self> {
+        match self.0.operator.at {
+            Semicolon => self.0.operands,
+            OpenParen(Paren, _, _) if self.0.operands.len() == 1 => {
+                self.inner().items()
+            }
+            _ => vec![self],
+        }
+    }
+
+    fn spine(self) -> Vec<Self> {
+        if self.is_application() {
+            let (f, x) = self.split();
+            let mut spine = f.spine();
+            spine.push(x);
+            spine
         } else {
-            Err(errors.into_iter().fold1(Semigroup::app).unwrap())
+            vec![self]
         }
     }
 }
 
-fn build_token_tree(tokens: Vec<Token>) -> Result<TokenTree, ParseErrors> {
+fn build_token_tree(tokens: Vec<Token>) -> MultiResult<TokenTree, ParseErrors> {
     let mut builder = TreeBuilder::from(tokens);
-    builder
-        .pratt(0, Power::End)
-        .map(TokenTree::try_from)
-        .unwrap_or(Err(error("empty program", Range::default()).into()))
-        .pair(builder.eof().map_err(Into::into))
-        .map(|(tree, _)| tree)
+    let tree = builder.block(0).unwrap_or_else(|| {
+        PreTokenTree(Err(error("empty program", Range::default())))
+    });
+    let mut tree = tree.recover();
+    if let Err(err) = builder.eof() {
+        tree += err;
+    }
+    tree
+}
+
+impl<'a> Token<'a> {
+    fn on_new_line(&self) -> bool {
+        matches!(
+            self.data,
+            Tifier(_, DoIndent) | OpenParen(_, _, DoIndent) | Invalid(DoIndent)
+        )
+    }
 }
 
 struct TreeBuilder<'a, I: Iterator<Item = Token<'a>>> {
@@ -294,27 +494,162 @@ impl<'a, I> TreeBuilder<'a, I>
 where
     I: Iterator<Item = Token<'a>>,
 {
-    fn eof(&self) -> Result<(), ParseError> {
-        match self.stream.peek() {
-            Some(token) => Err(error("Redundant tokens", token.range.from)),
+    fn eof(&mut self) -> Result<(), ParseError> {
+        match self.stream.next() {
+            Some(token) => {
+                let range = self.stream.by_ref().map(|token| token.range);
+                Err(error(
+                    "Redundant tokens",
+                    range.fold(token.range, Add::add),
+                ))
+            }
             None => Ok(()),
         }
     }
 
+    fn block(&mut self, indent: usize) -> TreeResult<'a> {
+        let mut items = vec![self.pratt(indent, Power::End)?];
+        while let Some(&token) = self.stream.peek() {
+            if token.data == Semicolon {
+                self.stream.next();
+                items.push(self.operand(indent, Power::End, token.range));
+            } else if token.on_new_line() && token.indent() == indent {
+                items.push(self.operand(indent, Power::End, token.range));
+            } else {
+                break;
+            }
+        }
+        Some(if items.len() == 1 {
+            items.pop().unwrap()
+        } else {
+            PreTokenTree::node(Semicolon.into(), items)
+        })
+    }
+
     fn pratt(&mut self, indent: usize, min_bp: Power) -> TreeResult<'a> {
-        let mut lhs = self.word()?;
+        let mut lhs = self.word(indent)?;
         while let Some(&token) = self.stream.peek() {
+            if token.on_new_line() && token.indent() <= indent {
+                break;
+            }
             let operator = Operator::from(token.data);
             let (l_bp, r_bp) = operator.powers();
-            if l_bp < min_bp {
+            if l_bp <= min_bp {
                 break;
             }
+            let rhs = if operator.repr {
+                self.stream.next();
+                match self.stream.peek() {
+                    Some(&next)
+                        if next.on_new_line() && next.indent() > indent =>
+                    {
+                        self.sequence(next.indent(), token.range)
+                    }
+                    Some(&next)
+                        if token.data == Amp
+                            && matches!(next.data, CloseParen(_) | Comma) =>
+                    {
+                        PreTokenTree(Ok(TokenTreeRec::atom(Amp, token.range)))
+                    }
+                    _ => self.operand(indent, r_bp, token.range),
+                }
+            } else {
+                self.operand(indent, r_bp, token.range)
+            };
+            lhs = PreTokenTree::node(operator, vec![lhs, rhs]);
         }
         Some(lhs)
     }
 
-    fn word(&mut self) -> TreeResult<'a> {
-        todo!()
+    fn operand(
+        &mut self,
+        indent: usize,
+        min_bp: Power,
+        after: Range,
+    ) -> PreTokenTree<'a> {
+        self.pratt(indent, min_bp)
+            .unwrap_or_else(|| PreTokenTree::end_of_input(after))
+    }
+
+    fn sequence(&mut self, indent: usize, after: Range) -> PreTokenTree<'a> {
+        self.block(indent)
+            .unwrap_or_else(|| PreTokenTree::end_of_input(after))
+    }
+
+    fn word(&mut self, indent: usize) -> TreeResult<'a> {
+        let token = self.stream.next()?;
+        Some(match token.data {
+            Tifier(name, _) if KEYWORDS.contains(&name) => {
+                let power = keyword_power(name);
+                let operand = self.operand(indent, power, token.range);
+                let range = token.range + operand.range();
+                PreTokenTree(Ok(TokenTreeRec {
+                    operator: token.data.into(),
+                    operands: vec![operand],
+                    range,
+                }))
+            }
+            Tifier(_, _) | Invalid(_) => {
+                PreTokenTree(Ok(TokenTreeRec::atom(token.data, token.range)))
+            }
+            OpenParen(kind, _, _) => self.group(indent, token, kind),
+            Amp => {
+                let operand = self.operand(indent, Power::With, token.range);
+                let missing =
+                    PreTokenTree(Ok(TokenTreeRec::atom(Amp, token.range)));
+                PreTokenTree::node(Amp.into(), vec![missing, operand])
+            }
+            data => PreTokenTree(Err(error(
+                format!("Unexpected {}", data),
+                token.range,
+            ))),
+        })
+    }
+
+    fn group(
+        &mut self,
+        indent: usize,
+        open: Token<'a>,
+        kind: ParenKind,
+    ) -> PreTokenTree<'a> {
+        let mut elements = vec![];
+        let mut tuple = false;
+        while let Some(&next) = self.stream.peek() {
+            if next.data == CloseParen(kind) {
+                break;
+            }
+            elements.push(if next.on_new_line() {
+                self.sequence(next.indent(), open.range)
+            } else {
+                self.sequence(indent, open.range)
+            });
+            match self.stream.peek() {
+                Some(comma) if comma.data == Comma => {
+                    self.stream.next();
+                    tuple = true;
+                }
+                _ => break,
+            }
+        }
+        let operands = if tuple {
+            vec![PreTokenTree::node(Comma.into(), elements)]
+        } else {
+            elements
+        };
+        match self.stream.peek() {
+            Some(&close) if close.data == CloseParen(kind) => {
+                self.stream.next();
+                PreTokenTree(Ok(TokenTreeRec {
+                    operator: open.data.into(),
+                    operands,
+                    range: open.range + close.range,
+                }))
+            }
+            _ => PreTokenTree(Err(error(
+                format!("Unclosed {}", open.data),
+                open.range,
+            ))),
+        }
     }
 }
 
@@ -322,29 +657,131 @@ struct PreInputTerm(
     Result<(InputTermRec<Box<PreInputTerm>, PreInputType>, Range), ParseError>,
 );
 
-impl TryFrom<PreInputTerm> for InputTerm {
-    type Error = ParseErrors;
+trait Recover {
+    type Output;
+
+    fn recover(self) -> MultiResult<Self::Output, ParseErrors>;
+}
+
+impl<T: Recover> Recover for Box<T> {
+    type Output = Box<T::Output>;
+
+    fn recover(self) -> MultiResult<Self::Output, ParseErrors> {
+        (*self).recover().map(Box::new)
+    }
+}
+
+impl Recover for PreInputTerm {
+    type Output = InputTerm;
 
-    fn try_from(value: PreInputTerm) -> Result<Self, Self::Error> {
-        let (tree, range) = value.0?;
+    fn recover(self) -> MultiResult<InputTerm, ParseErrors> {
+        let (tree, range) = match self.0 {
+            Ok(tree) => tree,
+            Err(err) => {
+                let range = err.range;
+                return MultiResult::new(InputTerm(TmError, range), err);
+            }
+        };
         let rec = match tree {
-            TmUnit => TmUnit,
-            TmVar(var) => TmVar(var),
-            TmAbs(var, ty, body) => {
-                let (ty, body) = ty.try_into().pair((*body).try_into())?;
-                TmAbs(var, ty, Box::new(body))
+            TmTuple(items) => convert(items).map(TmTuple),
+            TmProj(x, i) => x.recover().map(|x| TmProj(x, i)),
+            TmWith(l, r) => {
+                (l.recover() + r.recover()).map(|(l, r)| TmWith(l, r))
             }
+            TmRecord(fields) => convert_fields(fields).map(TmRecord),
+            TmField(x, l) => x.recover().map(|x| TmField(x, l)),
+            TmUpdate(x, fields) => (x.recover() + convert_fields(fields))
+                .map(|(x, fields)| TmUpdate(x, fields)),
+            TmInj(ty, l, x) => {
+                (ty.recover() + x.recover()).map(|(ty, x)| TmInj(ty, l, x))
+            }
+            TmCase(x, arms) => {
+                let arms = arms
+                    .into_iter()
+                    .map(|(label, pat, arm)| ((label, pat), arm))
+                    .collect();
+                (x.recover() + convert_fields(arms)).map(|(x, arms)| {
+                    let arms = arms
+                        .into_iter()
+                        .map(|((label, pat), arm)| (label, pat, arm))
+                        .collect();
+                    TmCase(x, arms)
+                })
+            }
+            TmHole(name) => TmHole(name).into(),
+            TmVar(var) => TmVar(var).into(),
+            TmAbs(pat, ty, body) => (ty.recover() + body.recover())
+                .map(|(ty, body)| TmAbs(pat, ty, body)),
             TmApp(f, x) => {
-                let (f, x) = (*f).try_into().pair((*x).try_into())?;
-                TmApp(Box::new(f), Box::new(x))
+                (f.recover() + x.recover()).map(|(f, x)| TmApp(f, x))
+            }
+            TmTyAbs(var, x) => x.recover().map(|x| TmTyAbs(var, x)),
+            TmThunk(x) => x.recover().map(TmThunk),
+            TmLet(pat, ty, x, y) => (ty.recover() + x.recover() + y.recover())
+                .map(|((ty, x), y)| TmLet(pat, ty, x, y)),
+            TmSeq(x, y) => {
+                (x.recover() + y.recover()).map(|(x, y)| TmSeq(x, y))
             }
-            TmTyAbs(var, x) => TmTyAbs(var, Box::new((*x).try_into()?)),
+            TmLetRec(name, ty, x, y) => {
+                (ty.recover() + x.recover() + y.recover())
+                    .map(|((ty, x), y)| TmLetRec(name, ty, x, y))
+            }
+            TmFold(ty, x) => {
+                (ty.recover() + x.recover()).map(|(ty, x)| TmFold(ty, x))
+            }
+            TmUnfold(x) => x.recover().map(TmUnfold),
+            TmPack(t, x, u) => (t.recover() + x.recover() + u.recover())
+                .map(|((t, x), u)| TmPack(t, x, u)),
+            TmUnpack(param, pat, x, y) => (x.recover() + y.recover())
+                .map(|(x, y)| TmUnpack(param, pat, x, y)),
             TmTyApp(f, ty) => {
-                let (f, ty) = (*f).try_into().pair(ty.try_into())?;
-                TmTyApp(Box::new(f), ty)
+                (f.recover() + ty.recover()).map(|(f, ty)| TmTyApp(f, ty))
+            }
+            TmQualAbs(class, ty, x) => (ty.recover() + x.recover())
+                .map(|(ty, x)| TmQualAbs(class, ty, x)),
+            TmClass(name, param, methods, body) => (convert_fields(methods)
+                + body.recover())
+            .map(|(methods, body)| TmClass(name, param, methods, body)),
+            TmData(name, params, variants, body) => (convert_fields(variants)
+                + body.recover())
+            .map(|(variants, body)| TmData(name, params, variants, body)),
+            TmInstance(instance, body) => {
+                let InputInstance {
+                    params,
+                    context,
+                    class,
+                    head,
+                    methods,
+                } = instance;
+                (convert_fields(context)
+                    + head.recover()
+                    + convert_fields(methods)
+                    + body.recover())
+                .map(|(((context, head), methods), body)| {
+                    let instance = InputInstance {
+                        params,
+                        context,
+                        class,
+                        head,
+                        methods,
+                    };
+                    TmInstance(instance, body)
+                })
+            }
+            TmImport(import, module, body) => {
+                let module = match module {
+                    Some(module) => module.recover().map(Some),
+                    None => None.into(),
+                };
+                (module + body.recover())
+                    .map(|(module, body)| TmImport(import, module, body))
+            }
+            TmModule(name, exports, body) => {
+                body.recover().map(|body| TmModule(name, exports, body))
             }
+            TmError => TmError.into(),
         };
-        Ok(InputTerm(rec, range))
+        rec.map(|rec| InputTerm(rec, range))
     }
 }
 
@@ -352,30 +789,906 @@ struct PreInputType(
     Result<(InputTypeRec<Box<PreInputType>>, Range), ParseError>,
 );
 
-impl TryFrom<PreInputType> for InputType {
-    type Error = ParseErrors;
+impl Recover for PreInputType {
+    type Output = InputType;
 
-    fn try_from(value: PreInputType) -> Result<Self, Self::Error> {
-        let (tree, range) = value.0?;
+    fn recover(self) -> MultiResult<InputType, ParseErrors> {
+        let (tree, range) = match self.0 {
+            Ok(tree) => tree,
+            Err(err) => {
+                let range = err.range;
+                return MultiResult::new(InputType(TyError, range), err);
+            }
+        };
         let rec = match tree {
-            TyUnit => TyUnit,
-            TyHole => TyHole,
-            TyVar(var) => TyVar(var),
-            TyArrow(from, to) => {
-                let (from, to) = (*from).try_into().pair((*to).try_into())?;
-                TyArrow(Box::new(from), Box::new(to))
-            }
-            TyForall(var, ty) => TyForall(var, Box::new((*ty).try_into()?)),
+            TyTuple(items) => convert(items).map(TyTuple),
+            TyWith(l, r) => {
+                (l.recover() + r.recover()).map(|(l, r)| TyWith(l, r))
+            }
+            TyRecord(fields, tail) => (convert_fields(fields)
+                + recover_tail(tail))
+            .map(|(fields, tail)| TyRecord(fields, tail)),
+            TyVariant(fields, tail) => (convert_fields(fields)
+                + recover_tail(tail))
+            .map(|(fields, tail)| TyVariant(fields, tail)),
+            TyHole => TyHole.into(),
+            TyVar(var) => TyVar(var).into(),
+            TyApp(name, args) => convert(args).map(|args| TyApp(name, args)),
+            TyArrow(from, to) => (from.recover() + to.recover())
+                .map(|(from, to)| TyArrow(from, to)),
+            TyForall(var, ty) => ty.recover().map(|ty| TyForall(var, ty)),
+            TyRec(var, ty) => ty.recover().map(|ty| TyRec(var, ty)),
+            TyExists(var, ty) => ty.recover().map(|ty| TyExists(var, ty)),
+            TyQual(class, arg, ty) => (arg.recover() + ty.recover())
+                .map(|(arg, ty)| TyQual(class, arg, ty)),
+            TyError => TyError.into(),
         };
-        Ok(InputType(rec, range))
+        rec.map(|rec| InputType(rec, range))
+    }
+}
+
+fn recover_tail(
+    tail: Option<Box<PreInputType>>,
+) -> MultiResult<Option<Box<InputType>>, ParseErrors> {
+    match tail {
+        Some(tail) => tail.recover().map(Some),
+        None => None.into(),
+    }
+}
+
+fn convert<T: Recover>(
+    items: Vec<T>,
+) -> MultiResult<Vec<T::Output>, ParseErrors> {
+    items.into_iter().fold(Vec::new().into(), |items, item| {
+        (items + item.recover()).map(|(mut items, item)| {
+            items.push(item);
+            items
+        })
+    })
+}
+
+fn convert_fields<K, T: Recover>(
+    items: Vec<(K, T)>,
+) -> MultiResult<Vec<(K, T::Output)>, ParseErrors> {
+    let (keys, items): (Vec<_>, Vec<_>) = items.into_iter().unzip();
+    convert(items).map(|items| keys.into_iter().zip(items).collect())
+}
+
+impl PreInputTerm {
+    fn new(
+        rec: InputTermRec<Box<PreInputTerm>, PreInputType>,
+        range: Range,
+    ) -> Self {
+        Self(Ok((rec, range)))
+    }
+
+    fn fail(reason: impl Into<Cow<'static, str>>, range: Range) -> Self {
+        Self(Err(error(reason, range)))
+    }
+}
+
+impl PreInputType {
+    fn new(rec: InputTypeRec<Box<PreInputType>>, range: Range) -> Self {
+        Self(Ok((rec, range)))
+    }
+
+    fn fail(reason: impl Into<Cow<'static, str>>, range: Range) -> Self {
+        Self(Err(error(reason, range)))
+    }
+
+    fn is_hole(&self) -> bool {
+        matches!(self.0, Ok((TyHole, _)))
     }
 }
 
 fn parse_term(tree: TokenTree) -> PreInputTerm {
-    todo!()
+    let range = tree.0.range;
+    if tree.is_invalid() {
+        return PreInputTerm::new(TmError, range);
+    }
+    if let Some(name) = tree.hole() {
+        return PreInputTerm::new(TmHole(Some(name.into())), range);
+    }
+    match tree.name() {
+        Some("_") => return PreInputTerm::new(TmHole(None), range),
+        Some(name) => return PreInputTerm::new(TmVar(name.into()), range),
+        None => {}
+    }
+    if tree.is_group(Paren) {
+        return match tree.elements() {
+            Ok(items) => {
+                let items = items.into_iter().map(parse_term).map(Box::new);
+                PreInputTerm::new(TmTuple(items.collect()), range)
+            }
+            Err(inner) => parse_term(inner),
+        };
+    }
+    if tree.is_group(Brace) {
+        let body = match tree.elements() {
+            Ok(items) if items.is_empty() => {
+                PreInputTerm::new(TmTuple(vec![]), range)
+            }
+            Ok(items) => return parse_record(items, range),
+            Err(inner) if matches!(inner.0.operator.at, Equals | Bar) => {
+                return parse_record(vec![inner], range)
+            }
+            Err(inner) => parse_term(inner),
+        };
+        return PreInputTerm::new(TmThunk(Box::new(body)), range);
+    }
+    if tree.is_application() {
+        let (f, x) = tree.split();
+        if f.is_application() && f.0.operands[0].keyword() == Some("inj") {
+            let (inj, label) = f.split();
+            let ty = parse_bracket(inj.inner());
+            return match label.name() {
+                Some(label) => {
+                    let term = TmInj(ty, label.into(), Box::new(parse_term(x)));
+                    PreInputTerm::new(term, range)
+                }
+                None => PreInputTerm::fail("Expected a label", label.0.range),
+            };
+        }
+        if f.keyword() == Some("fold") {
+            let ty = parse_bracket(f.inner());
+            return PreInputTerm::new(
+                TmFold(ty, Box::new(parse_term(x))),
+                range,
+            );
+        }
+        let f = Box::new(parse_term(f));
+        return if x.is_group(Bracket) {
+            PreInputTerm::new(TmTyApp(f, parse_bracket(x)), range)
+        } else {
+            PreInputTerm::new(TmApp(f, Box::new(parse_term(x))), range)
+        };
+    }
+    match tree.keyword() {
+        Some("unfold") => {
+            let term = Box::new(parse_term(tree.inner()));
+            return PreInputTerm::new(TmUnfold(term), range);
+        }
+        Some("fold") => {
+            return PreInputTerm::fail("Expected a term to fold", range);
+        }
+        Some("pack") => return parse_pack(tree.inner(), range),
+        Some("case") => return parse_case(tree.inner(), range),
+        Some("inj") => {
+            return PreInputTerm::fail("Expected a label and a term", range);
+        }
+        Some("module") => return parse_module(tree, vec![], range),
+        Some("import") => {
+            return PreInputTerm::fail("Expected a term after import", range);
+        }
+        _ => {}
+    }
+    match tree.0.operator.at {
+        ThinArrow => {
+            let (param, body) = tree.split();
+            let body = Box::new(parse_term(body));
+            match parse_annotated_pattern(param) {
+                Ok((pat, ty)) => PreInputTerm::new(TmAbs(pat, ty, body), range),
+                Err(err) => PreInputTerm(Err(err)),
+            }
+        }
+        Dot => {
+            let (tuple, index) = tree.split();
+            let tuple = Box::new(parse_term(tuple));
+            match index.name() {
+                Some(index) => match index.parse() {
+                    Ok(index) => PreInputTerm::new(TmProj(tuple, index), range),
+                    Err(_) => {
+                        PreInputTerm::new(TmField(tuple, index.into()), range)
+                    }
+                },
+                None => PreInputTerm::fail("Expected a field", index.0.range),
+            }
+        }
+        Amp => match tree.split() {
+            (l, r) if !l.is_missing() && !r.is_missing() => {
+                let (l, r) = (Box::new(parse_term(l)), Box::new(parse_term(r)));
+                PreInputTerm::new(TmWith(l, r), range)
+            }
+            _ => PreInputTerm::fail("Expected both alternatives", range),
+        },
+        FatArrow => {
+            let (param, body) = tree.split();
+            let body = Box::new(parse_term(body));
+            if let Some(name) = param.name() {
+                let param = Binder(name.into(), param.0.range);
+                return PreInputTerm::new(TmTyAbs(param, body), range);
+            }
+            match parse_constraint(param) {
+                Ok((class, ty)) => {
+                    PreInputTerm::new(TmQualAbs(class, ty, body), range)
+                }
+                Err(err) => PreInputTerm(Err(err)),
+            }
+        }
+        Semicolon => {
+            let mut items = tree.0.operands;
+            if items[0].keyword() == Some("module") {
+                let header = items.remove(0);
+                return parse_module(header, items, range);
+            }
+            let body = parse_term(items.pop().unwrap());
+            items.into_iter().rev().fold(body, parse_declaration)
+        }
+        // A declaration whose value swallowed the rest of the input has
+        // already been reported.
+        Equals if tree.0.operands.last().is_some_and(TokenTree::is_invalid) => {
+            PreInputTerm::new(TmError, range)
+        }
+        Equals => {
+            PreInputTerm::fail("Expected a term after declaration", range)
+        }
+        Colon => PreInputTerm::fail("Unexpected type annotation", range),
+        Tifier(keyword, _) => {
+            PreInputTerm::fail(format!("Expected '=' after {}", keyword), range)
+        }
+        _ => PreInputTerm::fail("Expected a term", range),
+    }
+}
+
+fn parse_constraint(
+    tree: TokenTree,
+) -> Result<(String, PreInputType), ParseError> {
+    let range = tree.0.range;
+    let mut spine = tree.spine();
+    if spine.len() != 2 {
+        return Err(error("Expected a type parameter or a constraint", range));
+    }
+    let arg = spine.pop().unwrap();
+    match spine[0].name() {
+        Some(class) => Ok((class.into(), parse_type(arg))),
+        None => Err(error("Expected a class name", spine[0].0.range)),
+    }
+}
+
+fn parse_declaration(body: PreInputTerm, tree: TokenTree) -> PreInputTerm {
+    let range = tree.0.range;
+    let range = match &body.0 {
+        Ok((_, body)) => range + *body,
+        Err(err) => range + err.range,
+    };
+    if tree.0.operator.at != Equals {
+        match tree.keyword() {
+            Some("import") => {
+                return match parse_import(tree.inner()) {
+                    Ok(import) => PreInputTerm::new(
+                        TmImport(import, None, Box::new(body)),
+                        range,
+                    ),
+                    Err(err) => PreInputTerm(Err(err)),
+                }
+            }
+            Some("module") => {
+                return PreInputTerm::fail(
+                    "Module header must come first",
+                    tree.0.range,
+                )
+            }
+            _ => {}
+        }
+        let statement = Box::new(parse_term(tree));
+        return PreInputTerm::new(TmSeq(statement, Box::new(body)), range);
+    }
+    let (header, definition) = tree.split();
+    let body = Box::new(body);
+    match header.keyword() {
+        Some("class") => match parse_class(header.inner(), definition) {
+            Ok((name, param, methods)) => {
+                PreInputTerm::new(TmClass(name, param, methods, body), range)
+            }
+            Err(err) => PreInputTerm(Err(err)),
+        },
+        Some("data") => match parse_data(header.inner(), definition) {
+            Ok((name, params, variants)) => {
+                let term = TmData(name, params, variants, body);
+                PreInputTerm::new(term, range)
+            }
+            Err(err) => PreInputTerm(Err(err)),
+        },
+        Some("let") if header.0.operands[0].keyword() == Some("rec") => {
+            match split_annotation(header.inner().inner()) {
+                Ok((name, ty)) => match name.name() {
+                    Some(binder) => {
+                        let binder = Binder(binder.into(), name.0.range);
+                        let ty = parse_type(ty);
+                        let definition = Box::new(parse_term(definition));
+                        let term = TmLetRec(binder, ty, definition, body);
+                        PreInputTerm::new(term, range)
+                    }
+                    None => PreInputTerm::fail("Expected a name", name.0.range),
+                },
+                Err(tree) => PreInputTerm::fail(
+                    "Expected a type annotation",
+                    tree.0.range,
+                ),
+            }
+        }
+        Some("let") => match parse_annotated_pattern(header.inner()) {
+            Ok((pat, ty)) => {
+                let definition = Box::new(parse_term(definition));
+                PreInputTerm::new(TmLet(pat, ty, definition, body), range)
+            }
+            Err(err) => PreInputTerm(Err(err)),
+        },
+        Some("unpack") => match parse_unpack(header.inner()) {
+            Ok((param, pat)) => {
+                let definition = Box::new(parse_term(definition));
+                let term = TmUnpack(param, pat, definition, body);
+                PreInputTerm::new(term, range)
+            }
+            Err(err) => PreInputTerm(Err(err)),
+        },
+        Some("instance") => match parse_instance(header.inner(), definition) {
+            Ok(instance) => {
+                PreInputTerm::new(TmInstance(instance, body), range)
+            }
+            Err(err) => PreInputTerm(Err(err)),
+        },
+        _ => PreInputTerm::fail("Expected a declaration", range),
+    }
+}
+
+fn parse_pack(tree: TokenTree, range: Range) -> PreInputTerm {
+    let mut spine = tree.spine().into_iter();
+    match (spine.next(), spine.next(), spine.next(), spine.next()) {
+        (Some(pair), Some(r#as), Some(ty), None)
+            if pair.is_group(Bracket) && r#as.name() == Some("as") =>
+        {
+            match pair.elements() {
+                Ok(items) if items.len() == 2 => {
+                    let mut items = items.into_iter();
+                    let witness = parse_type(items.next().unwrap());
+                    let term = Box::new(parse_term(items.next().unwrap()));
+                    let term = TmPack(witness, term, parse_type(ty));
+                    PreInputTerm::new(term, range)
+                }
+                _ => PreInputTerm::fail("Expected a type and a term", range),
+            }
+        }
+        _ => PreInputTerm::fail("Expected 'pack [type, term] as type'", range),
+    }
+}
+
+fn parse_module(
+    header: TokenTree,
+    items: Vec<TokenTree>,
+    range: Range,
+) -> PreInputTerm {
+    let mut spine = header.inner().spine().into_iter();
+    let (name, exports) = match (spine.next(), spine.next(), spine.next()) {
+        (Some(name), exports, None) => (name, exports),
+        _ => return PreInputTerm::fail("Expected a module name", range),
+    };
+    let name = match name.name() {
+        Some(name) => name.to_owned(),
+        None => return PreInputTerm::fail("Expected a module name", range),
+    };
+    let exports = match exports.map(parse_names) {
+        Some(Ok(exports)) => exports,
+        Some(Err(err)) => return PreInputTerm(Err(err)),
+        None => items
+            .iter()
+            .filter_map(declared)
+            .map(String::from)
+            .collect(),
+    };
+    let fields = exports.iter().map(|name| {
+        let var = PreInputTerm::new(TmVar(name.clone()), range);
+        (name.clone(), Box::new(var))
+    });
+    let body = PreInputTerm::new(TmRecord(fields.collect()), range);
+    let body = items.into_iter().rev().fold(body, parse_declaration);
+    PreInputTerm::new(TmModule(name, exports, Box::new(body)), range)
+}
+
+fn declared<'a>(tree: &TokenTree<'a>) -> Option<&'a str> {
+    if tree.0.operator.at != Equals
+        || tree.0.operands[0].keyword() != Some("let")
+    {
+        return None;
+    }
+    let mut binder = &tree.0.operands[0].0.operands[0];
+    if binder.keyword() == Some("rec") {
+        binder = &binder.0.operands[0];
+    }
+    while matches!(binder.0.operator.at, ThinArrow | FatArrow | Colon) {
+        binder = &binder.0.operands[0];
+    }
+    binder.name()
+}
+
+fn parse_import(tree: TokenTree) -> Result<InputImport, ParseError> {
+    let range = tree.0.range;
+    let mut spine = tree.spine().into_iter();
+    let first = spine.next().unwrap();
+    let (qualified, module) = match first.name() {
+        Some("qualified") => (true, spine.next()),
+        _ => (false, Some(first)),
+    };
+    let module = module.as_ref().and_then(TokenTree::name);
+    let rest = spine.collect::<Vec<_>>();
+    let import = match (module, qualified, rest.as_slice()) {
+        (Some(module), false, []) => InputImport {
+            module: module.into(),
+            qualified: None,
+            names: None,
+        },
+        (Some(module), true, []) => InputImport {
+            module: module.into(),
+            qualified: Some(module.into()),
+            names: None,
+        },
+        (Some(module), true, [r#as, alias]) if r#as.name() == Some("as") => {
+            let alias = alias.name().ok_or_else(|| {
+                error("Expected a module alias", alias.0.range)
+            })?;
+            InputImport {
+                module: module.into(),
+                qualified: Some(alias.into()),
+                names: None,
+            }
+        }
+        (Some(module), false, [_]) => {
+            let names = parse_names(rest.into_iter().next().unwrap())?;
+            InputImport {
+                module: module.into(),
+                qualified: None,
+                names: Some(names),
+            }
+        }
+        _ => {
+            return Err(error(
+                "Expected 'import M', 'import M (names)' \
+                 or 'import qualified M as N'",
+                range,
+            ))
+        }
+    };
+    Ok(import)
+}
+
+fn parse_names(tree: TokenTree) -> Result<Vec<String>, ParseError> {
+    if !tree.is_group(Paren) {
+        return Err(error("Expected a list of names", tree.0.range));
+    }
+    let items = match tree.elements() {
+        Ok(items) => items,
+        Err(inner) => vec![inner],
+    };
+    items
+        .into_iter()
+        .map(|item| match item.name() {
+            Some(name) => Ok(name.to_owned()),
+            None => Err(error("Expected a name", item.0.range)),
+        })
+        .collect()
+}
+
+fn parse_record(items: Vec<TokenTree>, range: Range) -> PreInputTerm {
+    let mut items = items.into_iter();
+    let mut base = None;
+    let mut fields = vec![];
+    if let Some(first) = items.next() {
+        if first.0.operator.at == Bar {
+            let (record, field) = first.split();
+            base = Some(Box::new(parse_term(record)));
+            fields.push(field);
+        } else {
+            fields.push(first);
+        }
+    }
+    let fields = fields.into_iter().chain(items).map(|field| {
+        let field_range = field.0.range;
+        if field.0.operator.at == Equals {
+            let (label, value) = field.split();
+            if let Some(label) = label.name() {
+                return Ok((label.to_string(), Box::new(parse_term(value))));
+            }
+        }
+        Err(error("Expected 'field = term'", field_range))
+    });
+    match fields.collect::<Result<_, _>>() {
+        Ok(fields) => match base {
+            Some(base) => PreInputTerm::new(TmUpdate(base, fields), range),
+            None => PreInputTerm::new(TmRecord(fields), range),
+        },
+        Err(err) => PreInputTerm(Err(err)),
+    }
+}
+
+fn parse_case(tree: TokenTree, range: Range) -> PreInputTerm {
+    if !tree.is_application() {
+        return PreInputTerm::fail("Expected 'case term (arms)'", range);
+    }
+    let (scrutinee, arms) = tree.split();
+    if !arms.is_group(Paren) {
+        return PreInputTerm::fail("Expected case arms", arms.0.range);
+    }
+    let arms = arms.items().into_iter().map(|arm| {
+        let arm_range = arm.0.range;
+        if arm.0.operator.at != ThinArrow {
+            return Err(error("Expected 'Label pattern -> term'", arm_range));
+        }
+        let (lhs, body) = arm.split();
+        let (label, pattern) = match lhs.name() {
+            Some(label) => (label, PtWild),
+            None if lhs.is_application() => {
+                let (label, pattern) = lhs.split();
+                match label.name() {
+                    Some(label) => (label, parse_pattern(pattern)?),
+                    None => return Err(error("Expected a label", arm_range)),
+                }
+            }
+            None => return Err(error("Expected a label", arm_range)),
+        };
+        Ok((label.to_string(), pattern, Box::new(parse_term(body))))
+    });
+    match arms.collect::<Result<_, _>>() {
+        Ok(arms) => {
+            let scrutinee = Box::new(parse_term(scrutinee));
+            PreInputTerm::new(TmCase(scrutinee, arms), range)
+        }
+        Err(err) => PreInputTerm(Err(err)),
+    }
+}
+
+type PreRow = (Vec<(String, Box<PreInputType>)>, Option<Box<PreInputType>>);
+
+fn parse_row(items: Vec<TokenTree>) -> Result<PreRow, ParseError> {
+    let mut tail = None;
+    let count = items.len();
+    let mut fields = vec![];
+    for (i, item) in items.into_iter().enumerate() {
+        let item = if i + 1 == count && item.0.operator.at == Bar {
+            let (item, rest) = item.split();
+            tail = Some(Box::new(parse_type(rest)));
+            item
+        } else {
+            item
+        };
+        let item_range = item.0.range;
+        match split_annotation(item) {
+            Ok((label, ty)) => match label.name() {
+                Some(label) => {
+                    fields.push((label.to_string(), Box::new(parse_type(ty))))
+                }
+                None => return Err(error("Expected a label", item_range)),
+            },
+            Err(_) => return Err(error("Expected 'label : type'", item_range)),
+        }
+    }
+    Ok((fields, tail))
+}
+
+fn parse_unpack(tree: TokenTree) -> Result<(Binder, InputPattern), ParseError> {
+    let range = tree.0.range;
+    if tree.is_group(Bracket) {
+        if let Ok(items) = tree.elements() {
+            if let [param, _] = &items[..] {
+                if let Some(name) = param.name() {
+                    let param = Binder(name.into(), param.0.range);
+                    let pattern = items.into_iter().nth(1).unwrap();
+                    return Ok((param, parse_pattern(pattern)?));
+                }
+            }
+        }
+    }
+    Err(error("Expected '[type, pattern]'", range))
+}
+
+fn parse_annotated_pattern(
+    tree: TokenTree,
+) -> Result<(InputPattern, PreInputType), ParseError> {
+    let range = tree.0.range;
+    match split_annotation(tree) {
+        Ok((pat, ty)) => Ok((parse_pattern(pat)?, parse_type(ty))),
+        Err(tree) if tree.is_group(Paren) => match tree.elements() {
+            Ok(items) => {
+                let items = items.into_iter().map(parse_annotated_pattern);
+                let items = items.collect::<Result<Vec<_>, _>>()?;
+                let annotated = items.iter().any(|(_, ty)| !ty.is_hole());
+                let (items, types): (Vec<_>, Vec<_>) =
+                    items.into_iter().unzip();
+                let ty = if annotated {
+                    TyTuple(types.into_iter().map(Box::new).collect())
+                } else {
+                    TyHole
+                };
+                Ok((PtTuple(items), PreInputType::new(ty, range)))
+            }
+            Err(inner) => parse_annotated_pattern(inner),
+        },
+        Err(tree) => {
+            Ok((parse_pattern(tree)?, PreInputType::new(TyHole, range)))
+        }
+    }
 }
 
-#[derive(Default)]
+fn parse_pattern(tree: TokenTree) -> Result<InputPattern, ParseError> {
+    let range = tree.0.range;
+    if tree.is_invalid() {
+        return Ok(PtWild);
+    }
+    match tree.name() {
+        Some("_") => return Ok(PtWild),
+        Some(name) => return Ok(PtVar(Binder(name.into(), range))),
+        None => {}
+    }
+    if tree.is_group(Paren) {
+        return match tree.elements() {
+            Ok(items) => {
+                let items = items.into_iter().map(parse_pattern);
+                Ok(PtTuple(items.collect::<Result<_, _>>()?))
+            }
+            Err(inner) => parse_pattern(inner),
+        };
+    }
+    if tree.0.operator.at == Amp {
+        return match tree.split() {
+            (l, r) if r.is_missing() => Ok(PtLeft(Box::new(parse_pattern(l)?))),
+            (l, r) if l.is_missing() => {
+                Ok(PtRight(Box::new(parse_pattern(r)?)))
+            }
+            _ => Err(error("Expected a single alternative", range)),
+        };
+    }
+    Err(error("Expected a pattern", range))
+}
+
+type ClassDecl = (String, String, Vec<(String, PreInputType)>);
+
+fn parse_class(
+    header: TokenTree,
+    definition: TokenTree,
+) -> Result<ClassDecl, ParseError> {
+    let range = header.0.range;
+    let (name, param) = match header.spine().as_slice() {
+        [name, param] => match (name.name(), param.name()) {
+            (Some(name), Some(param)) => (name.into(), param.into()),
+            _ => {
+                return Err(error("Expected a class name and parameter", range))
+            }
+        },
+        _ => return Err(error("Expected a class name and parameter", range)),
+    };
+    let methods = definition
+        .items()
+        .into_iter()
+        .map(parse_signature)
+        .collect::<Result<_, _>>()?;
+    Ok((name, param, methods))
+}
+
+type DataDecl = (Binder, Vec<Binder>, Vec<(String, PreInputType)>);
+
+fn parse_data(
+    header: TokenTree,
+    definition: TokenTree,
+) -> Result<DataDecl, ParseError> {
+    let range = header.0.range;
+    let mut binders =
+        header.spine().into_iter().map(|tree| match tree.name() {
+            Some(name) => Ok(Binder(name.into(), tree.0.range)),
+            None => Err(error("Expected a type name and parameters", range)),
+        });
+    let name = binders.next().unwrap()?;
+    let params = binders.collect::<Result<_, _>>()?;
+    if !definition.is_group(Bracket) {
+        return Err(error("Expected a variant type", definition.0.range));
+    }
+    let items = match definition.elements() {
+        Ok(items) => items,
+        Err(inner) => vec![inner],
+    };
+    match parse_row(items)? {
+        (variants, None) => {
+            let variants = variants.into_iter().map(|(l, ty)| (l, *ty));
+            Ok((name, params, variants.collect()))
+        }
+        (_, Some(_)) => Err(error("Data types cannot be extended", range)),
+    }
+}
+
+fn parse_signature(
+    tree: TokenTree,
+) -> Result<(String, PreInputType), ParseError> {
+    match split_annotation(tree) {
+        Ok((name, ty)) => match name.name() {
+            Some(name) => Ok((name.into(), parse_type(ty))),
+            None => Err(error("Expected a method name", name.0.range)),
+        },
+        Err(tree) => Err(error("Expected a method signature", tree.0.range)),
+    }
+}
+
+fn split_annotation(
+    tree: TokenTree,
+) -> Result<(TokenTree, TokenTree), TokenTree> {
+    let operator = tree.0.operator;
+    match operator.at {
+        Colon => Ok(tree.split()),
+        ThinArrow | FatArrow => {
+            let (lhs, rhs) = tree.split();
+            match split_annotation(lhs) {
+                Ok((subject, lhs)) => {
+                    Ok((subject, TokenTree::node(operator, vec![lhs, rhs])))
+                }
+                Err(lhs) => Err(TokenTree::node(operator, vec![lhs, rhs])),
+            }
+        }
+        _ => Err(tree),
+    }
+}
+
+type PreInstance = InputInstance<Box<PreInputTerm>, PreInputType>;
+
+fn parse_instance(
+    header: TokenTree,
+    definition: TokenTree,
+) -> Result<PreInstance, ParseError> {
+    let mut params = vec![];
+    let mut context = vec![];
+    let mut header = header;
+    while header.0.operator.at == FatArrow {
+        let (lhs, rhs) = header.split();
+        match lhs.name() {
+            Some(param) => params.push(param.into()),
+            None => context.push(parse_constraint(lhs)?),
+        }
+        header = rhs;
+    }
+    let (class, head) = parse_constraint(header)?;
+    let methods = definition
+        .items()
+        .into_iter()
+        .map(|item| {
+            let range = item.0.range;
+            if item.0.operator.at != Equals {
+                return Err(error("Expected a method definition", range));
+            }
+            let (name, method) = item.split();
+            match name.name() {
+                Some(name) => Ok((name.into(), Box::new(parse_term(method)))),
+                None => Err(error("Expected a method name", name.0.range)),
+            }
+        })
+        .collect::<Result<_, _>>()?;
+    Ok(InputInstance {
+        params,
+        context,
+        class,
+        head,
+        methods,
+    })
+}
+
+fn parse_bracket(tree: TokenTree) -> PreInputType {
+    let range = tree.0.range;
+    match tree.0.operands.len() {
+        0 => PreInputType::fail("Expected a type", range),
+        _ => parse_type(tree.inner()),
+    }
+}
+
+fn parse_type(tree: TokenTree) -> PreInputType {
+    let range = tree.0.range;
+    if tree.is_invalid() {
+        return PreInputType::new(TyError, range);
+    }
+    match tree.name() {
+        Some("_") => return PreInputType::new(TyHole, range),
+        Some(name) => return PreInputType::new(TyVar(name.into()), range),
+        None => {}
+    }
+    if tree.is_group(Paren) {
+        return match tree.elements() {
+            Ok(items) => {
+                let items = items.into_iter().map(parse_type).map(Box::new);
+                PreInputType::new(TyTuple(items.collect()), range)
+            }
+            Err(inner) => parse_type(inner),
+        };
+    }
+    if tree.is_group(Bracket) {
+        let items = match tree.elements() {
+            Ok(items) => items,
+            Err(inner) => vec![inner],
+        };
+        return match parse_row(items) {
+            Ok((fields, tail)) => {
+                PreInputType::new(TyVariant(fields, tail), range)
+            }
+            Err(err) => PreInputType(Err(err)),
+        };
+    }
+    if tree.is_group(Brace) {
+        let items = match tree.elements() {
+            Ok(items) if items.is_empty() => Err(None),
+            Ok(items) => Ok(items),
+            Err(inner) if matches!(inner.0.operator.at, Colon | Bar) => {
+                Ok(vec![inner])
+            }
+            Err(inner) => Err(Some(inner)),
+        };
+        let result = match items {
+            Ok(items) => {
+                return match parse_row(items) {
+                    Ok((fields, tail)) => {
+                        PreInputType::new(TyRecord(fields, tail), range)
+                    }
+                    Err(err) => PreInputType(Err(err)),
+                }
+            }
+            Err(None) => PreInputType::new(TyTuple(vec![]), range),
+            Err(Some(inner)) => parse_type(inner),
+        };
+        let unit = Box::new(PreInputType::new(TyTuple(vec![]), range));
+        return PreInputType::new(TyArrow(unit, Box::new(result)), range);
+    }
+    if let Some(keyword @ ("rec" | "exists")) = tree.keyword() {
+        let inner = tree.inner();
+        if inner.0.operator.at == FatArrow {
+            let (param, ty) = inner.split();
+            if let Some(name) = param.name() {
+                let param = Binder(name.into(), param.0.range);
+                let ty = Box::new(parse_type(ty));
+                let ty = match keyword {
+                    "rec" => TyRec(param, ty),
+                    _ => TyExists(param, ty),
+                };
+                return PreInputType::new(ty, range);
+            }
+        }
+        return PreInputType::fail("Expected a binder", range);
+    }
+    if tree.is_application() {
+        let mut spine = tree.spine().into_iter();
+        let head = spine.next().unwrap();
+        return match head.name() {
+            Some(name) => {
+                let args = spine.map(parse_type).map(Box::new).collect();
+                PreInputType::new(TyApp(name.into(), args), range)
+            }
+            None => PreInputType::fail("Expected a type name", head.0.range),
+        };
+    }
+    match tree.0.operator.at {
+        Amp => {
+            let (l, r) = tree.split();
+            if l.is_missing() || r.is_missing() {
+                return PreInputType::fail("Expected both alternatives", range);
+            }
+            let (l, r) = (parse_type(l), parse_type(r));
+            PreInputType::new(TyWith(Box::new(l), Box::new(r)), range)
+        }
+        ThinArrow => {
+            let (from, to) = tree.split();
+            let (from, to) = (parse_type(from), parse_type(to));
+            PreInputType::new(TyArrow(Box::new(from), Box::new(to)), range)
+        }
+        FatArrow => {
+            let (param, ty) = tree.split();
+            let ty = Box::new(parse_type(ty));
+            if let Some(name) = param.name() {
+                let param = Binder(name.into(), param.0.range);
+                return PreInputType::new(TyForall(param, ty), range);
+            }
+            match parse_constraint(param) {
+                Ok((class, arg)) => {
+                    PreInputType::new(TyQual(class, Box::new(arg), ty), range)
+                }
+                Err(err) => PreInputType(Err(err)),
+            }
+        }
+        _ => PreInputType::fail("Expected a type", range),
+    }
+}
+
+#[derive(Default, PartialEq)]
 pub struct ParseErrors(VecDeque<ParseError>);
 
 impl Error for ParseErrors {}
@@ -423,7 +1736,7 @@ impl Extend<ParseError> for ParseErrors {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub struct ParseError {
     reason: Cow<'static, str>,
     range: Range,
@@ -452,8 +1765,121 @@ mod test {
     #[test]
     fn id() {
         let src = include_str!("../examples/id.od");
-        let tokens = tokenize(src).unwrap();
-        let tree = build_token_tree(tokens).unwrap();
-        let _ = InputTerm::try_from(parse_term(tree)).unwrap();
+        let tokens = tokenize(src).into_result().unwrap();
+        let tree = build_token_tree(tokens).into_result().unwrap();
+        let _ = parse_term(tree).recover().into_result().unwrap();
+    }
+
+    #[test]
+    fn layout() {
+        let src = include_str!("../examples/classes.od");
+        let InputTerm(term, _) = parse(src).into_result().unwrap();
+        assert!(
+            matches!(term, TmClass(_, _, ref methods, _) if methods.len() == 2)
+        );
+        assert!(parse("class Show a = show : a -> ()")
+            .into_result()
+            .is_err());
+    }
+
+    #[test]
+    fn unit_call() {
+        let InputTerm(term, _) = parse("g f()").into_result().unwrap();
+        assert!(matches!(term, TmApp(_, x) if matches!(x.0, TmApp(_, _))));
+    }
+
+    #[test]
+    fn thunk_block() {
+        let InputTerm(term, _) =
+            parse("run {\n    ()\n    ()\n}").into_result().unwrap();
+        assert!(matches!(term, TmApp(_, x) if matches!(
+            x.0,
+            TmThunk(ref body) if matches!(body.0, TmSeq(_, _))
+        )));
+    }
+
+    #[test]
+    fn tuples() {
+        let InputTerm(term, _) =
+            parse("(x, (y,), ()).1").into_result().unwrap();
+        assert!(matches!(term, TmProj(ref t, 1) if matches!(
+            t.0,
+            TmTuple(ref items) if items.len() == 3
+        )));
+        let InputTerm(term, _) =
+            parse("(x, y: ()) -> x").into_result().unwrap();
+        assert!(matches!(
+            term,
+            TmAbs(PtTuple(ref items), InputType(TyTuple(_), _), _)
+                if items.len() == 2
+        ));
+        assert!(parse("x.()").into_result().is_err());
+    }
+
+    #[test]
+    fn additive_patterns() {
+        let InputTerm(term, _) =
+            parse("(f &) -> (& g) -> f & g").into_result().unwrap();
+        assert!(matches!(term, TmAbs(PtLeft(_), _, ref body) if matches!(
+            body.0,
+            TmAbs(PtRight(_), _, ref body) if matches!(body.0, TmWith(_, _))
+        )));
+        assert!(parse("(f &)").into_result().is_err());
+        assert!(parse("(f & g) -> f").into_result().is_err());
+    }
+
+    #[test]
+    fn recursive_definitions() {
+        let src =
+            "let rec f: () -> () = x: () -> f x; unfold (fold [rec t => t] y)";
+        let InputTerm(term, _) = parse(src).into_result().unwrap();
+        assert!(matches!(term, TmLetRec(_, _, _, ref body) if matches!(
+            body.0,
+            TmUnfold(ref x) if matches!(x.0, TmFold(InputType(TyRec(_, _), _), _))
+        )));
+        assert!(parse("let rec f = (); ()").into_result().is_err());
+    }
+
+    #[test]
+    fn existentials() {
+        let src = "unpack [a, x] = pack [(), ()] as exists a => a; x";
+        let InputTerm(term, _) = parse(src).into_result().unwrap();
+        assert!(matches!(term, TmUnpack(_, PtVar(_), ref x, _) if matches!(
+            x.0,
+            TmPack(_, _, InputType(TyExists(_, _), _))
+        )));
+        assert!(parse("pack [()] as exists a => a").into_result().is_err());
+    }
+
+    #[test]
+    fn rows() {
+        let InputTerm(term, _) = parse("{ { a = x, b = y } | a = z }.b")
+            .into_result()
+            .unwrap();
+        assert!(matches!(term, TmField(ref r, _) if matches!(
+            r.0,
+            TmUpdate(ref r, ref fields)
+                if fields.len() == 1 && matches!(r.0, TmRecord(ref fs) if fs.len() == 2)
+        )));
+        let InputTerm(term, _) = parse("x: { a : (), b : () | r } -> x")
+            .into_result()
+            .unwrap();
+        assert!(matches!(
+            term,
+            TmAbs(_, InputType(TyRecord(ref fields, Some(_)), _), _)
+                if fields.len() == 2
+        ));
+        let InputTerm(term, _) =
+            parse("case x (A y -> y; B -> ())").into_result().unwrap();
+        assert!(matches!(term, TmCase(_, ref arms) if arms.len() == 2));
+        assert!(parse("{ a = x, y }").into_result().is_err());
+    }
+
+    #[test]
+    fn error_ranges() {
+        let errors = parse("let a = (; ()").collect.to_string();
+        assert_eq!(errors, "[0:8-0:9] Unclosed '('\n");
+        let errors = parse("() ) )").collect.to_string();
+        assert_eq!(errors, "[0:3-0:6] Redundant tokens\n");
     }
 }
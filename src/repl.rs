@@ -1,17 +1,34 @@
-use std::{error::Error, fs::File, io};
+use std::{
+    error::Error,
+    fs::File,
+    io,
+    sync::atomic::{AtomicBool, Ordering},
+};
 
 use rustyline::{error::ReadlineError, Editor};
 use thiserror::Error;
 
 use crate::{
-    eval::eval,
-    ident::identify,
-    parser::parse,
+    erase::{erase, Erased},
+    eval::{equal, eval, normalize, Budget, Strategy, Unfinished},
+    ident::{identify, NameWarnings},
+    input::{InputTerm, TmTuple},
+    modules::Loader,
+    parser::{parse, ParseErrors},
+    step::{skip_imports, step, Step},
+    syntax::{de, Term},
     typeck::typeck,
+    vm::{compile, run, Stopped},
     prelude::*,
 };
 
-const HISTORY_FILE: &'static str = ".odlang_history";
+const HISTORY_FILE: &str = ".odlang_history";
+
+/// Terminal escapes that underline the redex of a step.
+const HIGHLIGHT: (&str, &str) = ("\x1b[4m", "\x1b[24m");
+
+/// Set by Ctrl-C to stop the evaluation of the current line.
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
 
 #[derive(Debug, Error)]
 pub enum HistoryError {
@@ -21,16 +38,88 @@ pub enum HistoryError {
     NotAppended(#[from] ReadlineError),
 }
 
-pub fn repl() -> Result<(), HistoryError> {
+/// The output or error for a line, along with the warnings about its names,
+/// which are shown even when the line fails.
+pub type Report<'a> =
+    MultiResult<Result<String, Box<dyn Error + 'a>>, NameWarnings>;
+
+#[derive(Clone, Copy)]
+pub struct Options {
+    pub prelude: bool,
+    pub strategy: Strategy,
+    pub mode: Mode,
+    pub budget: Budget,
+}
+
+/// What is shown for a term that type checks.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Mode {
+    #[default]
+    Evaluate,
+    Normalize,
+    Step,
+    Trace,
+    Bytecode,
+}
+
+/// A term reduced by one step for every empty line in `:step` mode.
+struct Stepper {
+    term: Term,
+    names: Names,
+}
+
+impl Stepper {
+    /// Shows the step the term takes next, keeping the stepper in `slot`
+    /// unless the term is a value.
+    fn show(self, slot: &mut Option<Stepper>) -> String {
+        match step(&self.term) {
+            Some(step) => {
+                let shown = show_step(&step, &self.names);
+                *slot = Some(self);
+                shown
+            }
+            None => show_value(&self.term, &self.names),
+        }
+    }
+
+    fn advance(&mut self) {
+        if let Some(step) = step(&self.term) {
+            self.term = step.after;
+        }
+    }
+}
+
+pub fn repl(mut options: Options) -> Result<(), HistoryError> {
     let mut editor = Editor::<()>::new();
-    if let Err(_) = editor.load_history(HISTORY_FILE) {
+    if editor.load_history(HISTORY_FILE).is_err() {
         File::create(HISTORY_FILE)?;
     }
+    let interrupt = || INTERRUPTED.store(true, Ordering::Relaxed);
+    if ctrlc::set_handler(interrupt).is_ok() {
+        options.budget.interrupt = Some(&INTERRUPTED);
+    }
+    let mut stepper: Option<Stepper> = None;
     while let Ok(line) = editor.readline("turtle > ") {
-        if !editor.add_history_entry(&line) {
+        INTERRUPTED.store(false, Ordering::Relaxed);
+        if !line.trim().is_empty() && !editor.add_history_entry(&line) {
             println!("This entry will not appear in history.");
         }
-        match process_line(&line) {
+        let result = match (line.trim().strip_prefix(':'), stepper.take()) {
+            (None, Some(mut current)) if line.trim().is_empty() => {
+                current.advance();
+                Ok(current.show(&mut stepper)).into()
+            }
+            (Some(command), _) => run_command(command, &mut options),
+            (None, _) if options.mode == Mode::Step => {
+                process(parse(&line), options, |term, names| {
+                    let term = skip_imports(term, &names);
+                    Ok(Stepper { term, names }.show(&mut stepper))
+                })
+            }
+            (None, _) => process_line(&line, options),
+        };
+        eprint!("{}", result.collect);
+        match result.result {
             Ok(line) => println!("{}", line),
             Err(err) => eprintln!("{}", err),
         }
@@ -38,12 +127,204 @@ pub fn repl() -> Result<(), HistoryError> {
     Ok(editor.append_history(HISTORY_FILE)?)
 }
 
-fn process_line<'a>(
-    line: &'a str,
+fn run_command<'a>(command: &'a str, options: &mut Options) -> Report<'a> {
+    match command.split_once(char::is_whitespace) {
+        Some(("equal", terms)) if !terms.trim().is_empty() => {
+            compare(terms.trim(), *options)
+        }
+        _ => set_option(command, options).into(),
+    }
+}
+
+/// Runs a command that changes `options` rather than showing a term.
+fn set_option<'a>(
+    command: &'a str,
+    options: &mut Options,
 ) -> Result<String, Box<dyn Error + 'a>> {
-    let (term, names) = identify(parse(line)?)?;
-    match typeck(term.clone()) {
-        Ok(_) => Ok(eval(term).pprint(&names)),
-        Err(err) => Err(err.pprint(&names).into()),
+    let (name, rest) = match command.split_once(char::is_whitespace) {
+        Some((name, rest)) => (name, rest.trim()),
+        None => (command, ""),
+    };
+    match (name, rest) {
+        ("strategy", "") => {}
+        ("strategy", strategy) => options.strategy = strategy.parse()?,
+        ("eval", "") => options.mode = Mode::Evaluate,
+        ("normalize", "") => {
+            options.mode = Mode::Normalize;
+            return Ok("Normalizing".to_owned());
+        }
+        ("step", "") => {
+            options.mode = Mode::Step;
+            return Ok("Stepping, an empty line takes the next step".to_owned());
+        }
+        ("trace", "") => {
+            options.mode = Mode::Trace;
+            return Ok("Tracing".to_owned());
+        }
+        ("bytecode", "") => {
+            options.mode = Mode::Bytecode;
+            return Ok("Running on the bytecode machine".to_owned());
+        }
+        ("steps", steps) => {
+            options.budget.steps = match steps {
+                "" => options.budget.steps,
+                "off" => None,
+                _ => Some(steps.parse().map_err(|_| {
+                    "Expected a number of steps or 'off'".to_owned()
+                })?),
+            };
+            return Ok(match options.budget.steps {
+                Some(steps) => format!("Stopping after {} steps", steps),
+                None => "Running without a step limit".to_owned(),
+            });
+        }
+        _ => return Err(format!("Unknown command ':{}'", command).into()),
+    }
+    Ok(format!("Evaluating {}", options.strategy))
+}
+
+pub fn process_line(line: &str, options: Options) -> Report<'_> {
+    let Options { strategy, budget, .. } = options;
+    process(parse(line), options, |term, mut names| {
+        let result = match options.mode {
+            Mode::Evaluate => eval(erase(&term), strategy, budget),
+            Mode::Normalize => normalize(erase(&term), &mut names, budget),
+            Mode::Step | Mode::Trace => return trace(term, &names, budget),
+            Mode::Bytecode => {
+                let code = compile(&erase(&term));
+                let value = run(&code, budget).map_err(stopped)?;
+                return Ok(value.pprint(&names));
+            }
+        };
+        match result {
+            Ok(term) => Ok(term.pprint(&names)),
+            Err(err) => Err(unfinished(err, &names)),
+        }
+    })
+}
+
+/// Compiles a program with `backend` instead of running it. Holes are
+/// reported as errors, since there is nothing to compile for them.
+pub fn compile_line<'a>(
+    text: &'a str,
+    options: Options,
+    backend: impl FnOnce(&Erased, &Names) -> String,
+) -> Report<'a> {
+    let mut compiled = None;
+    let report = process(parse(text), options, |term, names| {
+        compiled = Some(backend(&erase(&term), &names));
+        Ok(String::new())
+    });
+    report.map(|report| {
+        let report = report?;
+        compiled.ok_or_else(|| report.into())
+    })
+}
+
+/// Shows every step of the reduction of `term` to a value, or the steps taken
+/// until `budget` is exhausted.
+fn trace<'a>(
+    term: Term,
+    names: &Names,
+    budget: Budget,
+) -> Result<String, Box<dyn Error + 'a>> {
+    let mut term = skip_imports(term, names);
+    let mut lines = vec![];
+    while let Some(step) = step(&term) {
+        if budget.exhausted(lines.len()) {
+            let err = Unfinished {
+                steps: lines.len(),
+                partial: erase(&term),
+                hole: None,
+            };
+            lines.push(unfinished(err, names).to_string());
+            return Err(lines.join("\n").into());
+        }
+        lines.push(show_step(&step, names));
+        term = step.after;
+    }
+    lines.push(show_value(&term, names));
+    Ok(lines.join("\n"))
+}
+
+/// Reports the budget running out along with the partial result.
+fn unfinished<'a>(err: Unfinished, names: &Names) -> Box<dyn Error + 'a> {
+    format!("{}\n{}", err, err.partial.pprint(names)).into()
+}
+
+/// Reports the bytecode machine stopping. Its state cannot be read back as a
+/// term, so unlike `unfinished` there is no partial result to show.
+fn stopped<'a>(err: Stopped) -> Box<dyn Error + 'a> {
+    match err {
+        Stopped::OutOfBudget(_) => {
+            let hint = "The bytecode machine keeps no partial result, \
+                        use :eval to see one";
+            format!("{}\n{}", err, hint).into()
+        }
+        err => err.into(),
+    }
+}
+
+fn show_step(step: &Step, names: &Names) -> String {
+    let before = step.before.pprint_marked(names, &step.redex, HIGHLIGHT);
+    format!("{:>10}  {}", step.rule, before)
+}
+
+fn show_value(value: &Term, names: &Names) -> String {
+    format!("{:>10}  {}", "", value.pprint(names))
+}
+
+/// Decides whether the two terms in `terms`, separated by a comma, are
+/// definitionally equal.
+fn compare(terms: &str, options: Options) -> Report<'_> {
+    let pair = parse(&format!("({})", terms));
+    let is_pair = match &pair.result {
+        InputTerm(TmTuple(items), _) => items.len() == 2,
+        _ => false,
+    };
+    if !is_pair && pair.collect == ParseErrors::default() {
+        return Err("Expected two terms separated by a comma".into()).into();
+    }
+    process(pair, options, |term, mut names| {
+        let (left, right) = (de::proj(term.clone(), 0), de::proj(term, 1));
+        let (left, right) = (erase(&left), erase(&right));
+        match equal(left, right, &mut names, options.budget) {
+            Ok(true) => Ok("Equal".to_owned()),
+            Ok(false) => Ok("Not equal".to_owned()),
+            Err(err) => Err(unfinished(err, &names)),
+        }
+    })
+}
+
+fn process<'a>(
+    parsed: MultiResult<InputTerm, ParseErrors>,
+    options: Options,
+    show: impl FnOnce(Term, Names) -> Result<String, Box<dyn Error + 'a>>,
+) -> Report<'a> {
+    let MultiResult {
+        result: term,
+        collect: parse_errors,
+    } = parsed;
+    let MultiResult {
+        result: term,
+        collect: load_errors,
+    } = Loader::from_env(options.prelude).link(term);
+    let MultiResult {
+        result: (term, mut names, warnings),
+        collect: unbound,
+    } = identify(term);
+    let mut report = format!("{}{}{}", parse_errors, load_errors, unbound);
+    let result = match typeck(term, &mut names) {
+        Err(err) => {
+            report += &err.pprint(&names);
+            Err(report.trim_end().to_owned().into())
+        }
+        Ok(_) if !report.is_empty() => Err(report.trim_end().to_owned().into()),
+        Ok((_, holes)) if !holes.is_empty() => Ok(holes.pprint(&names)),
+        Ok((term, _)) => show(term, names),
+    };
+    MultiResult {
+        result,
+        collect: warnings,
     }
 }
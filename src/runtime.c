@@ -0,0 +1,280 @@
+/* The runtime of compiled odlang programs. */
+
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+typedef struct object *value;
+
+/* The names of the labels of injections and records, which are numbered. */
+extern const char *const label_names[];
+
+enum kind { TUPLE, RECORD, INJ, CLOSURE, FIX, WITH };
+
+/* A piece of the printed form of a closure: text, or the captured value with
+   the index `hole`, in parentheses if `arg` is set and the value needs them. */
+struct chunk {
+    const char *text;
+    int hole;
+    int arg;
+};
+
+/* The code of a function body, a lazy side of a pair or a recursive
+   definition. Functions get their argument, recursive definitions get
+   themselves and lazy sides get nothing. */
+struct code {
+    value (*run)(value self, value arg);
+    const struct chunk *template;
+    int chunks;
+};
+
+struct object {
+    enum kind kind;
+    int size;
+    /* The label of an injection. */
+    int label;
+    /* The labels of the fields of a record. */
+    const int *labels;
+    const struct code *code;
+    /* The items of a tuple, the fields of a record, the payload of an
+       injection, the captured values of a closure or the sides of a pair. */
+    value items[];
+};
+
+/* There is no collector, so objects are never freed: they are carved out of
+   large blocks, which are released when the program exits. A program thus
+   holds on to everything it ever allocated, including the closures and
+   tuples of loop iterations that have finished, and a long-running loop
+   runs out of memory even if it keeps little alive. To fail clearly rather
+   than exhaust the machine, allocation stops the program once its blocks
+   add up to more than HEAP_LIMIT bytes, which can be set when compiling. */
+#ifndef HEAP_LIMIT
+#define HEAP_LIMIT ((size_t)1 << 30)
+#endif
+
+static char *heap, *heap_end;
+static size_t heap_used;
+
+static void *allocate(size_t size) {
+    void *object;
+    size = (size + 15) & ~(size_t)15;
+    if ((size_t)(heap_end - heap) < size) {
+        size_t block = size > (1 << 20) ? size : 1 << 20;
+        heap_used += block;
+        heap = heap_used <= HEAP_LIMIT ? malloc(block) : NULL;
+        if (heap == NULL) {
+            fputs("Out of memory: compiled programs never free memory\n",
+                  stderr);
+            exit(1);
+        }
+        heap_end = heap + block;
+    }
+    object = heap;
+    heap += size;
+    return object;
+}
+
+static value make(enum kind kind, int size) {
+    value object = allocate(sizeof(struct object) + size * sizeof(value));
+    object->kind = kind;
+    object->size = size;
+    return object;
+}
+
+static value inj(int label, value x) {
+    value object = make(INJ, 1);
+    object->label = label;
+    object->items[0] = x;
+    return object;
+}
+
+static value field(value record, int label) {
+    int i = 0;
+    while (record->labels[i] != label) {
+        i++;
+    }
+    return record->items[i];
+}
+
+static value update(value record) {
+    value copy = make(RECORD, record->size);
+    copy->labels = record->labels;
+    memcpy(copy->items, record->items, record->size * sizeof(value));
+    return copy;
+}
+
+static void set_field(value record, int label, value x) {
+    int i;
+    for (i = 0; i < record->size; i++) {
+        if (record->labels[i] == label) {
+            record->items[i] = x;
+        }
+    }
+}
+
+/* Calls in tail position return TAIL after saving the function and its
+   argument, and the nearest call that is not in tail position makes them. */
+static struct object tail;
+static value pending_f, pending_x;
+
+#define TAIL (&tail)
+
+static value tail_call(value f, value x) {
+    pending_f = f;
+    pending_x = x;
+    return TAIL;
+}
+
+/* Calls that are not in tail position nest on the C stack, which is much
+   smaller than the heap and cannot be grown from here. To fail clearly rather
+   than crash, calls stop the program once the stack below `main` is more than
+   STACK_LIMIT bytes deep, which can be set when compiling and must stay below
+   the stack size of the system. */
+#ifndef STACK_LIMIT
+#define STACK_LIMIT ((size_t)1 << 22)
+#endif
+
+static char *stack_base;
+
+static void check_stack(void) {
+    char here;
+    size_t depth = stack_base > &here ? stack_base - &here : &here - stack_base;
+    if (depth > STACK_LIMIT) {
+        fputs("Stack overflow: calls are nested too deeply\n", stderr);
+        exit(1);
+    }
+}
+
+static value settle(value result) {
+    while (result == TAIL) {
+        result = pending_f->code->run(pending_f, pending_x);
+    }
+    return result;
+}
+
+/* Stops the program at a hole, which has no value. */
+static value hole(const char *name) {
+    if (name != NULL) {
+        fprintf(stderr, "Evaluation reached the hole ?%s\n", name);
+    } else {
+        fputs("Evaluation reached a hole\n", stderr);
+    }
+    exit(1);
+}
+
+static value apply(value f, value x) {
+    check_stack();
+    return settle(f->code->run(f, x));
+}
+
+static value unroll(value fix) {
+    check_stack();
+    return settle(fix->code->run(fix, fix));
+}
+
+static value force(value side) {
+    check_stack();
+    return settle(side->code->run(side, NULL));
+}
+
+/* Printing works through a stack of values and text still to print, so that
+   deeply nested values do not exhaust the C stack. */
+struct piece {
+    value v;
+    int arg;
+    const char *text;
+};
+
+static struct piece *pieces;
+static size_t piece_count, piece_capacity;
+
+static void push(value v, int arg, const char *text) {
+    if (piece_count == piece_capacity) {
+        piece_capacity = piece_capacity > 0 ? 2 * piece_capacity : 64;
+        pieces = realloc(pieces, piece_capacity * sizeof(struct piece));
+        if (pieces == NULL) {
+            fputs("Out of memory: the value is too large to print\n", stderr);
+            exit(1);
+        }
+    }
+    pieces[piece_count].v = v;
+    pieces[piece_count].arg = arg;
+    pieces[piece_count].text = text;
+    piece_count++;
+}
+
+static void push_text(const char *text) {
+    push(NULL, 0, text);
+}
+
+/* Pushes the printed form of `v` in reverse, so that it pops in order. */
+static void expand(value v, int arg) {
+    int i, parens = arg && (v->kind == INJ || v->kind == CLOSURE ||
+                            v->kind == FIX);
+    if (parens) {
+        push_text(")");
+    }
+    switch (v->kind) {
+    case TUPLE:
+        push_text(v->size == 1 ? ",)" : ")");
+        for (i = v->size - 1; i >= 0; i--) {
+            push(v->items[i], 0, NULL);
+            push_text(i > 0 ? ", " : "(");
+        }
+        if (v->size == 0) {
+            push_text("(");
+        }
+        break;
+    case RECORD:
+        push_text("}");
+        for (i = v->size - 1; i >= 0; i--) {
+            push(v->items[i], 0, NULL);
+            push_text(" = ");
+            push_text(label_names[v->labels[i]]);
+            push_text(i > 0 ? ", " : "{");
+        }
+        if (v->size == 0) {
+            push_text("{");
+        }
+        break;
+    case INJ:
+        push(v->items[0], 1, NULL);
+        push_text(" ");
+        push_text(label_names[v->label]);
+        push_text("inj ");
+        break;
+    case CLOSURE:
+    case FIX:
+        for (i = v->code->chunks - 1; i >= 0; i--) {
+            const struct chunk *chunk = &v->code->template[i];
+            if (chunk->text != NULL) {
+                push_text(chunk->text);
+            } else {
+                push(v->items[chunk->hole], chunk->arg, NULL);
+            }
+        }
+        break;
+    case WITH:
+        push_text(")");
+        push(v->items[1], 0, NULL);
+        push_text(" & ");
+        push(v->items[0], 0, NULL);
+        push_text("(");
+        break;
+    }
+    if (parens) {
+        push_text("(");
+    }
+}
+
+static void print(value v, int arg) {
+    push(v, arg, NULL);
+    while (piece_count > 0) {
+        struct piece piece = pieces[--piece_count];
+        if (piece.text != NULL) {
+            fputs(piece.text, stdout);
+        } else {
+            expand(piece.v, piece.arg);
+        }
+    }
+}
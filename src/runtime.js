@@ -0,0 +1,164 @@
+// The runtime of odlang programs compiled to JavaScript. Tuples are arrays,
+// records are objects without prototypes, functions are closures and lazy
+// sides of pairs are closures without parameters.
+
+"use strict";
+
+class Inj {
+    constructor(label, value) {
+        this.label = label;
+        this.value = value;
+    }
+}
+
+class With {
+    constructor(left, right) {
+        this.left = left;
+        this.right = right;
+    }
+}
+
+// A recursive definition, whose body gets the definition itself.
+class Fix {
+    constructor(body, template, captured) {
+        this.body = body;
+        this.template = template;
+        this.captured = captured;
+    }
+}
+
+// Closures keep the printed form of their source, with holes for the
+// captured values given by their index and whether they are arguments.
+function closure(f, template, captured) {
+    f.template = template;
+    f.captured = captured;
+    return f;
+}
+
+// Builds a record from label and value pairs. Its fields are defined rather
+// than assigned, so that a label like __proto__ is an ordinary field.
+function record(fields) {
+    const r = Object.create(null);
+    for (const [label, value] of fields) {
+        Object.defineProperty(r, label, {
+            value,
+            enumerable: true,
+            writable: true,
+        });
+    }
+    return r;
+}
+
+function update(r, fields) {
+    return record([...Object.entries(r), ...fields]);
+}
+
+function fix(body, template, captured) {
+    return new Fix(body, template, captured);
+}
+
+// Calls in tail position return TAIL after saving the function and its
+// argument, and the nearest call that is not in tail position makes them.
+const TAIL = {};
+let pendingF, pendingX;
+
+function tailCall(f, x) {
+    pendingF = f;
+    pendingX = x;
+    return TAIL;
+}
+
+function settle(result) {
+    while (result === TAIL) {
+        result = pendingF(pendingX);
+    }
+    return result;
+}
+
+// Stops the program at a hole, which has no value.
+function hole(name) {
+    throw new Error(
+        name === null
+            ? "Evaluation reached a hole"
+            : "Evaluation reached the hole ?" + name,
+    );
+}
+
+function apply(f, x) {
+    return settle(f(x));
+}
+
+function unroll(fix) {
+    return settle(fix.body(fix));
+}
+
+function force(side) {
+    return settle(side());
+}
+
+// Shows a value with a stack of values and text still to show, so that deeply
+// nested values do not exhaust the JavaScript stack.
+function show(v, arg) {
+    const text = [];
+    const stack = [[v, arg]];
+    while (stack.length > 0) {
+        const top = stack.pop();
+        if (typeof top === "string") {
+            text.push(top);
+            continue;
+        }
+        const parts = pieces(top[0], top[1]);
+        for (let i = parts.length - 1; i >= 0; i--) {
+            stack.push(parts[i]);
+        }
+    }
+    return text.join("");
+}
+
+// The printed form of a value, as text and pairs of a value and whether it is
+// an argument.
+function pieces(v, arg) {
+    if (Array.isArray(v)) {
+        const items = v.flatMap((x, i) =>
+            i > 0 ? [", ", [x, false]] : [[x, false]],
+        );
+        return ["(", ...items, v.length === 1 ? ",)" : ")"];
+    }
+    if (v instanceof With) {
+        return ["(", [v.left, false], " & ", [v.right, false], ")"];
+    }
+    let parts;
+    if (v instanceof Inj) {
+        parts = ["inj " + v.label + " ", [v.value, true]];
+    } else if (typeof v === "function" || v instanceof Fix) {
+        parts = v.template.map((chunk) =>
+            typeof chunk === "string"
+                ? chunk
+                : [v.captured[chunk[0]], chunk[1]],
+        );
+    } else {
+        const fields = Object.keys(v).flatMap((l, i) => [
+            (i > 0 ? ", " : "") + l + " = ",
+            [v[l], false],
+        ]);
+        return ["{", ...fields, "}"];
+    }
+    return arg ? ["(", ...parts, ")"] : parts;
+}
+
+// Runs the whole program and prints its value. Calls that are not in tail
+// position nest on the JavaScript stack, so a program that nests too many of
+// them stops with an error, as compiled C programs do.
+function main(program) {
+    let value;
+    try {
+        value = program();
+    } catch (e) {
+        if (!(e instanceof RangeError)) {
+            throw e;
+        }
+        console.error("Stack overflow: calls are nested too deeply");
+        process.exit(1);
+    }
+    console.log(show(value, false));
+}
@@ -0,0 +1,402 @@
+use std::fmt::Display;
+
+use crate::{
+    prelude::*,
+    subst::{children, rebuild, subst, subst_type},
+    syntax::*,
+};
+
+/// A reduction of the redex that left-to-right call-by-value evaluation
+/// contracts next. `before` is the reduced term with the redex in a node of
+/// its own, so that it can be told apart from equal subterms.
+pub struct Step {
+    pub rule: Rule,
+    pub before: Term,
+    pub redex: Term,
+    pub after: Term,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rule {
+    Beta,
+    TypeBeta,
+    Let,
+    Fix,
+    Projection,
+    Update,
+    Case,
+    Unfold,
+    Unpack,
+}
+
+pub use Rule::*;
+
+impl Display for Rule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Beta => "beta",
+            TypeBeta => "type beta",
+            Let => "let",
+            Fix => "fix",
+            Projection => "projection",
+            Update => "update",
+            Case => "case",
+            Unfold => "unfold",
+            Unpack => "unpack",
+        };
+        f.pad(name)
+    }
+}
+
+impl Step {
+    fn new(rule: Rule, redex: &Term, after: Term) -> Self {
+        let redex = Term::from((**redex).clone());
+        Self {
+            rule,
+            before: redex.clone(),
+            redex,
+            after,
+        }
+    }
+
+    fn map(self, context: impl Fn(Term) -> Term) -> Self {
+        Self {
+            before: context(self.before),
+            after: context(self.after),
+            ..self
+        }
+    }
+}
+
+/// Reduces `term` by one step, or returns `None` if it is a value.
+///
+/// The redex is found by descending through the evaluated subterms of each
+/// node in order, keeping the path on a stack so that deep terms do not
+/// overflow the call stack. The step is then rebuilt along the path.
+pub fn step(term: &Term) -> Option<Step> {
+    // Each node on the path, with the number of its subterms tried so far.
+    let mut path = vec![(term, 0)];
+    let mut found = None;
+    while let Some((node, tried)) = path.last_mut() {
+        let node = *node;
+        if *tried < evaluated(node) {
+            let next = children(node)[*tried];
+            *tried += 1;
+            path.push((next, 0));
+        } else if let Some(step) = contract(node) {
+            path.pop();
+            found = Some(step);
+            break;
+        } else {
+            path.pop();
+        }
+    }
+    let mut step = found?;
+    while let Some((node, tried)) = path.pop() {
+        step = step.map(|x| replace(node, tried - 1, x));
+    }
+    Some(step)
+}
+
+/// The number of leading `children` of `term` that are evaluated before it
+/// can be contracted.
+fn evaluated(term: &Term) -> usize {
+    match &**term {
+        TmTuple(items) => items.len(),
+        TmRecord(fields) => fields.len(),
+        TmUpdate(_, fields) => 1 + fields.len(),
+        TmApp(_, _) => 2,
+        TmProj(_, _)
+        | TmField(_, _)
+        | TmInj(_, _, _)
+        | TmCase(_, _)
+        | TmTyApp(_, _)
+        | TmLet(_, _, _, _)
+        | TmFold(_, _)
+        | TmUnfold(_)
+        | TmPack(_, _, _)
+        | TmUnpack(_, _, _, _) => 1,
+        TmHole(_)
+        | TmVar(_)
+        | TmWith(_, _)
+        | TmAbs(_, _, _)
+        | TmTyAbs(_, _)
+        | TmFix(_, _, _) => 0,
+        TmQualAbs(_, _, _) | TmClass(_, _) | TmInstance(_, _) | TmError => {
+            unreachable!()
+        }
+    }
+}
+
+/// Contracts `term` if it is a redex, given that its evaluated subterms are
+/// values.
+fn contract(term: &Term) -> Option<Step> {
+    match &**term {
+        TmProj(x, index) => match &**x {
+            TmTuple(items) => {
+                Some(Step::new(Projection, term, items[*index].clone()))
+            }
+            _ => None,
+        },
+        TmField(x, l) => match &**x {
+            TmRecord(fields) => {
+                Some(Step::new(Projection, term, field(fields, l)))
+            }
+            _ => None,
+        },
+        TmUpdate(x, updates) => match &**x {
+            TmRecord(fields) => {
+                let mut fields = fields.clone();
+                for (label, value) in updates {
+                    if let Some(field) =
+                        fields.iter_mut().find(|(l, _)| l == label)
+                    {
+                        field.1 = value.clone();
+                    }
+                }
+                Some(Step::new(Update, term, de::record(fields)))
+            }
+            _ => None,
+        },
+        TmCase(x, arms) => match &**x {
+            TmInj(_, label, value) => {
+                let (_, p, y) =
+                    arms.iter().find(|(l, _, _)| l == label).unwrap();
+                let after = bind(p, value.clone(), y.clone());
+                Some(Step::new(Case, term, after))
+            }
+            _ => None,
+        },
+        TmApp(f, x) => match &**f {
+            TmAbs(v, _, y) => {
+                let after = subst(x.clone(), y.clone(), *v);
+                Some(Step::new(Beta, term, after))
+            }
+            _ => None,
+        },
+        TmTyApp(f, t) => match &**f {
+            TmTyAbs(v, y) => {
+                let after = subst_type(t.clone(), y.clone(), *v);
+                Some(Step::new(TypeBeta, term, after))
+            }
+            _ => None,
+        },
+        TmLet(p, _, x, y) => {
+            let after = bind(p, x.clone(), y.clone());
+            Some(Step::new(Let, term, after))
+        }
+        TmFix(f, _, x) => {
+            let after = subst(term.clone(), x.clone(), *f);
+            Some(Step::new(Fix, term, after))
+        }
+        TmUnfold(x) => match &**x {
+            TmFold(_, value) => Some(Step::new(Unfold, term, value.clone())),
+            _ => None,
+        },
+        TmUnpack(a, p, x, y) => match &**x {
+            TmPack(t, value, _) => {
+                let y = subst_type(t.clone(), y.clone(), *a);
+                let after = bind(p, value.clone(), y);
+                Some(Step::new(Unpack, term, after))
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Rebuilds `term` with its `index`th child replaced by `with`.
+fn replace(term: &Term, index: usize, with: Term) -> Term {
+    let mut done: Vec<_> = children(term).into_iter().cloned().collect();
+    done[index] = with;
+    rebuild(term, &mut done, Type::clone)
+}
+
+/// Reduces the imports `term` starts with, such as that of the Prelude, and
+/// returns the rest of the program with their definitions substituted in.
+pub fn skip_imports(mut term: Term, names: &Names) -> Term {
+    let mut imported = vec![];
+    while let TmLet(PtVar(var), _, _, _) = &*term {
+        if !imported.contains(var) {
+            imported = import_binders(&term, names);
+            if imported.is_empty() {
+                break;
+            }
+        }
+        match step(&term) {
+            Some(step) => term = step.after,
+            None => break,
+        }
+    }
+    term
+}
+
+/// The variables bound by an import that `term` starts with: the module,
+/// then each of its exports under its qualified name. Such names cannot be
+/// written in programs, so the definitions of the program are never taken
+/// for an import.
+fn import_binders(term: &Term, names: &Names) -> Vec<Var> {
+    let (module, mut body) = match &**term {
+        TmLet(PtVar(module), _, _, body) => (*module, body),
+        _ => return vec![],
+    };
+    let mut vars = vec![module];
+    while let TmLet(PtVar(var), _, value, rest) = &**body {
+        match &**value {
+            TmField(record, label)
+                if **record == TmVar(module)
+                    && names[*var]
+                        == format!("{}.{}", names[module], label) =>
+            {
+                vars.push(*var);
+                body = rest;
+            }
+            _ => break,
+        }
+    }
+    if vars.len() == 1 {
+        vars.clear();
+    }
+    vars
+}
+
+/// Substitutes the components of `value` for the variables of `pattern`.
+/// Components of lazy pairs are not values, so they are bound by a `let`.
+fn bind(pattern: &Pattern, value: Term, body: Term) -> Term {
+    match (pattern, &*value) {
+        (PtVar(var), _) => subst(value, body, *var),
+        (PtTuple(items), TmTuple(values)) => items
+            .iter()
+            .zip(values)
+            .rev()
+            .fold(body, |body, (item, value)| bind(item, value.clone(), body)),
+        (PtLeft(p), TmWith(l, _)) => {
+            de::r#let((**p).clone(), ty::hole(), l.clone(), body)
+        }
+        (PtRight(p), TmWith(_, r)) => {
+            de::r#let((**p).clone(), ty::hole(), r.clone(), body)
+        }
+        (PtWild, _) => body,
+        _ => unreachable!(),
+    }
+}
+
+fn field(fields: &[(String, Term)], label: &str) -> Term {
+    let (_, value) = fields.iter().find(|(l, _)| l == label).unwrap();
+    value.clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        erase::erase,
+        eval::{eval, Budget, CallByValue},
+        testing::{examples, named},
+    };
+
+    fn trace(term: Term, names: &Names) -> (Vec<(Rule, String)>, Term) {
+        let mut term = term;
+        let mut steps = vec![];
+        while let Some(step) = step(&term) {
+            let before =
+                step.before.pprint_marked(names, &step.redex, ("[", "]"));
+            steps.push((step.rule, before));
+            term = step.after;
+        }
+        (steps, term)
+    }
+
+    #[test]
+    fn redexes() {
+        let (term, names) = named("(x: () -> x) ((y: () -> y) ())");
+        let (steps, value) = trace(term, &names);
+        assert_eq!(
+            steps,
+            vec![
+                (Beta, r"(\x: (). x) [(\y: (). y) ()]".to_owned()),
+                (Beta, r"[(\x: (). x) ()]".to_owned()),
+            ]
+        );
+        assert_eq!(value, de::unit());
+
+        let (term, names) = named("(a => x: a -> x) [()] ()");
+        let (steps, _) = trace(term, &names);
+        assert_eq!(
+            steps,
+            vec![
+                (TypeBeta, r"[(/\ a. \x: a. x) [()]] ()".to_owned()),
+                (Beta, r"[(\x: (). x) ()]".to_owned()),
+            ]
+        );
+
+        let (term, names) = named("let (x &) = ((), ((),)) & (); x.1.0");
+        let rules = trace(term, &names).0.into_iter().map(|(rule, _)| rule);
+        assert_eq!(
+            rules.collect::<Vec<_>>(),
+            vec![Let, Let, Projection, Projection]
+        );
+    }
+
+    #[test]
+    fn skips_only_imports() {
+        let input =
+            "import Prelude\nlet x = (y: () -> y) ((z: () -> z) ()); (x, x)";
+        let (term, names) = named(input);
+        let (steps, _) = trace(skip_imports(term, &names), &names);
+        assert_eq!(
+            steps[0],
+            (
+                Beta,
+                r"let x = (\y: (). y) [(\z: (). z) ()]; (x, x)".to_owned()
+            )
+        );
+        assert_eq!(steps.len(), 3);
+    }
+
+    #[test]
+    fn agrees_with_eval() {
+        for (term, names) in examples() {
+            let (_, value) = trace(term.clone(), &names);
+            assert_eq!(
+                erase(&value),
+                eval(erase(&term), CallByValue, Budget::default()).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn deep_terms() {
+        let depth = 1_000_000;
+        let mut names = Names::default();
+        let (a, x) = (names.push("a".into()), names.push("x".into()));
+        let y = names.push("y".into());
+        let mut body = de::var(x);
+        for _ in 0..depth {
+            body = de::tuple(vec![de::abs(y, ty::var(a), body)]);
+        }
+        let term = de::app(
+            de::ty_app(de::ty_abs(a, de::abs(x, ty::var(a), body)), ty::unit()),
+            de::unit(),
+        );
+        let mut rules = vec![];
+        let mut term = term;
+        while let Some(step) = step(&term) {
+            rules.push(step.rule);
+            term = step.after;
+        }
+        assert_eq!(rules, vec![TypeBeta, Beta]);
+        for _ in 0..depth {
+            term = match &*term {
+                TmTuple(items) => match &*items[0] {
+                    TmAbs(v, t, body) if *v == y && *t == ty::unit() => {
+                        body.clone()
+                    }
+                    _ => panic!("expected an abstraction over ()"),
+                },
+                _ => panic!("expected a single-element tuple"),
+            };
+        }
+        assert_eq!(term, de::unit());
+    }
+}
@@ -0,0 +1,197 @@
+use crate::{prelude::*, syntax::*, typeck};
+
+pub fn binds(pattern: &Pattern, var: Var) -> bool {
+    match pattern {
+        PtVar(v) => *v == var,
+        PtTuple(items) => items.iter().any(|item| binds(item, var)),
+        PtLeft(p) | PtRight(p) => binds(p, var),
+        PtWild => false,
+    }
+}
+
+/// Substitutes `with` for the type variable `var` in the types inside
+/// `term`, with a work stack as in `erase`.
+pub fn subst_type(with: Type, term: Term, var: Var) -> Term {
+    enum Task<'t> {
+        Visit(&'t Term),
+        Build(&'t Term),
+    }
+    let mut tasks = vec![Task::Visit(&term)];
+    let mut done = vec![];
+    while let Some(task) = tasks.pop() {
+        match task {
+            Task::Visit(term) => match &**term {
+                TmHole(_) | TmVar(_) => done.push(term.clone()),
+                TmTyAbs(n, _) if *n == var => done.push(term.clone()),
+                _ => {
+                    tasks.push(Task::Build(term));
+                    tasks.extend(
+                        children(term).into_iter().rev().map(Task::Visit),
+                    )
+                }
+            },
+            Task::Build(term) => {
+                let term = rebuild(term, &mut done, |t| {
+                    typeck::subst_type(t.clone(), with.clone(), var)
+                });
+                done.push(term)
+            }
+        }
+    }
+    pop(&mut done)
+}
+
+/// Substitutes `with` for the variable `what` in `inside`, with a work stack
+/// as in `erase`. Subterms in which a binder shadows `what` are kept.
+pub fn subst(with: Term, inside: Term, what: Var) -> Term {
+    enum Task<'t> {
+        Visit(&'t Term),
+        Keep(&'t Term),
+        Build(&'t Term),
+    }
+    let mut tasks = vec![Task::Visit(&inside)];
+    let mut done = vec![];
+    while let Some(task) = tasks.pop() {
+        match task {
+            Task::Visit(term) => match &**term {
+                TmVar(var) if *var == what => done.push(with.clone()),
+                TmHole(_) | TmVar(_) => done.push(term.clone()),
+                TmAbs(n, _, _) | TmTyAbs(n, _) | TmFix(n, _, _)
+                    if *n == what =>
+                {
+                    done.push(term.clone())
+                }
+                _ => {
+                    let shadowed = |index: usize| match &**term {
+                        TmCase(_, arms) => {
+                            index > 0 && binds(&arms[index - 1].1, what)
+                        }
+                        TmLet(p, _, _, _) | TmUnpack(_, p, _, _) => {
+                            index == 1 && binds(p, what)
+                        }
+                        _ => false,
+                    };
+                    tasks.push(Task::Build(term));
+                    let children = children(term).into_iter().enumerate();
+                    tasks.extend(children.rev().map(|(index, child)| {
+                        match shadowed(index) {
+                            true => Task::Keep(child),
+                            false => Task::Visit(child),
+                        }
+                    }))
+                }
+            },
+            Task::Keep(term) => done.push(term.clone()),
+            Task::Build(term) => {
+                let term = rebuild(term, &mut done, Type::clone);
+                done.push(term)
+            }
+        }
+    }
+    pop(&mut done)
+}
+
+/// The subterms of `term`, in the order `rebuild` takes them.
+pub fn children(term: &Term) -> Vec<&Term> {
+    match &**term {
+        TmTuple(items) => items.iter().collect(),
+        TmRecord(fields) => fields.iter().map(|(_, x)| x).collect(),
+        TmUpdate(x, fields) => Some(x)
+            .into_iter()
+            .chain(fields.iter().map(|(_, y)| y))
+            .collect(),
+        TmCase(x, arms) => Some(x)
+            .into_iter()
+            .chain(arms.iter().map(|(_, _, y)| y))
+            .collect(),
+        TmProj(x, _)
+        | TmField(x, _)
+        | TmInj(_, _, x)
+        | TmAbs(_, _, x)
+        | TmTyAbs(_, x)
+        | TmTyApp(x, _)
+        | TmFix(_, _, x)
+        | TmFold(_, x)
+        | TmUnfold(x)
+        | TmPack(_, x, _) => vec![x],
+        TmWith(x, y)
+        | TmApp(x, y)
+        | TmLet(_, _, x, y)
+        | TmUnpack(_, _, x, y) => vec![x, y],
+        TmHole(_) | TmVar(_) => vec![],
+        TmQualAbs(_, _, _) | TmClass(_, _) | TmInstance(_, _) | TmError => {
+            unreachable!()
+        }
+    }
+}
+
+/// Rebuilds `term` from its new children on top of `done`, mapping its types
+/// with `ty`.
+pub fn rebuild(
+    term: &Term,
+    done: &mut Vec<Term>,
+    ty: impl Fn(&Type) -> Type,
+) -> Term {
+    match &**term {
+        TmTuple(items) => de::tuple(done.split_off(done.len() - items.len())),
+        TmProj(_, index) => de::proj(pop(done), *index),
+        TmWith(_, _) => {
+            let r = pop(done);
+            de::with(pop(done), r)
+        }
+        TmRecord(fields) => {
+            let values = done.split_off(done.len() - fields.len());
+            de::record(relabel(fields, values))
+        }
+        TmField(_, l) => de::field(pop(done), l.clone()),
+        TmUpdate(_, fields) => {
+            let values = done.split_off(done.len() - fields.len());
+            de::update(pop(done), relabel(fields, values))
+        }
+        TmInj(t, l, _) => de::inj(ty(t), l.clone(), pop(done)),
+        TmCase(_, arms) => {
+            let ys = done.split_off(done.len() - arms.len());
+            let arms = arms
+                .iter()
+                .zip(ys)
+                .map(|((l, p, _), y)| (l.clone(), p.clone(), y))
+                .collect();
+            de::case(pop(done), arms)
+        }
+        TmAbs(v, t, _) => de::abs(*v, ty(t), pop(done)),
+        TmApp(_, _) => {
+            let x = pop(done);
+            de::app(pop(done), x)
+        }
+        TmTyAbs(a, _) => de::ty_abs(*a, pop(done)),
+        TmTyApp(_, t) => de::ty_app(pop(done), ty(t)),
+        TmLet(p, t, _, _) => {
+            let y = pop(done);
+            de::r#let(p.clone(), ty(t), pop(done), y)
+        }
+        TmFix(f, t, _) => de::fix(*f, ty(t), pop(done)),
+        TmFold(t, _) => de::fold(ty(t), pop(done)),
+        TmUnfold(_) => de::unfold(pop(done)),
+        TmPack(t, _, u) => de::pack(ty(t), pop(done), ty(u)),
+        TmUnpack(a, p, _, _) => {
+            let y = pop(done);
+            de::unpack(*a, p.clone(), pop(done), y)
+        }
+        TmHole(_) | TmVar(_) => term.clone(),
+        TmQualAbs(_, _, _) | TmClass(_, _) | TmInstance(_, _) | TmError => {
+            unreachable!()
+        }
+    }
+}
+
+fn pop(done: &mut Vec<Term>) -> Term {
+    done.pop().unwrap()
+}
+
+fn relabel(
+    fields: &[(String, Term)],
+    values: Vec<Term>,
+) -> Vec<(String, Term)> {
+    let labels = fields.iter().map(|(label, _)| label.clone());
+    labels.zip(values).collect()
+}
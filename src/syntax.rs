@@ -1,4 +1,6 @@
-use std::{ops::Deref, rc::Rc};
+use std::{mem, ops::Deref, rc::Rc};
+
+use itertools::Itertools;
 
 use crate::prelude::*;
 
@@ -9,34 +11,135 @@ pub struct Term(Rc<TermData>);
 pub struct Type(Rc<TypeData>);
 
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[allow(clippy::enum_variant_names)]
 pub enum TermData {
-    TmUnit,
+    TmTuple(Vec<Term>),
+    TmProj(Term, usize),
+    /// A lazy pair, of which a `(p &)` or `(& p)` pattern evaluates one side.
+    /// Types are not substructural, so nothing checks that only one side is
+    /// consumed.
+    TmWith(Term, Term),
+    TmRecord(Vec<(String, Term)>),
+    TmField(Term, String),
+    TmUpdate(Term, Vec<(String, Term)>),
+    TmInj(Type, String, Term),
+    TmCase(Term, Vec<(String, Pattern, Term)>),
+    TmHole(Option<String>),
     TmVar(Var),
     TmAbs(Var, Type, Term),
     TmApp(Term, Term),
     TmTyAbs(Var, Term),
     TmTyApp(Term, Type),
+    TmLet(Pattern, Type, Term, Term),
+    TmFix(Var, Type, Term),
+    TmFold(Type, Term),
+    TmUnfold(Term),
+    TmPack(Type, Term, Type),
+    TmUnpack(Var, Pattern, Term, Term),
+    TmQualAbs(Var, Type, Term),
+    TmClass(Class, Term),
+    TmInstance(Instance, Term),
     TmError,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[allow(clippy::enum_variant_names)]
+pub enum Pattern {
+    PtWild,
+    PtTuple(Vec<Pattern>),
+    PtLeft(Box<Pattern>),
+    PtRight(Box<Pattern>),
+    PtVar(Var),
+}
+
+/// A class with a single parameter, which ranges over types. There are no
+/// type constructors or modalities, so classes like `Bind ' m` from
+/// examples/yield.od cannot be declared.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Class {
+    pub name: Var,
+    pub param: Var,
+    pub methods: Vec<(Var, Type)>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Instance {
+    pub params: Vec<Var>,
+    pub context: Vec<(Var, Type)>,
+    pub class: Var,
+    pub head: Type,
+    pub methods: Vec<(Var, Term)>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[allow(clippy::enum_variant_names)]
 pub enum TypeData {
-    TyUnit,
+    TyTuple(Vec<Type>),
+    TyWith(Type, Type),
+    TyRecord(Vec<(String, Type)>, Option<Type>),
+    TyVariant(Vec<(String, Type)>, Option<Type>),
     TyHole,
     TyVar(Var),
     TyArrow(Type, Type),
     TyForall(Var, Type),
+    TyRec(Var, Type),
+    TyExists(Var, Type),
+    TyQual(Var, Type, Type),
     TyError,
 }
 
+pub use Pattern::*;
 pub use TermData::*;
 pub use TypeData::*;
 
 pub mod de {
     use super::*;
 
+    pub fn tuple(items: Vec<Term>) -> Term {
+        TmTuple(items).into()
+    }
+
+    #[cfg(test)]
     pub fn unit() -> Term {
-        TmUnit.into()
+        tuple(vec![])
+    }
+
+    pub fn proj(tuple: impl Into<Term>, index: usize) -> Term {
+        TmProj(tuple.into(), index).into()
+    }
+
+    pub fn with(left: impl Into<Term>, right: impl Into<Term>) -> Term {
+        TmWith(left.into(), right.into()).into()
+    }
+
+    pub fn record(fields: Vec<(String, Term)>) -> Term {
+        TmRecord(fields).into()
+    }
+
+    pub fn field(record: impl Into<Term>, label: String) -> Term {
+        TmField(record.into(), label).into()
+    }
+
+    pub fn update(
+        record: impl Into<Term>,
+        fields: Vec<(String, Term)>,
+    ) -> Term {
+        TmUpdate(record.into(), fields).into()
+    }
+
+    pub fn inj(
+        r#type: impl Into<Type>,
+        label: String,
+        term: impl Into<Term>,
+    ) -> Term {
+        TmInj(r#type.into(), label, term.into()).into()
+    }
+
+    pub fn case(
+        term: impl Into<Term>,
+        arms: Vec<(String, Pattern, Term)>,
+    ) -> Term {
+        TmCase(term.into(), arms).into()
     }
 
     pub fn abs(
@@ -51,6 +154,10 @@ pub mod de {
         TmApp(f.into(), x.into()).into()
     }
 
+    pub fn hole(name: Option<String>) -> Term {
+        TmHole(name).into()
+    }
+
     pub fn var(key: impl Into<Var>) -> Term {
         TmVar(key.into()).into()
     }
@@ -63,6 +170,64 @@ pub mod de {
         TmTyApp(f.into(), ty.into()).into()
     }
 
+    pub fn r#let(
+        pattern: Pattern,
+        r#type: impl Into<Type>,
+        value: impl Into<Term>,
+        body: impl Into<Term>,
+    ) -> Term {
+        TmLet(pattern, r#type.into(), value.into(), body.into()).into()
+    }
+
+    pub fn fix(
+        var: impl Into<Var>,
+        r#type: impl Into<Type>,
+        body: impl Into<Term>,
+    ) -> Term {
+        TmFix(var.into(), r#type.into(), body.into()).into()
+    }
+
+    pub fn fold(r#type: impl Into<Type>, term: impl Into<Term>) -> Term {
+        TmFold(r#type.into(), term.into()).into()
+    }
+
+    pub fn unfold(term: impl Into<Term>) -> Term {
+        TmUnfold(term.into()).into()
+    }
+
+    pub fn pack(
+        witness: impl Into<Type>,
+        term: impl Into<Term>,
+        r#type: impl Into<Type>,
+    ) -> Term {
+        TmPack(witness.into(), term.into(), r#type.into()).into()
+    }
+
+    pub fn unpack(
+        param: impl Into<Var>,
+        pattern: Pattern,
+        value: impl Into<Term>,
+        body: impl Into<Term>,
+    ) -> Term {
+        TmUnpack(param.into(), pattern, value.into(), body.into()).into()
+    }
+
+    pub fn qual_abs(
+        class: impl Into<Var>,
+        r#type: impl Into<Type>,
+        body: impl Into<Term>,
+    ) -> Term {
+        TmQualAbs(class.into(), r#type.into(), body.into()).into()
+    }
+
+    pub fn class(class: Class, body: impl Into<Term>) -> Term {
+        TmClass(class, body.into()).into()
+    }
+
+    pub fn instance(instance: Instance, body: impl Into<Term>) -> Term {
+        TmInstance(instance, body.into()).into()
+    }
+
     pub fn error() -> Term {
         TmError.into()
     }
@@ -72,7 +237,11 @@ pub mod ty {
     use super::*;
 
     pub fn unit() -> Type {
-        TyUnit.into()
+        tuple(vec![])
+    }
+
+    pub fn tuple(items: Vec<Type>) -> Type {
+        TyTuple(items).into()
     }
 
     pub fn hole() -> Type {
@@ -83,6 +252,51 @@ pub mod ty {
         TyVar(key.into()).into()
     }
 
+    pub fn with(left: impl Into<Type>, right: impl Into<Type>) -> Type {
+        TyWith(left.into(), right.into()).into()
+    }
+
+    pub fn record(fields: Vec<(String, Type)>, tail: Option<Type>) -> Type {
+        let (fields, tail) = row(fields, tail, |ty| match ty {
+            TyRecord(fields, tail) => Some((fields.clone(), tail.clone())),
+            _ => None,
+        });
+        match (fields.is_empty(), tail) {
+            (true, None) => unit(),
+            (true, Some(tail)) => tail,
+            (false, tail) => TyRecord(fields, tail).into(),
+        }
+    }
+
+    pub fn variant(fields: Vec<(String, Type)>, tail: Option<Type>) -> Type {
+        let (fields, tail) = row(fields, tail, |ty| match ty {
+            TyVariant(fields, tail) => Some((fields.clone(), tail.clone())),
+            _ => None,
+        });
+        match (fields.is_empty(), tail) {
+            (true, Some(tail)) => tail,
+            (_, tail) => TyVariant(fields, tail).into(),
+        }
+    }
+
+    type Row = (Vec<(String, Type)>, Option<Type>);
+
+    fn row(
+        mut fields: Vec<(String, Type)>,
+        mut tail: Option<Type>,
+        open: impl Fn(&TypeData) -> Option<Row>,
+    ) -> Row {
+        while let Some((more, rest)) = tail.as_deref().and_then(&open) {
+            fields.extend(more);
+            tail = rest;
+        }
+        if matches!(tail.as_deref(), Some(TyTuple(items)) if items.is_empty()) {
+            tail = None;
+        }
+        fields.sort_by(|(a, _), (b, _)| a.cmp(b));
+        (fields, tail)
+    }
+
     pub fn arr(from: impl Into<Type>, to: impl Into<Type>) -> Type {
         TyArrow(from.into(), to.into()).into()
     }
@@ -91,6 +305,22 @@ pub mod ty {
         TyForall(param.into(), of.into()).into()
     }
 
+    pub fn rec(param: impl Into<Var>, of: impl Into<Type>) -> Type {
+        TyRec(param.into(), of.into()).into()
+    }
+
+    pub fn exists(param: impl Into<Var>, of: impl Into<Type>) -> Type {
+        TyExists(param.into(), of.into()).into()
+    }
+
+    pub fn qual(
+        class: impl Into<Var>,
+        arg: impl Into<Type>,
+        of: impl Into<Type>,
+    ) -> Type {
+        TyQual(class.into(), arg.into(), of.into()).into()
+    }
+
     pub fn error() -> Type {
         TyError.into()
     }
@@ -118,43 +348,357 @@ impl Deref for Term {
     type Target = TermData;
 
     fn deref(&self) -> &Self::Target {
-        &*self.0
+        &self.0
+    }
+}
+
+impl Drop for Term {
+    fn drop(&mut self) {
+        let mut stack = vec![];
+        self.take_children(&mut stack);
+        while let Some(mut term) = stack.pop() {
+            term.take_children(&mut stack);
+        }
+    }
+}
+
+impl Term {
+    fn take_children(&mut self, stack: &mut Vec<Term>) {
+        let data = match Rc::get_mut(&mut self.0) {
+            Some(data) => mem::replace(data, TmError),
+            None => return,
+        };
+        match data {
+            TmTuple(items) => stack.extend(items),
+            TmRecord(fields) => stack.extend(fields.into_iter().map(|f| f.1)),
+            TmUpdate(x, fields) => {
+                stack.push(x);
+                stack.extend(fields.into_iter().map(|f| f.1));
+            }
+            TmCase(x, arms) => {
+                stack.push(x);
+                stack.extend(arms.into_iter().map(|arm| arm.2));
+            }
+            TmInstance(instance, y) => {
+                stack.push(y);
+                stack.extend(instance.methods.into_iter().map(|m| m.1));
+            }
+            TmProj(x, _)
+            | TmField(x, _)
+            | TmInj(_, _, x)
+            | TmAbs(_, _, x)
+            | TmTyAbs(_, x)
+            | TmTyApp(x, _)
+            | TmFix(_, _, x)
+            | TmFold(_, x)
+            | TmUnfold(x)
+            | TmPack(_, x, _)
+            | TmQualAbs(_, _, x)
+            | TmClass(_, x) => stack.push(x),
+            TmWith(x, y)
+            | TmApp(x, y)
+            | TmLet(_, _, x, y)
+            | TmUnpack(_, _, x, y) => stack.extend(vec![x, y]),
+            TmHole(_) | TmVar(_) | TmError => {}
+        }
     }
 }
 
+enum Piece<'a> {
+    Text(String),
+    Term(&'a Term),
+    Arg(&'a Term),
+}
+
+use Piece::Text;
+
 impl Named for Term {
     fn pprint(&self, names: &Names) -> String {
-        match (*self.0).clone() {
-            TmUnit => "()".into(),
-            TmVar(var) => names[var].clone(),
-            TmAbs(n, t, y) => {
-                format!(
-                    "\\{}: {}. {}",
-                    names[n],
-                    t.pprint(names),
-                    y.pprint(names)
-                )
-            }
-            TmApp(f, x) => match *f {
-                TmAbs(_, _, _) => {
-                    format!("({}) {}", f.pprint(names), x.pprint(names))
+        self.print(names, None)
+    }
+}
+
+impl Term {
+    /// Prints the term with `subterm`, found by identity rather than
+    /// equality, between `open` and `close`.
+    pub fn pprint_marked(
+        &self,
+        names: &Names,
+        subterm: &Term,
+        (open, close): (&str, &str),
+    ) -> String {
+        self.print(names, Some((subterm, open, close)))
+    }
+
+    fn print(
+        &self,
+        names: &Names,
+        mark: Option<(&Term, &str, &str)>,
+    ) -> String {
+        let mut output = String::new();
+        let mut stack = vec![Piece::Term(self)];
+        while let Some(piece) = stack.pop() {
+            match piece {
+                Text(text) => output.push_str(&text),
+                Piece::Term(term) => match mark {
+                    Some((marked, open, close))
+                        if Rc::ptr_eq(&term.0, &marked.0) =>
+                    {
+                        stack.push(Text(close.into()));
+                        stack.extend(term.pieces(names).into_iter().rev());
+                        stack.push(Text(open.into()));
+                    }
+                    _ => stack.extend(term.pieces(names).into_iter().rev()),
+                },
+                Piece::Arg(term) => match **term {
+                    TmTuple(_)
+                    | TmProj(_, _)
+                    | TmWith(_, _)
+                    | TmRecord(_)
+                    | TmField(_, _)
+                    | TmUpdate(_, _)
+                    | TmHole(_)
+                    | TmVar(_) => stack.push(Piece::Term(term)),
+                    _ => stack.extend(vec![
+                        Text(")".into()),
+                        Piece::Term(term),
+                        Text("(".into()),
+                    ]),
+                },
+            }
+        }
+        output
+    }
+
+    fn pieces(&self, names: &Names) -> Vec<Piece<'_>> {
+        match &**self {
+            TmTuple(items) => {
+                let mut pieces = vec![Text("(".into())];
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        pieces.push(Text(", ".into()));
+                    }
+                    pieces.push(Piece::Term(item));
                 }
-                _ => format!("{} {}", f.pprint(names), x.pprint(names)),
-            },
-            TmTyAbs(n, y) => format!("/\\ {}. {}", names[n], y.pprint(names)),
-            TmTyApp(f, x) => match *f {
-                TmTyAbs(_, _) => {
-                    format!("({}) [{}]", f.pprint(names), x.pprint(names))
+                let close = if items.len() == 1 { ",)" } else { ")" };
+                pieces.push(Text(close.into()));
+                pieces
+            }
+            TmProj(x, i) => match **x {
+                TmTuple(_) | TmVar(_) | TmProj(_, _) => {
+                    vec![Piece::Term(x), Text(format!(".{}", i))]
                 }
-                _ => {
-                    format!("{} [{}]", f.pprint(names), x.pprint(names))
+                _ => vec![
+                    Text("(".into()),
+                    Piece::Term(x),
+                    Text(format!(").{}", i)),
+                ],
+            },
+            TmWith(l, r) => vec![
+                Text("(".into()),
+                Piece::Term(l),
+                Text(" & ".into()),
+                Piece::Term(r),
+                Text(")".into()),
+            ],
+            TmRecord(fields) => {
+                let mut pieces = vec![Text("{".into())];
+                pieces.extend(field_pieces(fields));
+                pieces.push(Text("}".into()));
+                pieces
+            }
+            TmField(x, l) => vec![Piece::Arg(x), Text(format!(".{}", l))],
+            TmUpdate(x, fields) => {
+                let mut pieces = vec![Text("{".into()), Piece::Term(x)];
+                pieces.push(Text(" | ".into()));
+                pieces.extend(field_pieces(fields));
+                pieces.push(Text("}".into()));
+                pieces
+            }
+            TmInj(t, l, x) => {
+                let header = match **t {
+                    TyHole => format!("inj {} ", l),
+                    _ => format!("inj [{}] {} ", t.pprint(names), l),
+                };
+                vec![Text(header), Piece::Arg(x)]
+            }
+            TmCase(x, arms) => {
+                let mut pieces = vec![
+                    Text("case ".into()),
+                    Piece::Arg(x),
+                    Text(" (".into()),
+                ];
+                for (i, (l, p, y)) in arms.iter().enumerate() {
+                    let sep = if i > 0 { "; " } else { "" };
+                    let arm = format!("{}{} {} -> ", sep, l, p.pprint(names));
+                    pieces.extend(vec![Text(arm), Piece::Term(y)]);
                 }
+                pieces.push(Text(")".into()));
+                pieces
+            }
+            TmHole(Some(name)) => vec![Text(format!("?{}", name))],
+            TmHole(None) => vec![Text("_".into())],
+            TmVar(var) => vec![Text(names[*var].clone())],
+            TmAbs(n, t, y) => {
+                let header = match **t {
+                    TyHole => format!("\\{}. ", names[*n]),
+                    _ => format!("\\{}: {}. ", names[*n], t.pprint(names)),
+                };
+                vec![Text(header), Piece::Term(y)]
+            }
+            TmApp(f, x) => match **f {
+                TmAbs(_, _, _)
+                | TmTyAbs(_, _)
+                | TmFix(_, _, _)
+                | TmLet(_, _, _, _)
+                | TmUnpack(_, _, _, _)
+                | TmInj(_, _, _)
+                | TmFold(_, _)
+                | TmUnfold(_) => vec![
+                    Text("(".into()),
+                    Piece::Term(f),
+                    Text(") ".into()),
+                    Piece::Term(x),
+                ],
+                _ => vec![Piece::Term(f), Text(" ".into()), Piece::Arg(x)],
             },
-            TmError => "ERROR".into(),
+            TmTyAbs(n, y) => {
+                vec![Text(format!("/\\ {}. ", names[*n])), Piece::Term(y)]
+            }
+            TmTyApp(f, x) => match **f {
+                TmAbs(_, _, _)
+                | TmTyAbs(_, _)
+                | TmFix(_, _, _)
+                | TmLet(_, _, _, _)
+                | TmUnpack(_, _, _, _)
+                | TmInj(_, _, _)
+                | TmFold(_, _)
+                | TmUnfold(_) => vec![
+                    Text("(".into()),
+                    Piece::Term(f),
+                    Text(format!(") [{}]", x.pprint(names))),
+                ],
+                _ => vec![
+                    Piece::Term(f),
+                    Text(format!(" [{}]", x.pprint(names))),
+                ],
+            },
+            TmLet(p, t, x, y) => {
+                let header = match **t {
+                    TyHole => format!("let {} = ", p.pprint(names)),
+                    _ => format!(
+                        "let {}: {} = ",
+                        p.pprint(names),
+                        t.pprint(names)
+                    ),
+                };
+                vec![
+                    Text(header),
+                    Piece::Term(x),
+                    Text("; ".into()),
+                    Piece::Term(y),
+                ]
+            }
+            TmFix(n, t, y) => {
+                let header = match **t {
+                    TyHole => format!("fix {}. ", names[*n]),
+                    _ => format!("fix {}: {}. ", names[*n], t.pprint(names)),
+                };
+                vec![Text(header), Piece::Term(y)]
+            }
+            TmFold(t, x) => vec![
+                Text(format!("fold [{}] ", t.pprint(names))),
+                Piece::Arg(x),
+            ],
+            TmUnfold(x) => vec![Text("unfold ".into()), Piece::Arg(x)],
+            TmPack(t, x, u) => vec![
+                Text(format!("pack [{}, ", t.pprint(names))),
+                Piece::Term(x),
+                Text(format!("] as {}", u.pprint(names))),
+            ],
+            TmUnpack(a, p, x, y) => vec![
+                Text(format!("unpack [{}, {}] = ", names[*a], p.pprint(names))),
+                Piece::Term(x),
+                Text("; ".into()),
+                Piece::Term(y),
+            ],
+            TmQualAbs(c, t, y) => vec![
+                Text(format!("{} {} => ", names[*c], t.pprint_arg(names))),
+                Piece::Term(y),
+            ],
+            TmClass(class, y) => {
+                vec![Text(format!("{}; ", class.pprint(names))), Piece::Term(y)]
+            }
+            TmInstance(instance, y) => vec![
+                Text(format!("{}; ", instance.pprint(names))),
+                Piece::Term(y),
+            ],
+            TmError => vec![Text("ERROR".into())],
+        }
+    }
+}
+
+fn field_pieces(fields: &[(String, Term)]) -> Vec<Piece<'_>> {
+    let mut pieces = vec![];
+    for (i, (l, x)) in fields.iter().enumerate() {
+        let sep = if i > 0 { ", " } else { "" };
+        pieces.extend(vec![Text(format!("{}{} = ", sep, l)), Piece::Term(x)]);
+    }
+    pieces
+}
+
+impl Named for Pattern {
+    fn pprint(&self, names: &Names) -> String {
+        match self {
+            PtWild => "_".into(),
+            PtTuple(items) => tuple(items.iter().map(|p| p.pprint(names))),
+            PtLeft(p) => format!("({} &)", p.pprint(names)),
+            PtRight(p) => format!("(& {})", p.pprint(names)),
+            PtVar(var) => names[*var].clone(),
         }
     }
 }
 
+impl Named for Class {
+    fn pprint(&self, names: &Names) -> String {
+        format!(
+            "class {} {} = {}",
+            names[self.name],
+            names[self.param],
+            self.methods
+                .iter()
+                .map(|(m, t)| format!("{}: {}", names[*m], t.pprint(names)))
+                .join(", ")
+        )
+    }
+}
+
+impl Named for Instance {
+    fn pprint(&self, names: &Names) -> String {
+        let params = self
+            .params
+            .iter()
+            .map(|p| format!("{} => ", names[*p]))
+            .join("");
+        let context = self
+            .context
+            .iter()
+            .map(|(c, t)| format!("{} {} => ", names[*c], t.pprint_arg(names)))
+            .join("");
+        format!(
+            "instance {}{}{} {} = {}",
+            params,
+            context,
+            names[self.class],
+            self.head.pprint_arg(names),
+            self.methods
+                .iter()
+                .map(|(m, y)| format!("{} = {}", names[*m], y.pprint(names)))
+                .join(", ")
+        )
+    }
+}
+
 impl From<Var> for Type {
     fn from(var: Var) -> Self {
         ty::var(var)
@@ -177,18 +721,31 @@ impl Deref for Type {
     type Target = TypeData;
 
     fn deref(&self) -> &Self::Target {
-        &*self.0
+        &self.0
     }
 }
 
 impl Named for Type {
     fn pprint(&self, names: &Names) -> String {
         match (**self).clone() {
-            TyUnit => "()".into(),
+            TyTuple(items) => tuple(items.iter().map(|t| t.pprint(names))),
+            TyWith(l, r) => {
+                format!("{} & {}", l.pprint_arg(names), r.pprint_arg(names))
+            }
+            TyRecord(fields, tail) => {
+                format!("{{{}}}", row_of(&fields, &tail, names))
+            }
+            TyVariant(fields, tail) => {
+                format!("[{}]", row_of(&fields, &tail, names))
+            }
             TyHole => "_".into(),
             TyVar(var) => names[var].clone(),
             TyArrow(f, t) => match *f {
-                TyUnit | TyHole | TyVar(_) => {
+                TyTuple(_)
+                | TyRecord(_, _)
+                | TyVariant(_, _)
+                | TyHole
+                | TyVar(_) => {
                     format!("{} -> {}", f.pprint(names), t.pprint(names))
                 }
                 _ => {
@@ -198,7 +755,60 @@ impl Named for Type {
             TyForall(n, y) => {
                 format!("/\\ {} => {}", names[n], y.pprint(names))
             }
+            TyRec(n, y) => format!("rec {} => {}", names[n], y.pprint(names)),
+            TyExists(n, y) => {
+                format!("exists {} => {}", names[n], y.pprint(names))
+            }
+            TyQual(c, t, y) => format!(
+                "{} {} => {}",
+                names[c],
+                t.pprint_arg(names),
+                y.pprint(names)
+            ),
             TyError => "ERROR".into(),
         }
     }
 }
+
+fn fields_of<T: Named>(
+    fields: &[(String, T)],
+    sep: &str,
+    names: &Names,
+) -> String {
+    fields
+        .iter()
+        .map(|(l, x)| format!("{}{}{}", l, sep, x.pprint(names)))
+        .join(", ")
+}
+
+fn row_of(
+    fields: &[(String, Type)],
+    tail: &Option<Type>,
+    names: &Names,
+) -> String {
+    let fields = fields_of(fields, " : ", names);
+    match tail {
+        Some(tail) => format!("{} | {}", fields, tail.pprint(names)),
+        None => fields,
+    }
+}
+
+fn tuple(items: impl ExactSizeIterator<Item = String>) -> String {
+    match items.len() {
+        1 => format!("({},)", items.format("")),
+        _ => format!("({})", items.format(", ")),
+    }
+}
+
+impl Type {
+    fn pprint_arg(&self, names: &Names) -> String {
+        match **self {
+            TyTuple(_)
+            | TyRecord(_, _)
+            | TyVariant(_, _)
+            | TyHole
+            | TyVar(_) => self.pprint(names),
+            _ => format!("({})", self.pprint(names)),
+        }
+    }
+}
@@ -0,0 +1,112 @@
+use crate::{erase::*, prelude::*};
+
+/// A piece of the printed form of a compiled closure.
+pub enum Chunk {
+    Text(String),
+    /// The captured value with the given index, which goes in parentheses
+    /// if it is an injection or a function and `arg` is set.
+    Hole {
+        index: usize,
+        arg: bool,
+    },
+}
+
+/// Splits the printed source of a closure around its captured variables, so
+/// that compiled programs print closures the way evaluation reads them back.
+///
+/// Each captured variable is replaced by an injection with a marker label.
+/// The printer puts parentheses around those markers exactly where it would
+/// put them around a captured injection or function, and nothing else that a
+/// value can be needs them.
+pub fn template(
+    source: &Erased,
+    captures: &[Var],
+    names: &Names,
+) -> Vec<Chunk> {
+    let marked = captures.iter().enumerate().fold(
+        source.clone(),
+        |source, (index, var)| {
+            let marker = format!("{}{}{}", MARK, index, MARK);
+            let marker = EInj(marker, ETuple(vec![]).into()).into();
+            subst(marker, source, *var)
+        },
+    );
+    let text = marked.pprint(names);
+    let mut chunks = vec![];
+    let mut rest = text.as_str();
+    let open = format!("inj {}", MARK);
+    while let Some(start) = rest.find(&open) {
+        let label = &rest[start + open.len()..];
+        let end = label.find(MARK).unwrap();
+        let index = label[..end].parse().unwrap();
+        let after = &label[end + MARK.len_utf8() + " ()".len()..];
+        let arg = rest[..start].ends_with('(') && after.starts_with(')');
+        let (before, after) = match arg {
+            true => (&rest[..start - 1], &after[1..]),
+            false => (&rest[..start], after),
+        };
+        if !before.is_empty() {
+            chunks.push(Chunk::Text(before.into()));
+        }
+        chunks.push(Chunk::Hole { index, arg });
+        rest = after;
+    }
+    if !rest.is_empty() {
+        chunks.push(Chunk::Text(rest.into()));
+    }
+    chunks
+}
+
+/// Delimits the indices in marker labels, and cannot occur in source text.
+const MARK: char = '\u{1}';
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn show(chunks: &[Chunk], values: &[(&str, bool)]) -> String {
+        let mut output = String::new();
+        for chunk in chunks {
+            match chunk {
+                Chunk::Text(text) => output.push_str(text),
+                Chunk::Hole { index, arg } => {
+                    let (value, atomic) = values[*index];
+                    match *arg && !atomic {
+                        true => output += &format!("({})", value),
+                        false => output.push_str(value),
+                    }
+                }
+            }
+        }
+        output
+    }
+
+    #[test]
+    fn holes() {
+        let mut names = Names::default();
+        let (f, x, y) = (
+            names.push("f".into()),
+            names.push("x".into()),
+            names.push("y".into()),
+        );
+        let var = |v| Erased::from(EVar(v));
+        let source = EAbs(
+            x,
+            ETuple(vec![
+                EApp(var(f), var(y)).into(),
+                var(y),
+                EInj("A".into(), var(f)).into(),
+            ])
+            .into(),
+        )
+        .into();
+        let chunks = template(&source, &[f, y], &names);
+        let values = [("\\z. z", false), ("inj B ()", false)];
+        assert_eq!(
+            show(&chunks, &values),
+            "\\x. ((\\z. z) inj B (), inj B (), inj A (\\z. z))"
+        );
+        let values = [("g", true), ("()", true)];
+        assert_eq!(show(&chunks, &values), "\\x. (g (), (), inj A g)");
+    }
+}
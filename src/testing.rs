@@ -0,0 +1,117 @@
+use std::fs;
+
+use crate::{
+    ident::identify, modules::Loader, parser::parse, prelude::*, syntax::Term,
+    typeck::typeck,
+};
+
+/// Church numerals with addition and multiplication, to be followed by a
+/// term that uses them.
+pub const CHURCH: &str = "
+let zero = r => s: (r -> r) -> z: r -> z
+let succ = n: (r => (r -> r) -> r -> r) -> r => s: (r -> r) -> z: r -> s (n [r] s z)
+let add = m: (r => (r -> r) -> r -> r) -> n: (r => (r -> r) -> r -> r) ->
+    r => s: (r -> r) -> z: r -> m [r] s (n [r] s z)
+let mul = m: (r => (r -> r) -> r -> r) -> n: (r => (r -> r) -> r -> r) ->
+    r => s: (r -> r) -> m [r] (n [r] s)
+";
+
+/// Runs a million tail calls, allocating a closure for each, after `CHURCH`.
+pub const MILLION: &str = "
+let ten = succ (succ (succ (succ (succ (succ (succ (succ (succ (succ zero)))))))))
+let million = mul (mul ten (mul ten ten)) (mul ten (mul ten ten))
+million [() -> ()] (k: (() -> ()) -> x: () -> k x) (x: () -> x) ()
+";
+
+/// A list of a hundred thousand elements, and a fold over it that is not tail
+/// recursive and so nests as many calls, after `CHURCH`.
+pub const DEEP: &str = "
+data List = [Nil : (), Cons : ((), List)]
+let ten = succ (succ (succ (succ (succ (succ (succ (succ (succ (succ zero)))))))))
+let count = mul (mul ten ten) (mul ten (mul ten ten))
+let xs = count [List] (ys: List -> Cons ((), ys)) (Nil ())
+let rec length: List -> () = ys: List -> case (unfold ys) (
+    Nil _ -> ()
+    Cons (_, rest) -> (length rest, ()).0
+)
+";
+
+/// Programs that cover what the examples do not: shadowing, captures,
+/// injections as arguments and labels that are special to a backend.
+const OTHERS: [&str; 11] = [
+    "let f = a => x: a -> inj [[Just : a, Nothing : ()]] Just x; f [()]",
+    "let rec f: () -> () = x: () -> f x; y: () -> f",
+    "let r = { a = (), b = ((),) }; (x: () -> ({ r | a = x }, { () } & r))",
+    "let p = (x: () -> x) & ((), ((),)); let (& (a, b)) = p; (b, a)",
+    "case inj [[A : ((), ()), B : ()]] A ((), ()) (A (x, _) -> x; B y -> y)",
+    "let x = (); let f = y: () -> (x, y); let x = ((),); (f (), x)",
+    "let f = x: [A : (), B : ()] -> y: () -> (x, y); (f (inj [[A : (), B : ()]] B ()), {})",
+    "let g = x: () -> x; let h = y: () -> g (g y); (h, x: () -> g)",
+    "{ __proto__ = ((),), b = () }",
+    "unpack [t, (x, f)] = pack [(), ((), y: () -> y)] as exists a => (a, a -> ()); f x",
+    "let rec loop: () -> () = x: () -> loop x; a => loop ()",
+];
+
+/// Church numerals bound to `n`, and what to compute with them.
+const NUMERALS: [(usize, &str); 3] = [
+    (2, "mul n (add n (succ n))"),
+    (3, "n [()] (x: () -> x) ()"),
+    (3, "mul n n [((),)] (x: ((),) -> x)"),
+];
+
+/// Examples that sketch syntax the language does not have yet.
+const SKETCHES: [&str; 2] = ["main.od", "yield.od"];
+
+/// Links, identifies and type checks `input` without the Prelude, with the
+/// examples on the search path, and panics on any error.
+pub fn named(input: &str) -> (Term, Names) {
+    let mut loader = Loader::new(vec!["examples".into()], false);
+    let linked = loader.link(parse(input).into_result().unwrap());
+    let (term, mut names, _) = identify(linked.into_result().unwrap())
+        .into_result()
+        .unwrap();
+    (typeck(term, &mut names).unwrap().0, names)
+}
+
+pub fn checked(input: &str) -> Term {
+    named(input).0
+}
+
+/// `body` with the Church numeral for `n` bound to `n`.
+pub fn church(n: usize, body: &str) -> Term {
+    numeral(n, body).0
+}
+
+fn numeral(n: usize, body: &str) -> (Term, Names) {
+    let numeral = "succ (".repeat(n) + "zero" + &")".repeat(n);
+    named(&format!("{}let n = {}\n{}", CHURCH, numeral, body))
+}
+
+/// Every program in `examples/`, in the order of the file names.
+pub fn examples() -> Vec<(Term, Names)> {
+    let mut files = fs::read_dir("examples")
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| {
+            let name = path.file_name().unwrap().to_str().unwrap();
+            name.ends_with(".od") && !SKETCHES.contains(&name)
+        })
+        .collect::<Vec<_>>();
+    files.sort();
+    files
+        .iter()
+        .map(|path| named(&fs::read_to_string(path).unwrap()))
+        .collect()
+}
+
+/// The examples followed by other programs, for the tests that check that
+/// each way of running a program agrees with evaluation.
+pub fn programs() -> Vec<(Term, Names)> {
+    let others = OTHERS.iter().map(|src| named(src));
+    let numerals = NUMERALS.iter().map(|(n, body)| numeral(*n, body));
+    examples()
+        .into_iter()
+        .chain(others)
+        .chain(numerals)
+        .collect()
+}
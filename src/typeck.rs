@@ -1,34 +1,120 @@
-use std::collections::{HashMap, VecDeque};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    vec,
+};
 
 use itertools::Itertools;
 
 use crate::{prelude::*, syntax::*};
 
-pub fn typeck(term: Term) -> Result<(), TypeckErrors> {
-    let MultiResult { result: _, collect } =
-        Typeck::default().typeck_term(term);
-    if collect.is_empty() {
-        Ok(())
-    } else {
-        Err(collect)
+pub fn typeck(
+    term: Term,
+    names: &mut Names,
+) -> Result<(Term, Holes), TypeckErrors> {
+    let mut typeck = Typeck::new(names);
+    let MultiResult {
+        result: (term, _),
+        mut collect,
+    } = typeck.typeck_term(term);
+    collect.retain(|err| !err.is_cascade());
+    debug_assert!(
+        !collect.is_empty() || closed(&term, &mut vec![]),
+        "unbound variable in output"
+    );
+    let result = (term, typeck.holes);
+    MultiResult { result, collect }.into_result()
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Hole {
+    pub name: Option<String>,
+    pub goal: Type,
+    /// The type variables in scope, outermost first.
+    pub types: Vec<Var>,
+    pub context: Vec<(Var, Type)>,
+}
+
+pub type Holes = Vec<Hole>;
+
+impl Named for Hole {
+    fn pprint(&self, names: &Names) -> String {
+        let name = match &self.name {
+            Some(name) => format!("?{}", name),
+            None => "_".into(),
+        };
+        let types = self
+            .types
+            .iter()
+            .map(|v| format!("\n  {} : type", names[*v]));
+        let context = self
+            .context
+            .iter()
+            .map(|(v, t)| format!("\n  {} : {}", names[*v], t.pprint(names)));
+        let context = types.chain(context).join("");
+        format!("Hole {} : {}{}", name, self.goal.pprint(names), context)
+    }
+}
+
+impl Named for Holes {
+    fn pprint(&self, names: &Names) -> String {
+        self.iter().map(|hole| hole.pprint(names)).join("\n")
     }
 }
 
 pub fn subst_type(body: Type, with: Type, what: Var) -> Type {
     match (*body).clone() {
-        TyUnit => body,
-        TyHole => body,
+        TyTuple(items) => ty::tuple(
+            items
+                .into_iter()
+                .map(|item| subst_type(item, with.clone(), what))
+                .collect(),
+        ),
+        TyWith(l, r) => ty::with(
+            subst_type(l, with.clone(), what),
+            subst_type(r, with, what),
+        ),
+        TyRecord(fields, tail) => {
+            let (fields, tail) = subst_row(fields, tail, with, what);
+            ty::record(fields, tail)
+        }
+        TyVariant(fields, tail) => {
+            let (fields, tail) = subst_row(fields, tail, with, what);
+            ty::variant(fields, tail)
+        }
+        TyHole | TyError => body,
         TyVar(var) if var == what => with,
         TyVar(_) => body,
         TyArrow(from, to) => ty::arr(
             subst_type(from, with.clone(), what),
             subst_type(to, with, what),
         ),
+        TyForall(n, _) | TyRec(n, _) | TyExists(n, _) if n == what => body,
         TyForall(n, x) => ty::forall(n, subst_type(x, with, what)),
-        TyError => unreachable!(),
+        TyRec(n, x) => ty::rec(n, subst_type(x, with, what)),
+        TyExists(n, x) => ty::exists(n, subst_type(x, with, what)),
+        TyQual(c, t, x) => ty::qual(
+            c,
+            subst_type(t, with.clone(), what),
+            subst_type(x, with, what),
+        ),
     }
 }
 
+type Row = Vec<(String, Type)>;
+
+fn subst_row(
+    fields: Vec<(String, Type)>,
+    tail: Option<Type>,
+    with: Type,
+    what: Var,
+) -> (Row, Option<Type>) {
+    let fields = fields
+        .into_iter()
+        .map(|(label, ty)| (label, subst_type(ty, with.clone(), what)))
+        .collect();
+    (fields, tail.map(|tail| subst_type(tail, with, what)))
+}
+
 pub type TypeckErrors = VecDeque<TypeckError>;
 
 impl Named for TypeckErrors {
@@ -42,10 +128,58 @@ pub enum TypeckError {
     NotAFunction(Type),
     NotAForall(Type),
     NotEqual(Type, Type),
+    NotATuple(Type, usize),
+    NoComponent(Type, usize),
+    NotAWith(Type),
+    NotRecursive(Type),
+    MissingAnnotation(Var),
+    NotAnExists(Type),
+    EscapingType(Var, Type),
+    NoField(Type, String),
+    DuplicateField(String),
+    NotARow(Type),
+    NotAVariant(Type),
+    MissingCase(Type, String),
+    NonExhaustive(Type),
+    NotAClass(Var),
+    NotAMethod(Var, Var),
+    MissingMethod(Var, Type, Var),
+    DuplicateMethod(Var),
+    NoInstance(Var, Type),
+    OverlappingInstances(Var, Type, Type),
+    UnboundVariable(Var),
+    NotATerm(Var),
+    NotAType(Var),
 }
 
 use TypeckError::*;
 
+impl TypeckError {
+    fn is_cascade(&self) -> bool {
+        match self {
+            NotAFunction(t)
+            | NotAForall(t)
+            | NotATuple(t, _)
+            | NoComponent(t, _)
+            | NotAWith(t)
+            | NotRecursive(t)
+            | NotAnExists(t)
+            | EscapingType(_, t)
+            | NoField(t, _)
+            | NotARow(t)
+            | NotAVariant(t)
+            | MissingCase(t, _)
+            | NonExhaustive(t)
+            | MissingMethod(_, t, _)
+            | NoInstance(_, t) => erroneous(t),
+            NotEqual(a, b) | OverlappingInstances(_, a, b) => {
+                erroneous(a) || erroneous(b)
+            }
+            _ => false,
+        }
+    }
+}
+
 impl Named for TypeckError {
     fn pprint(&self, names: &Names) -> String {
         match self {
@@ -60,65 +194,2033 @@ impl Named for TypeckError {
             NotAForall(f) => {
                 format!("Must be a forall: '{}'", f.pprint(names))
             }
+            NotATuple(t, n) => format!(
+                "Must be a tuple of {} elements: '{}'",
+                n,
+                t.pprint(names)
+            ),
+            NoComponent(t, i) => format!(
+                "Must be a tuple with component {}: '{}'",
+                i,
+                t.pprint(names)
+            ),
+            NotAWith(t) => {
+                format!("Must be an additive pair: '{}'", t.pprint(names))
+            }
+            NotRecursive(t) => {
+                format!("Must be a recursive type: '{}'", t.pprint(names))
+            }
+            MissingAnnotation(v) => {
+                format!("Recursive '{}' needs a type annotation", names[*v])
+            }
+            NotAnExists(t) => {
+                format!("Must be an existential: '{}'", t.pprint(names))
+            }
+            EscapingType(v, t) => format!(
+                "Type '{}' escapes its scope in '{}'",
+                names[*v],
+                t.pprint(names)
+            ),
+            NoField(t, l) => {
+                format!("Type '{}' has no field '{}'", t.pprint(names), l)
+            }
+            DuplicateField(l) => format!("Field '{}' is defined twice", l),
+            NotARow(t) => format!("Must be a row: '{}'", t.pprint(names)),
+            NotAVariant(t) => {
+                format!("Must be a variant: '{}'", t.pprint(names))
+            }
+            MissingCase(t, l) => {
+                format!("Case '{}' of '{}' is not handled", l, t.pprint(names))
+            }
+            NonExhaustive(t) => format!(
+                "Cannot handle every case of an open variant: '{}'",
+                t.pprint(names)
+            ),
+            NotAClass(c) => format!("Must be a class: '{}'", names[*c]),
+            NotAMethod(m, c) => format!(
+                "'{}' is not a method of class '{}'",
+                names[*m], names[*c]
+            ),
+            MissingMethod(c, t, m) => format!(
+                "Instance '{} {}' does not define method '{}'",
+                names[*c],
+                t.pprint(names),
+                names[*m]
+            ),
+            DuplicateMethod(m) => {
+                format!("Method '{}' is defined twice", names[*m])
+            }
+            NoInstance(c, t) => {
+                format!("No instance for '{} {}'", names[*c], t.pprint(names))
+            }
+            OverlappingInstances(c, a, b) => format!(
+                "Overlapping instances: '{} {}', '{} {}'",
+                names[*c],
+                a.pprint(names),
+                names[*c],
+                b.pprint(names)
+            ),
+            UnboundVariable(v) => {
+                format!("Variable '{}' is not in scope", names[*v])
+            }
+            NotATerm(v) => {
+                format!("Type '{}' is used as a term", names[*v])
+            }
+            NotAType(v) => {
+                format!("Term '{}' is used as a type", names[*v])
+            }
         }
     }
 }
 
-#[derive(Default)]
-struct Typeck(HashMap<Var, Type>, AlphaGen);
+struct Typeck<'a> {
+    vars: HashMap<Var, Type>,
+    classes: HashMap<Var, Class>,
+    instances: Vec<(Var, Instance)>,
+    givens: Vec<(Var, Var, Type)>,
+    scope: Vec<Var>,
+    types: Vec<Var>,
+    holes: Holes,
+    names: &'a mut Names,
+}
+
+type TypeckResult = MultiResult<(Term, Type), TypeckErrors>;
+
+type TypeResult = MultiResult<Type, TypeckErrors>;
+
+type ElabResult = MultiResult<Term, TypeckErrors>;
+
+type Checked = MultiResult<Vec<(Term, Type)>, TypeckErrors>;
+
+type CheckedFields = MultiResult<(Vec<(String, Term)>, Row), TypeckErrors>;
+
+type Cont<'a> = Box<dyn FnOnce(&mut Typeck<'a>, TypeckResult) -> Step<'a> + 'a>;
+
+/// A suspended type checking computation. Subterms are checked by the loop in
+/// `Typeck::typeck_term` over an explicit stack of continuations, so deeply
+/// nested terms do not grow the native stack.
+enum Step<'a> {
+    Done(TypeckResult),
+    Check(Term, Option<Type>, Cont<'a>),
+}
+
+impl<'a> Step<'a> {
+    fn check(
+        term: Term,
+        expected: Option<Type>,
+        cont: impl FnOnce(&mut Typeck<'a>, TypeckResult) -> Step<'a> + 'a,
+    ) -> Self {
+        Step::Check(term, expected, Box::new(cont))
+    }
 
-type TypeckResult = MultiResult<Type, VecDeque<TypeckError>>;
+    fn term(term: Term, expected: Option<Type>) -> Self {
+        Step::check(term, expected, |_, result| Step::Done(result))
+    }
+
+    fn then(
+        self,
+        typeck: &mut Typeck<'a>,
+        next: impl FnOnce(&mut Typeck<'a>, TypeckResult) -> Step<'a> + 'a,
+    ) -> Self {
+        match self {
+            Step::Done(result) => next(typeck, result),
+            Step::Check(term, expected, cont) => {
+                Step::check(term, expected, move |this, result| {
+                    cont(this, result).then(this, next)
+                })
+            }
+        }
+    }
+
+    fn map(self, f: impl FnOnce(TypeckResult) -> TypeckResult + 'a) -> Self {
+        match self {
+            Step::Done(result) => Step::Done(f(result)),
+            Step::Check(term, expected, cont) => {
+                Step::check(term, expected, move |this, result| {
+                    cont(this, result).map(f)
+                })
+            }
+        }
+    }
+}
+
+fn after<'a, T>(
+    result: MultiResult<T, TypeckErrors>,
+    next: impl FnOnce(T) -> Step<'a>,
+) -> Step<'a> {
+    let MultiResult { result, collect } = result;
+    if collect.is_empty() {
+        return next(result);
+    }
+    next(result).map(|mut result| {
+        let mut errors = collect;
+        errors.append(&mut result.collect);
+        result.collect = errors;
+        result
+    })
+}
+
+struct Case {
+    term: Term,
+    ty: Type,
+    cases: Row,
+    arms: vec::IntoIter<(String, Pattern, Term)>,
+    result_type: Option<Type>,
+    elaborated: Vec<(String, Pattern, Term)>,
+    errors: TypeckErrors,
+}
+
+impl<'a> Typeck<'a> {
+    fn new(names: &'a mut Names) -> Self {
+        Self {
+            vars: HashMap::new(),
+            classes: HashMap::new(),
+            instances: vec![],
+            givens: vec![],
+            scope: vec![],
+            types: vec![],
+            holes: vec![],
+            names,
+        }
+    }
 
-impl Typeck {
     fn typeck_term(&mut self, term: Term) -> TypeckResult {
+        let mut stack = vec![];
+        let mut step = self.check_term(term, None);
+        loop {
+            step = match step {
+                Step::Check(term, expected, cont) => {
+                    stack.push(cont);
+                    self.check_term(term, expected)
+                }
+                Step::Done(result) => match stack.pop() {
+                    Some(cont) => cont(self, result),
+                    None => return result,
+                },
+            }
+        }
+    }
+
+    fn check_term(&mut self, term: Term, expected: Option<Type>) -> Step<'a> {
+        match &*term {
+            TmHole(name) => Step::Done(
+                self.hole(name.clone(), expected.unwrap_or_else(ty::hole)),
+            ),
+            _ => self.typeck_step(term, expected),
+        }
+    }
+
+    /// Checks one step of `term`. The expected type only reaches holes, which
+    /// take it as their type, and the bodies of abstractions around them.
+    fn typeck_step(&mut self, term: Term, expected: Option<Type>) -> Step<'a> {
         match (*term).clone() {
-            TmUnit => ty::unit().into(),
-            TmVar(v) => self.get_or_alpha(v).into(),
-            TmAbs(v, t, y) => self
-                .insert(v, t.clone())
-                .typeck_term(y)
-                .map(move |y| ty::arr(t, y)),
-            TmApp(f, x) => (self.typeck_term(f) + self.typeck_term(x))
-                .then(|(f, x)| assert_app(f, x)),
-            TmTyAbs(n, x) => self.typeck_term(x).map(move |x| ty::forall(n, x)),
-            TmTyApp(f, t) => {
-                self.typeck_term(f).then(move |f| assert_ty_app(f, t))
+            TmTuple(items) => self.sequence(items, |_, items| {
+                Step::Done(items.map(|items| {
+                    let (items, types) = items.into_iter().unzip();
+                    (de::tuple(items), ty::tuple(types))
+                }))
+            }),
+            TmProj(x, i) => Step::check(x, None, move |_, x| {
+                Step::Done(x.then(|(x, xt)| {
+                    let term = de::proj(x, i);
+                    match &*xt {
+                        TyTuple(types) if i < types.len() => {
+                            (term, types[i].clone()).into()
+                        }
+                        _ => TypeckResult::new(
+                            (term, ty::error()),
+                            NoComponent(xt, i),
+                        ),
+                    }
+                }))
+            }),
+            TmWith(l, r) => Step::check(l, None, |_, l| {
+                Step::check(r, None, |_, r| {
+                    Step::Done((l + r).map(|((l, lt), (r, rt))| {
+                        (de::with(l, r), ty::with(lt, rt))
+                    }))
+                })
+            }),
+            TmRecord(fields) => self.typeck_fields(fields, |_, fields| {
+                Step::Done(fields.then(|(fields, types)| {
+                    let mut result = TypeckResult::from((
+                        de::record(fields),
+                        ty::record(types.clone(), None),
+                    ));
+                    let mut labels = HashSet::new();
+                    for (label, _) in &types {
+                        if !labels.insert(label) {
+                            result += DuplicateField(label.clone());
+                        }
+                    }
+                    result
+                }))
+            }),
+            TmField(x, l) => Step::check(x, None, |_, x| {
+                Step::Done(x.then(|(x, xt)| {
+                    let ty = match &*xt {
+                        TyRecord(fields, _) => find_field(fields, &l),
+                        _ => None,
+                    };
+                    let term = de::field(x, l.clone());
+                    match ty {
+                        Some(ty) => (term, ty).into(),
+                        None => TypeckResult::new(
+                            (term, ty::error()),
+                            NoField(xt, l),
+                        ),
+                    }
+                }))
+            }),
+            TmUpdate(x, fields) => Step::check(x, None, |this, x| {
+                this.typeck_fields(fields, |_, fields| {
+                    Step::Done((x + fields).then(
+                        |((x, xt), (fields, types))| {
+                            let (mut row, tail) = match &*xt {
+                                TyRecord(row, tail) => {
+                                    (row.clone(), tail.clone())
+                                }
+                                _ => (vec![], None),
+                            };
+                            let mut errors = TypeckErrors::new();
+                            for (label, ty) in types {
+                                match row.iter_mut().find(|(l, _)| *l == label)
+                                {
+                                    Some(field) => field.1 = ty,
+                                    None => errors
+                                        .push_back(NoField(xt.clone(), label)),
+                                }
+                            }
+                            let term = de::update(x, fields);
+                            MultiResult {
+                                result: (term, ty::record(row, tail)),
+                                collect: errors,
+                            }
+                        },
+                    ))
+                })
+            }),
+            TmInj(t, l, x) => {
+                let ty = match &*t {
+                    TyVariant(cases, _) => find_field(cases, &l),
+                    _ => None,
+                };
+                Step::check(x, ty.clone(), |this, x| {
+                    Step::Done((x + this.annotation(t.clone())).then(
+                        |((x, xt), e)| {
+                            let mut result = TypeckResult::from((
+                                de::inj(e, l.clone(), x),
+                                t.clone(),
+                            ));
+                            match ty {
+                                Some(ty) if equal(&ty, &xt) => {}
+                                Some(ty) => result += NotEqual(ty, xt),
+                                None => result += NoField(t, l),
+                            }
+                            result
+                        },
+                    ))
+                })
             }
-            TmError => unreachable!(),
+            TmCase(x, arms) => Step::check(x, None, |this, x| {
+                after(x, |(x, xt)| this.typeck_case(x, xt, arms))
+            }),
+            TmHole(name) => Step::Done(self.hole(name, ty::hole())),
+            TmVar(v) => Step::Done(match self.vars.get(&v).cloned() {
+                Some(ty) => self.discharge(term, ty),
+                None if self.types.contains(&v)
+                    || self.classes.contains_key(&v) =>
+                {
+                    TypeckResult::new((term, ty::error()), NotATerm(v))
+                }
+                None => {
+                    TypeckResult::new((term, ty::error()), UnboundVariable(v))
+                }
+            }),
+            TmAbs(v, t, y) => {
+                let e = self.annotation(t.clone());
+                let expected = match expected.as_deref() {
+                    Some(TyArrow(_, to)) => Some(to.clone()),
+                    _ => None,
+                };
+                self.scoped(|this| {
+                    this.insert(v, t.clone());
+                    Step::term(y, expected)
+                })
+                .map(move |y| {
+                    (e + y).map(move |(e, (y, yt))| {
+                        (de::abs(v, e, y), ty::arr(t, yt))
+                    })
+                })
+            }
+            TmApp(f, x) => Step::check(f, None, |_, f| {
+                after(f, |(f, ft)| {
+                    let expected = match &*ft {
+                        TyArrow(from, _) => Some(from.clone()),
+                        _ => None,
+                    };
+                    let unknown = matches!(*f, TmHole(_));
+                    Step::check(x, expected, move |this, x| {
+                        Step::Done(x.then(|(x, xt)| {
+                            if unknown {
+                                return (de::app(f, x), ty::hole()).into();
+                            }
+                            assert_app(ft, xt)
+                                .then(|t| this.discharge(de::app(f, x), t))
+                        }))
+                    })
+                })
+            }),
+            TmTyAbs(n, x) => self
+                .scoped(|this| {
+                    this.types.push(n);
+                    let expected = match expected.as_deref() {
+                        Some(TyForall(m, t)) => {
+                            Some(subst_type(t.clone(), ty::var(n), *m))
+                        }
+                        _ => None,
+                    };
+                    Step::term(x, expected)
+                })
+                .map(move |x| {
+                    x.map(move |(x, xt)| (de::ty_abs(n, x), ty::forall(n, xt)))
+                }),
+            TmTyApp(f, t) => Step::check(f, None, |this, f| {
+                Step::Done((f + this.annotation(t.clone())).then(
+                    |((f, ft), e)| {
+                        assert_ty_app(ft, t)
+                            .then(|t| this.discharge(de::ty_app(f, e), t))
+                    },
+                ))
+            }),
+            TmLet(p, t, x, y) => {
+                let expected = Some(t.clone()).filter(|t| **t != TyHole);
+                Step::check(x, expected, |this, x| {
+                    let x = x + this.annotation(t.clone());
+                    after(x, |((x, xt), e)| {
+                        this.scoped(|this| {
+                            let ty = match *t != TyHole && erroneous(&xt) {
+                                true => t.clone(),
+                                false => xt.clone(),
+                            };
+                            let mut bound = this.bind_pattern(&p, ty);
+                            if *t != TyHole && !equal(&t, &xt) {
+                                bound += NotEqual(t, xt);
+                            }
+                            after(bound, |_| Step::term(y, None))
+                        })
+                        .map(|y| y.map(|(y, yt)| (de::r#let(p, e, x, y), yt)))
+                    })
+                })
+            }
+            TmFix(f, t, x) => {
+                if *t == TyHole {
+                    let result =
+                        TypeckResult::new((term, t), MissingAnnotation(f));
+                    return Step::Done(result);
+                }
+                let e = self.annotation(t.clone());
+                self.scoped(|this| {
+                    this.insert(f, t.clone());
+                    Step::term(x, Some(t.clone()))
+                })
+                .map(move |x| {
+                    (e + x).then(|(e, (x, xt))| {
+                        let mut result =
+                            TypeckResult::from((de::fix(f, e, x), t.clone()));
+                        if !equal(&t, &xt) {
+                            result += NotEqual(t, xt);
+                        }
+                        result
+                    })
+                })
+            }
+            TmFold(t, x) => {
+                let expected = match &*t {
+                    TyRec(v, body) => {
+                        Some(subst_type(body.clone(), t.clone(), *v))
+                    }
+                    _ => None,
+                };
+                Step::check(x, expected.clone(), |this, x| {
+                    Step::Done((x + this.annotation(t.clone())).then(
+                        |((x, xt), e)| {
+                            let mut result =
+                                TypeckResult::from((de::fold(e, x), t.clone()));
+                            match expected {
+                                Some(body) if !equal(&body, &xt) => {
+                                    result += NotEqual(body, xt)
+                                }
+                                Some(_) => {}
+                                None => result += NotRecursive(t),
+                            }
+                            result
+                        },
+                    ))
+                })
+            }
+            TmUnfold(x) => Step::check(x, None, |_, x| {
+                Step::Done(x.then(|(x, xt)| {
+                    let term = de::unfold(x);
+                    match &*xt {
+                        TyRec(v, body) => {
+                            let body = subst_type(body.clone(), xt.clone(), *v);
+                            (term, body).into()
+                        }
+                        _ => TypeckResult::new(
+                            (term, ty::error()),
+                            NotRecursive(xt),
+                        ),
+                    }
+                }))
+            }),
+            TmPack(w, x, u) => {
+                let expected = match &*u {
+                    TyExists(v, body) => {
+                        Some(subst_type(body.clone(), w.clone(), *v))
+                    }
+                    _ => None,
+                };
+                Step::check(x, expected.clone(), |this, x| {
+                    let x = x + this.annotation(w) + this.annotation(u.clone());
+                    Step::Done(x.then(|(((x, xt), ew), eu)| {
+                        let mut result = TypeckResult::from((
+                            de::pack(ew, x, eu),
+                            u.clone(),
+                        ));
+                        match expected {
+                            Some(body) if !equal(&body, &xt) => {
+                                result += NotEqual(body, xt)
+                            }
+                            Some(_) => {}
+                            None => result += NotAnExists(u),
+                        }
+                        result
+                    }))
+                })
+            }
+            TmUnpack(a, p, x, y) => Step::check(x, None, move |this, x| {
+                after(x, |(x, xt)| {
+                    this.scoped(|this| {
+                        this.types.push(a);
+                        let bound = match &*xt {
+                            TyExists(v, body) => {
+                                let body =
+                                    subst_type(body.clone(), ty::var(a), *v);
+                                this.bind_pattern(&p, body)
+                            }
+                            _ => {
+                                let mut bound =
+                                    this.bind_pattern(&p, ty::error());
+                                bound += NotAnExists(xt.clone());
+                                bound
+                            }
+                        };
+                        after(bound, |_| Step::term(y, None))
+                    })
+                    .map(move |body| {
+                        body.then(|(y, yt)| {
+                            let escapes = occurs(a, &yt, &HashMap::new());
+                            let term = de::unpack(a, p, x, y);
+                            let mut result =
+                                TypeckResult::from((term, yt.clone()));
+                            if escapes {
+                                result += EscapingType(a, yt);
+                            }
+                            result
+                        })
+                    })
+                })
+            }),
+            TmQualAbs(c, t, y) => {
+                let dict = self.fresh_dict(c);
+                let dict_type =
+                    self.check_type(&t) + self.dict_type(c, t.clone());
+                self.givens.push((dict, c, t.clone()));
+                Step::check(y, None, move |this, body| {
+                    this.givens.pop();
+                    Step::Done((dict_type + body).map(|((_, d), (y, yt))| {
+                        (de::abs(dict, d, y), ty::qual(c, t, yt))
+                    }))
+                })
+            }
+            TmClass(class, body) => {
+                self.scoped(|this| this.typeck_class(class, body))
+            }
+            TmInstance(instance, body) => self.typeck_instance(instance, body),
+            TmError => Step::Done((term, ty::error()).into()),
+        }
+    }
+
+    fn sequence(
+        &mut self,
+        terms: Vec<Term>,
+        finish: impl FnOnce(&mut Self, Checked) -> Step<'a> + 'a,
+    ) -> Step<'a> {
+        self.sequence_from(terms.into_iter(), Vec::new().into(), finish)
+    }
+
+    fn sequence_from(
+        &mut self,
+        mut terms: vec::IntoIter<Term>,
+        checked: Checked,
+        finish: impl FnOnce(&mut Self, Checked) -> Step<'a> + 'a,
+    ) -> Step<'a> {
+        match terms.next() {
+            Some(term) => Step::check(term, None, move |this, item| {
+                let checked = (checked + item).map(|(mut items, item)| {
+                    items.push(item);
+                    items
+                });
+                this.sequence_from(terms, checked, finish)
+            }),
+            None => finish(self, checked),
+        }
+    }
+
+    fn typeck_fields(
+        &mut self,
+        fields: Vec<(String, Term)>,
+        finish: impl FnOnce(&mut Self, CheckedFields) -> Step<'a> + 'a,
+    ) -> Step<'a> {
+        let (labels, terms): (Vec<_>, _) = fields.into_iter().unzip();
+        self.sequence(terms, move |this, items| {
+            let fields = items.map(|items| {
+                labels
+                    .into_iter()
+                    .zip(items)
+                    .map(|(label, (x, xt))| ((label.clone(), x), (label, xt)))
+                    .unzip()
+            });
+            finish(this, fields)
+        })
+    }
+
+    fn typeck_case(
+        &mut self,
+        x: Term,
+        xt: Type,
+        arms: Vec<(String, Pattern, Term)>,
+    ) -> Step<'a> {
+        let mut errors = TypeckErrors::new();
+        let cases = match &*xt {
+            TyVariant(cases, None) => cases.clone(),
+            TyVariant(cases, Some(_)) => {
+                errors.push_back(NonExhaustive(xt.clone()));
+                cases.clone()
+            }
+            _ => {
+                errors.push_back(NotAVariant(xt.clone()));
+                vec![]
+            }
+        };
+        for (label, _) in &cases {
+            if !arms.iter().any(|(l, _, _)| l == label) {
+                errors.push_back(MissingCase(xt.clone(), label.clone()));
+            }
+        }
+        self.typeck_arms(Case {
+            term: x,
+            ty: xt,
+            cases,
+            arms: arms.into_iter(),
+            result_type: None,
+            elaborated: vec![],
+            errors,
+        })
+    }
+
+    fn typeck_arms(&mut self, mut case: Case) -> Step<'a> {
+        let (label, pattern, arm) = match case.arms.next() {
+            Some(arm) => arm,
+            None => {
+                let ty = case.result_type.unwrap_or_else(ty::error);
+                return Step::Done(MultiResult {
+                    result: (de::case(case.term, case.elaborated), ty),
+                    collect: case.errors,
+                });
+            }
+        };
+        if case.elaborated.iter().any(|(l, _, _)| *l == label) {
+            case.errors.push_back(DuplicateField(label.clone()));
+        }
+        let ty = find_field(&case.cases, &label);
+        if ty.is_none() {
+            let error = NoField(case.ty.clone(), label.clone());
+            case.errors.push_back(error);
+        }
+        let known = ty.is_some();
+        let ty = ty.unwrap_or_else(ty::error);
+        self.scoped(|this| {
+            let bound = this.bind_pattern(&pattern, ty);
+            after(bound, |_| Step::term(arm, None))
+        })
+        .then(self, move |this, result| {
+            let MultiResult {
+                result: (arm, at),
+                collect,
+            } = result;
+            case.errors.extend(collect);
+            match &case.result_type {
+                Some(rt) if known && !equal(rt, &at) => {
+                    case.errors.push_back(NotEqual(rt.clone(), at))
+                }
+                Some(_) => {}
+                None if known => case.result_type = Some(at),
+                None => {}
+            }
+            case.elaborated.push((label, pattern, arm));
+            this.typeck_arms(case)
+        })
+    }
+
+    fn typeck_class(&mut self, class: Class, body: Term) -> Step<'a> {
+        let Class {
+            name,
+            param,
+            ref methods,
+        } = class;
+        self.classes.insert(name, class.clone());
+        let mut types = vec![];
+        for (method, ty) in methods {
+            let ty = ty::forall(param, ty::qual(name, param, ty.clone()));
+            self.insert(*method, ty.clone());
+            types.push(self.annotation(ty));
+        }
+        let projections = (0..methods.len())
+            .map(|i| self.projection(&class, i))
+            .collect_vec();
+        let types = collect(types);
+        Step::check(body, None, move |_, body| {
+            Step::Done((types + body).map(|(types, (body, bt))| {
+                let abs = class
+                    .methods
+                    .iter()
+                    .zip(types)
+                    .rev()
+                    .fold(body, |body, ((method, _), ty)| {
+                        de::abs(*method, ty, body)
+                    });
+                (projections.into_iter().fold(abs, de::app), bt)
+            }))
+        })
+    }
+
+    fn projection(&mut self, class: &Class, index: usize) -> Term {
+        let dict = self.fresh_dict(class.name);
+        let dict_type = self.dict_type(class.name, class.param).result;
+        let fields = class
+            .methods
+            .iter()
+            .map(|(method, ty)| {
+                let field = self.names.push(self.names[*method].clone());
+                (field, self.elaborate_type(ty.clone()).result)
+            })
+            .collect_vec();
+        let select = fields
+            .iter()
+            .rev()
+            .fold(de::var(fields[index].0), |body, (field, ty)| {
+                de::abs(*field, ty.clone(), body)
+            });
+        let field_type = fields[index].1.clone();
+        de::ty_abs(
+            class.param,
+            de::abs(
+                dict,
+                dict_type,
+                de::app(de::ty_app(dict, field_type), select),
+            ),
+        )
+    }
+
+    fn typeck_instance(&mut self, instance: Instance, body: Term) -> Step<'a> {
+        let class = match self.classes.get(&instance.class) {
+            Some(class) => class.clone(),
+            None => {
+                return Step::check(body, None, move |_, mut result| {
+                    result += NotAClass(instance.class);
+                    Step::Done(result)
+                })
+            }
+        };
+        let types = self.types.len();
+        self.types.extend(&instance.params);
+        let mut result = self.check_instance(&class, &instance);
+        let scoped = instance
+            .context
+            .iter()
+            .map(|(_, ty)| ty)
+            .chain(Some(&instance.head))
+            .map(|ty| self.check_type(ty))
+            .collect_vec();
+        result = (result + collect(scoped)).map(|_| ());
+        for (method, _) in &class.methods {
+            let defined = instance.methods.iter().filter(|(m, _)| m == method);
+            match defined.count() {
+                0 => {
+                    result += MissingMethod(
+                        class.name,
+                        instance.head.clone(),
+                        *method,
+                    )
+                }
+                1 => {}
+                _ => result += DuplicateMethod(*method),
+            }
+        }
+        for (method, _) in &instance.methods {
+            if class.methods.iter().all(|(m, _)| m != method) {
+                result += NotAMethod(*method, class.name);
+            }
+        }
+        let name = class.name;
+        let valid = result.collect.is_empty();
+        let declared = instance.clone();
+        let finish = move |this: &mut Self, dict: ElabResult| {
+            let var = this.fresh_dict(name);
+            let dict_type = this.instance_type(&instance);
+            this.types.truncate(types);
+            this.instances.push((var, instance));
+            Step::check(body, None, move |this, body| {
+                this.instances.pop();
+                Step::Done((result + dict + dict_type + body).map(
+                    |(((_, dict), d), (body, bt))| {
+                        (de::app(de::abs(var, d, body), dict), bt)
+                    },
+                ))
+            })
+        };
+        if valid {
+            self.instance_dict(class, declared, finish)
+        } else {
+            finish(self, de::error().into())
+        }
+    }
+
+    fn check_instance(
+        &self,
+        class: &Class,
+        instance: &Instance,
+    ) -> MultiResult<(), TypeckErrors> {
+        let mut result = MultiResult::from(());
+        for (_, other) in &self.instances {
+            if other.class != instance.class {
+                continue;
+            }
+            let flexible = other.params.iter().chain(&instance.params);
+            let flexible = flexible.copied().collect_vec();
+            if unify(
+                &other.head,
+                &instance.head,
+                &flexible,
+                &mut HashMap::new(),
+            ) {
+                result += OverlappingInstances(
+                    class.name,
+                    other.head.clone(),
+                    instance.head.clone(),
+                );
+            }
+        }
+        result
+    }
+
+    fn instance_dict(
+        &mut self,
+        class: Class,
+        instance: Instance,
+        finish: impl FnOnce(&mut Self, ElabResult) -> Step<'a> + 'a,
+    ) -> Step<'a> {
+        let context = instance
+            .context
+            .iter()
+            .map(|(c, t)| (self.fresh_dict(*c), *c, t.clone()))
+            .collect_vec();
+        let context_types = context
+            .iter()
+            .map(|(_, c, t)| self.dict_type(*c, t.clone()))
+            .collect_vec();
+        let depth = self.givens.len();
+        self.givens.extend(context.iter().cloned());
+        let mut methods = vec![];
+        let mut expected = vec![];
+        let mut types = vec![];
+        for (method, ty) in &class.methods {
+            let ty = subst_type(ty.clone(), instance.head.clone(), class.param);
+            let (_, method) =
+                instance.methods.iter().find(|(m, _)| m == method).unwrap();
+            methods.push(method.clone());
+            types.push(self.elaborate_type(ty.clone()));
+            expected.push(ty);
+        }
+        self.sequence(methods, move |this, impls| {
+            this.givens.truncate(depth);
+            let impls = impls.then(|impls| {
+                collect(impls.into_iter().zip(expected).map(
+                    |((m, actual), expected)| {
+                        if equal(&expected, &actual) {
+                            m.into()
+                        } else {
+                            ElabResult::new(m, NotEqual(expected, actual))
+                        }
+                    },
+                ))
+            });
+            let result = this.names.push("r".into());
+            let cont = this.names.push("k".into());
+            let dict = (impls + collect(types) + collect(context_types)).map(
+                |((impls, types), context_types)| {
+                    let cont_type = types
+                        .into_iter()
+                        .rev()
+                        .fold(ty::var(result), |r, t| ty::arr(t, r));
+                    let dict = de::ty_abs(
+                        result,
+                        de::abs(
+                            cont,
+                            cont_type,
+                            impls.into_iter().fold(de::var(cont), de::app),
+                        ),
+                    );
+                    let dict = context
+                        .iter()
+                        .zip(context_types)
+                        .rev()
+                        .fold(dict, |dict, ((var, _, _), ty)| {
+                            de::abs(*var, ty, dict)
+                        });
+                    instance
+                        .params
+                        .iter()
+                        .rev()
+                        .fold(dict, |dict, param| de::ty_abs(*param, dict))
+                },
+            );
+            finish(this, dict)
+        })
+    }
+
+    fn instance_type(&mut self, instance: &Instance) -> TypeResult {
+        let head = self.dict_type(instance.class, instance.head.clone());
+        let context = instance
+            .context
+            .iter()
+            .map(|(c, t)| self.dict_type(*c, t.clone()))
+            .collect_vec();
+        (collect(context) + head).map(|(context, head)| {
+            let ty = context.into_iter().rev().fold(head, |r, t| ty::arr(t, r));
+            instance
+                .params
+                .iter()
+                .rev()
+                .fold(ty, |ty, param| ty::forall(*param, ty))
+        })
+    }
+
+    fn dict_type(&mut self, class: Var, arg: impl Into<Type>) -> TypeResult {
+        let class = match self.classes.get(&class) {
+            Some(class) => class.clone(),
+            None => return TypeResult::item(NotAClass(class)),
+        };
+        let arg = arg.into();
+        let result = self.names.push("r".into());
+        let methods = class
+            .methods
+            .iter()
+            .map(|(_, ty)| {
+                let ty = subst_type(ty.clone(), arg.clone(), class.param);
+                self.elaborate_type(ty)
+            })
+            .collect_vec();
+        collect(methods).map(|methods| {
+            let cont = methods
+                .into_iter()
+                .rev()
+                .fold(ty::var(result), |r, t| ty::arr(t, r));
+            ty::forall(result, ty::arr(cont, result))
+        })
+    }
+
+    fn elaborate_type(&mut self, ty: Type) -> TypeResult {
+        match (*ty).clone() {
+            TyTuple(items) => {
+                let items = items.into_iter().map(|t| self.elaborate_type(t));
+                collect(items.collect_vec()).map(ty::tuple)
+            }
+            TyWith(l, r) => (self.elaborate_type(l) + self.elaborate_type(r))
+                .map(|(l, r)| ty::with(l, r)),
+            TyRecord(fields, tail) => self
+                .elaborate_row(fields, tail)
+                .map(|(fields, tail)| ty::record(fields, tail)),
+            TyVariant(fields, tail) => self
+                .elaborate_row(fields, tail)
+                .map(|(fields, tail)| ty::variant(fields, tail)),
+            TyHole | TyVar(_) | TyError => ty.into(),
+            TyArrow(from, to) => (self.elaborate_type(from)
+                + self.elaborate_type(to))
+            .map(|(from, to)| ty::arr(from, to)),
+            TyForall(n, x) => {
+                self.elaborate_type(x).map(move |x| ty::forall(n, x))
+            }
+            TyRec(n, x) => self.elaborate_type(x).map(move |x| ty::rec(n, x)),
+            TyExists(n, x) => {
+                self.elaborate_type(x).map(move |x| ty::exists(n, x))
+            }
+            TyQual(c, t, x) => (self.dict_type(c, t) + self.elaborate_type(x))
+                .map(|(d, x)| ty::arr(d, x)),
         }
     }
 
-    fn get_or_alpha(&self, v: Var) -> Type {
-        self.0.get(&v).cloned().unwrap_or_else(ty::hole)
+    fn elaborate_row(
+        &mut self,
+        fields: Vec<(String, Type)>,
+        tail: Option<Type>,
+    ) -> MultiResult<(Row, Option<Type>), TypeckErrors> {
+        let fields = fields
+            .into_iter()
+            .map(|(label, ty)| self.elaborate_type(ty).map(|ty| (label, ty)))
+            .collect_vec();
+        let tail = match tail {
+            Some(tail) => self.elaborate_type(tail).map(Some),
+            None => None.into(),
+        };
+        collect(fields) + tail
+    }
+
+    fn discharge(&mut self, term: Term, ty: Type) -> TypeckResult {
+        match (*ty).clone() {
+            TyQual(c, t, rest) => self
+                .solve(c, t)
+                .then(|dict| self.discharge(de::app(term, dict), rest)),
+            _ => (term, ty).into(),
+        }
+    }
+
+    fn solve(&mut self, class: Var, ty: Type) -> ElabResult {
+        let given = self
+            .givens
+            .iter()
+            .rev()
+            .find(|(_, c, t)| *c == class && *t == ty);
+        if let Some((dict, _, _)) = given {
+            return de::var(*dict).into();
+        }
+        let found = self.instances.iter().rev().find_map(|(var, instance)| {
+            let mut subst = HashMap::new();
+            if instance.class == class
+                && match_type(&instance.head, &ty, &instance.params, &mut subst)
+            {
+                Some((*var, instance.clone(), subst))
+            } else {
+                None
+            }
+        });
+        let (var, instance, subst) = match found {
+            Some(found) => found,
+            None => return ElabResult::item(NoInstance(class, ty)),
+        };
+        let args = instance
+            .params
+            .iter()
+            .map(|param| {
+                let arg = subst.get(param).cloned().unwrap_or_else(ty::hole);
+                self.elaborate_type(arg)
+            })
+            .collect_vec();
+        let context = instance
+            .context
+            .iter()
+            .map(|(c, t)| {
+                let t = subst
+                    .iter()
+                    .fold(t.clone(), |t, (v, s)| subst_type(t, s.clone(), *v));
+                self.solve(*c, t)
+            })
+            .collect_vec();
+        (collect(args) + collect(context)).map(|(args, context)| {
+            let dict = args.into_iter().fold(de::var(var), de::ty_app);
+            context.into_iter().fold(dict, de::app)
+        })
+    }
+
+    fn fresh_dict(&mut self, class: Var) -> Var {
+        let name = format!("d{}", self.names[class]);
+        self.names.push(name)
+    }
+
+    fn bind_pattern(
+        &mut self,
+        pattern: &Pattern,
+        ty: Type,
+    ) -> MultiResult<(), TypeckErrors> {
+        match pattern {
+            PtWild => ().into(),
+            PtTuple(items) => match &*ty {
+                TyTuple(types) if types.len() == items.len() => {
+                    let bound = items
+                        .iter()
+                        .zip(types)
+                        .map(|(p, t)| self.bind_pattern(p, t.clone()))
+                        .collect_vec();
+                    collect(bound).map(|_| ())
+                }
+                TyHole | TyError => {
+                    let bound = items
+                        .iter()
+                        .map(|p| self.bind_pattern(p, ty.clone()))
+                        .collect_vec();
+                    collect(bound).map(|_| ())
+                }
+                _ => MultiResult::item(NotATuple(ty.clone(), items.len())),
+            },
+            PtLeft(p) | PtRight(p) => match &*ty {
+                TyWith(l, _) if matches!(pattern, PtLeft(_)) => {
+                    self.bind_pattern(p, l.clone())
+                }
+                TyWith(_, r) => self.bind_pattern(p, r.clone()),
+                TyHole | TyError => self.bind_pattern(p, ty.clone()),
+                _ => MultiResult::item(NotAWith(ty.clone())),
+            },
+            PtVar(var) => {
+                self.insert(*var, ty);
+                ().into()
+            }
+        }
+    }
+
+    fn annotation(&mut self, ty: Type) -> TypeResult {
+        (self.check_type(&ty) + self.elaborate_type(ty)).map(|(_, ty)| ty)
+    }
+
+    fn check_type(&mut self, ty: &Type) -> MultiResult<(), TypeckErrors> {
+        let checked = match &**ty {
+            TyTuple(items) => {
+                items.iter().map(|t| self.check_type(t)).collect_vec()
+            }
+            TyWith(l, r) | TyArrow(l, r) | TyQual(_, l, r) => {
+                vec![self.check_type(l), self.check_type(r)]
+            }
+            TyRecord(fields, tail) | TyVariant(fields, tail) => {
+                let mut checked = fields
+                    .iter()
+                    .map(|(_, t)| t)
+                    .chain(tail)
+                    .map(|t| self.check_type(t))
+                    .collect_vec();
+                if let Some(err) = row_error(fields, tail) {
+                    checked.push(MultiResult::item(err));
+                }
+                checked
+            }
+            TyHole | TyError => vec![],
+            TyVar(v) if self.types.contains(v) => vec![],
+            TyVar(v)
+                if self.vars.contains_key(v)
+                    || self.classes.contains_key(v) =>
+            {
+                vec![MultiResult::item(NotAType(*v))]
+            }
+            TyVar(v) => vec![MultiResult::item(UnboundVariable(*v))],
+            TyForall(v, x) | TyRec(v, x) | TyExists(v, x) => {
+                self.types.push(*v);
+                let checked = self.check_type(x);
+                self.types.pop();
+                vec![checked]
+            }
+        };
+        collect(checked).map(|_| ())
     }
 
     fn insert(&mut self, v: Var, t: Type) -> &mut Self {
-        self.0.insert(v, t);
+        self.vars.insert(v, t);
+        self.scope.push(v);
         self
     }
+
+    fn scoped(&mut self, body: impl FnOnce(&mut Self) -> Step<'a>) -> Step<'a> {
+        let (terms, types) = (self.scope.len(), self.types.len());
+        body(self).then(self, move |this, result| {
+            for var in this.scope.drain(terms..) {
+                this.vars.remove(&var);
+            }
+            this.types.truncate(types);
+            Step::Done(result)
+        })
+    }
+
+    fn hole(&mut self, name: Option<String>, goal: Type) -> TypeckResult {
+        let modules: HashSet<_> = self
+            .scope
+            .iter()
+            .filter_map(|v| self.names[*v].split_once('.'))
+            .map(|(module, _)| module)
+            .collect();
+        let context = self
+            .scope
+            .iter()
+            .filter(|v| self.names[**v] != "_")
+            .filter(|v| {
+                let name = &self.names[**v];
+                !name.contains('.') && !modules.contains(name.as_str())
+            })
+            .map(|v| (*v, self.vars[v].clone()))
+            .collect();
+        let types = self
+            .types
+            .iter()
+            .filter(|v| self.names[**v] != "_")
+            .copied()
+            .collect();
+        self.holes.push(Hole {
+            name: name.clone(),
+            goal: goal.clone(),
+            types,
+            context,
+        });
+        (de::hole(name), goal).into()
+    }
 }
 
-fn assert_app(fun: Type, arg: Type) -> TypeckResult {
+fn collect<T>(
+    items: impl IntoIterator<Item = MultiResult<T, TypeckErrors>>,
+) -> MultiResult<Vec<T>, TypeckErrors> {
+    items.into_iter().fold(Vec::new().into(), |items, item| {
+        (items + item).map(|(mut items, item)| {
+            items.push(item);
+            items
+        })
+    })
+}
+
+fn match_type(
+    pattern: &Type,
+    ty: &Type,
+    params: &[Var],
+    subst: &mut HashMap<Var, Type>,
+) -> bool {
+    match (&**pattern, &**ty) {
+        (TyVar(p), _) if params.contains(p) => match subst.get(p) {
+            Some(bound) => bound == ty,
+            None => {
+                subst.insert(*p, ty.clone());
+                true
+            }
+        },
+        (TyTuple(a), TyTuple(b)) => {
+            a.len() == b.len()
+                && a.iter()
+                    .zip(b)
+                    .all(|(a, b)| match_type(a, b, params, subst))
+        }
+        (TyWith(a, b), TyWith(c, d)) => {
+            match_type(a, c, params, subst) && match_type(b, d, params, subst)
+        }
+        (TyRecord(f, t), TyRecord(g, u)) => {
+            match_row((f, t), (g, u), ty::record, params, subst)
+        }
+        (TyVariant(f, t), TyVariant(g, u)) => {
+            match_row((f, t), (g, u), ty::variant, params, subst)
+        }
+        (TyHole, TyHole) => true,
+        (TyVar(a), TyVar(b)) => a == b,
+        (TyArrow(a, b), TyArrow(c, d)) => {
+            match_type(a, c, params, subst) && match_type(b, d, params, subst)
+        }
+        (TyForall(a, x), TyForall(b, y))
+        | (TyRec(a, x), TyRec(b, y))
+        | (TyExists(a, x), TyExists(b, y)) => {
+            a == b && match_type(x, y, params, subst)
+        }
+        (TyQual(c, a, x), TyQual(d, b, y)) => {
+            c == d
+                && match_type(a, b, params, subst)
+                && match_type(x, y, params, subst)
+        }
+        _ => false,
+    }
+}
+
+fn unify(
+    lhs: &Type,
+    rhs: &Type,
+    flexible: &[Var],
+    subst: &mut HashMap<Var, Type>,
+) -> bool {
+    let lhs = resolve(lhs, subst);
+    let rhs = resolve(rhs, subst);
+    match (&*lhs, &*rhs) {
+        (TyVar(a), TyVar(b)) if a == b => true,
+        (TyVar(a), _) if flexible.contains(a) => bind(*a, rhs, subst),
+        (_, TyVar(b)) if flexible.contains(b) => bind(*b, lhs, subst),
+        (TyTuple(a), TyTuple(b)) => {
+            a.len() == b.len()
+                && a.iter().zip(b).all(|(a, b)| unify(a, b, flexible, subst))
+        }
+        (TyWith(a, b), TyWith(c, d)) => {
+            unify(a, c, flexible, subst) && unify(b, d, flexible, subst)
+        }
+        (TyRecord(f, t), TyRecord(g, u)) => {
+            unify_row((f, t), (g, u), ty::record, flexible, subst)
+        }
+        (TyVariant(f, t), TyVariant(g, u)) => {
+            unify_row((f, t), (g, u), ty::variant, flexible, subst)
+        }
+        (TyHole, TyHole) => true,
+        (TyArrow(a, b), TyArrow(c, d)) => {
+            unify(a, c, flexible, subst) && unify(b, d, flexible, subst)
+        }
+        (TyForall(a, x), TyForall(b, y))
+        | (TyRec(a, x), TyRec(b, y))
+        | (TyExists(a, x), TyExists(b, y)) => {
+            a == b && unify(x, y, flexible, subst)
+        }
+        (TyQual(c, a, x), TyQual(d, b, y)) => {
+            c == d
+                && unify(a, b, flexible, subst)
+                && unify(x, y, flexible, subst)
+        }
+        _ => false,
+    }
+}
+
+type RowRef<'a> = (&'a [(String, Type)], &'a Option<Type>);
+
+type MakeRow = fn(Row, Option<Type>) -> Type;
+
+fn match_row(
+    pattern: RowRef,
+    ty: RowRef,
+    make: MakeRow,
+    params: &[Var],
+    subst: &mut HashMap<Var, Type>,
+) -> bool {
+    let mut rest = ty.0.to_vec();
+    for (label, p) in pattern.0 {
+        match rest.iter().position(|(l, _)| l == label) {
+            Some(i) => {
+                let (_, t) = rest.remove(i);
+                if !match_type(p, &t, params, subst) {
+                    return false;
+                }
+            }
+            None => return false,
+        }
+    }
+    match pattern.1 {
+        Some(tail) => {
+            match_type(tail, &make(rest, ty.1.clone()), params, subst)
+        }
+        None => rest.is_empty() && ty.1.is_none(),
+    }
+}
+
+fn unify_row(
+    lhs: RowRef,
+    rhs: RowRef,
+    make: MakeRow,
+    flexible: &[Var],
+    subst: &mut HashMap<Var, Type>,
+) -> bool {
+    let mut rest = rhs.0.to_vec();
+    let mut extra = vec![];
+    for (label, a) in lhs.0 {
+        match rest.iter().position(|(l, _)| l == label) {
+            Some(i) => {
+                let (_, b) = rest.remove(i);
+                if !unify(a, &b, flexible, subst) {
+                    return false;
+                }
+            }
+            None => extra.push((label.clone(), a.clone())),
+        }
+    }
+    let (lhs, rhs) = match (extra.is_empty(), rest.is_empty()) {
+        (true, true) => {
+            (make(vec![], lhs.1.clone()), make(vec![], rhs.1.clone()))
+        }
+        (false, true) => {
+            (make(extra, lhs.1.clone()), make(vec![], rhs.1.clone()))
+        }
+        (true, false) => {
+            (make(vec![], lhs.1.clone()), make(rest, rhs.1.clone()))
+        }
+        (false, false) => return false,
+    };
+    unify(&lhs, &rhs, flexible, subst)
+}
+
+fn find_field(fields: &[(String, Type)], label: &str) -> Option<Type> {
+    fields
+        .iter()
+        .find(|(l, _)| l == label)
+        .map(|(_, ty)| ty.clone())
+}
+
+fn resolve(ty: &Type, subst: &HashMap<Var, Type>) -> Type {
+    match **ty {
+        TyVar(var) => match subst.get(&var) {
+            Some(bound) => resolve(bound, subst),
+            None => ty.clone(),
+        },
+        _ => ty.clone(),
+    }
+}
+
+fn bind(var: Var, ty: Type, subst: &mut HashMap<Var, Type>) -> bool {
+    if occurs(var, &ty, subst) {
+        return false;
+    }
+    subst.insert(var, ty);
+    true
+}
+
+fn occurs(var: Var, ty: &Type, subst: &HashMap<Var, Type>) -> bool {
+    match &*resolve(ty, subst) {
+        TyVar(v) => *v == var,
+        TyArrow(a, b) | TyWith(a, b) | TyQual(_, a, b) => {
+            occurs(var, a, subst) || occurs(var, b, subst)
+        }
+        TyForall(_, x) | TyRec(_, x) | TyExists(_, x) => occurs(var, x, subst),
+        TyTuple(items) => items.iter().any(|item| occurs(var, item, subst)),
+        TyRecord(fields, tail) | TyVariant(fields, tail) => {
+            fields.iter().any(|(_, ty)| occurs(var, ty, subst))
+                || tail.iter().any(|tail| occurs(var, tail, subst))
+        }
+        _ => false,
+    }
+}
+
+fn equal(lhs: &Type, rhs: &Type) -> bool {
+    alpha_equal(lhs, rhs, &mut vec![])
+}
+
+fn alpha_equal(lhs: &Type, rhs: &Type, bound: &mut Vec<(Var, Var)>) -> bool {
+    match (&**lhs, &**rhs) {
+        (TyError | TyHole, _) | (_, TyError | TyHole) => true,
+        (TyVar(a), TyVar(b)) => {
+            match bound.iter().rev().find(|(x, y)| x == a || y == b) {
+                Some((x, y)) => x == a && y == b,
+                None => a == b,
+            }
+        }
+        (TyTuple(a), TyTuple(b)) => {
+            a.len() == b.len()
+                && a.iter().zip(b).all(|(a, b)| alpha_equal(a, b, bound))
+        }
+        (TyWith(a, b), TyWith(c, d)) | (TyArrow(a, b), TyArrow(c, d)) => {
+            alpha_equal(a, c, bound) && alpha_equal(b, d, bound)
+        }
+        (TyRecord(f, t), TyRecord(g, u))
+        | (TyVariant(f, t), TyVariant(g, u)) => {
+            f.len() == g.len()
+                && f.iter()
+                    .zip(g)
+                    .all(|((k, a), (l, b))| k == l && alpha_equal(a, b, bound))
+                && match (t, u) {
+                    (Some(a), Some(b)) => alpha_equal(a, b, bound),
+                    (None, None) => true,
+                    _ => false,
+                }
+        }
+        (TyForall(a, x), TyForall(b, y))
+        | (TyRec(a, x), TyRec(b, y))
+        | (TyExists(a, x), TyExists(b, y)) => {
+            bound.push((*a, *b));
+            let result = alpha_equal(x, y, bound);
+            bound.pop();
+            result
+        }
+        (TyQual(c, a, x), TyQual(d, b, y)) => {
+            c == d && alpha_equal(a, b, bound) && alpha_equal(x, y, bound)
+        }
+        _ => lhs == rhs,
+    }
+}
+
+enum Scope<'t> {
+    Term(&'t Term),
+    Type(&'t Type),
+    Var(Var),
+    Bind(Var),
+    Pattern(&'t Pattern),
+    Truncate(usize),
+}
+
+fn closed(term: &Term, bound: &mut Vec<Var>) -> bool {
+    let start = bound.len();
+    let mut stack = vec![Scope::Term(term)];
+    while let Some(item) = stack.pop() {
+        let closed = match item {
+            Scope::Term(term) => {
+                stack.push(Scope::Truncate(bound.len()));
+                let start = stack.len();
+                scope_of(term, bound.len(), &mut stack);
+                stack[start..].reverse();
+                true
+            }
+            Scope::Type(ty) => closed_type(ty, bound),
+            Scope::Var(var) => bound.contains(&var),
+            Scope::Bind(var) => {
+                bound.push(var);
+                true
+            }
+            Scope::Pattern(pattern) => {
+                pattern_vars(pattern, bound);
+                true
+            }
+            Scope::Truncate(depth) => {
+                bound.truncate(depth);
+                true
+            }
+        };
+        if !closed {
+            bound.truncate(start);
+            return false;
+        }
+    }
+    true
+}
+
+fn scope_of<'t>(term: &'t Term, depth: usize, stack: &mut Vec<Scope<'t>>) {
+    match &**term {
+        TmTuple(items) => stack.extend(items.iter().map(Scope::Term)),
+        TmProj(x, _) | TmField(x, _) | TmUnfold(x) => {
+            stack.push(Scope::Term(x))
+        }
+        TmWith(l, r) | TmApp(l, r) => {
+            stack.extend([Scope::Term(l), Scope::Term(r)])
+        }
+        TmRecord(fields) => {
+            stack.extend(fields.iter().map(|(_, x)| Scope::Term(x)))
+        }
+        TmUpdate(x, fields) => {
+            stack.push(Scope::Term(x));
+            stack.extend(fields.iter().map(|(_, y)| Scope::Term(y)));
+        }
+        TmInj(t, _, x) | TmFold(t, x) => {
+            stack.extend([Scope::Type(t), Scope::Term(x)])
+        }
+        TmCase(x, arms) => {
+            stack.push(Scope::Term(x));
+            for (_, p, y) in arms {
+                stack.extend([
+                    Scope::Pattern(p),
+                    Scope::Term(y),
+                    Scope::Truncate(depth),
+                ]);
+            }
+        }
+        TmHole(_) | TmError => {}
+        TmVar(v) => stack.push(Scope::Var(*v)),
+        TmAbs(v, t, y) | TmFix(v, t, y) => {
+            stack.extend([Scope::Type(t), Scope::Bind(*v), Scope::Term(y)])
+        }
+        TmTyAbs(v, y) => stack.extend([Scope::Bind(*v), Scope::Term(y)]),
+        TmTyApp(f, t) => stack.extend([Scope::Term(f), Scope::Type(t)]),
+        TmLet(p, t, x, y) => stack.extend([
+            Scope::Type(t),
+            Scope::Term(x),
+            Scope::Pattern(p),
+            Scope::Term(y),
+        ]),
+        TmPack(w, x, u) => {
+            stack.extend([Scope::Type(w), Scope::Term(x), Scope::Type(u)])
+        }
+        TmUnpack(a, p, x, y) => stack.extend([
+            Scope::Term(x),
+            Scope::Bind(*a),
+            Scope::Pattern(p),
+            Scope::Term(y),
+        ]),
+        TmQualAbs(c, t, y) => {
+            stack.extend([Scope::Var(*c), Scope::Type(t), Scope::Term(y)])
+        }
+        TmClass(class, body) => {
+            stack.extend([Scope::Bind(class.name), Scope::Bind(class.param)]);
+            stack.extend(class.methods.iter().map(|(_, ty)| Scope::Type(ty)));
+            stack.push(Scope::Truncate(depth + 1));
+            stack.extend(class.methods.iter().map(|(m, _)| Scope::Bind(*m)));
+            stack.push(Scope::Term(body));
+        }
+        TmInstance(instance, body) => {
+            stack.push(Scope::Var(instance.class));
+            stack.extend(instance.methods.iter().map(|(m, _)| Scope::Var(*m)));
+            stack.extend(instance.params.iter().copied().map(Scope::Bind));
+            for (c, t) in &instance.context {
+                stack.extend([Scope::Var(*c), Scope::Type(t)]);
+            }
+            stack.push(Scope::Type(&instance.head));
+            stack.extend(instance.methods.iter().map(|(_, x)| Scope::Term(x)));
+            stack.extend([Scope::Truncate(depth), Scope::Term(body)]);
+        }
+    }
+}
+
+fn closed_type(ty: &Type, bound: &mut Vec<Var>) -> bool {
+    match &**ty {
+        TyTuple(items) => items.iter().all(|t| closed_type(t, bound)),
+        TyWith(l, r) | TyArrow(l, r) => {
+            closed_type(l, bound) && closed_type(r, bound)
+        }
+        TyRecord(fields, tail) | TyVariant(fields, tail) => fields
+            .iter()
+            .map(|(_, t)| t)
+            .chain(tail)
+            .all(|t| closed_type(t, bound)),
+        TyHole | TyError => true,
+        TyVar(v) => bound.contains(v),
+        TyForall(v, x) | TyRec(v, x) | TyExists(v, x) => {
+            bound.push(*v);
+            let result = closed_type(x, bound);
+            bound.pop();
+            result
+        }
+        TyQual(c, t, x) => {
+            bound.contains(c) && closed_type(t, bound) && closed_type(x, bound)
+        }
+    }
+}
+
+fn pattern_vars(pattern: &Pattern, bound: &mut Vec<Var>) {
+    match pattern {
+        PtWild => {}
+        PtTuple(items) => items.iter().for_each(|p| pattern_vars(p, bound)),
+        PtLeft(p) | PtRight(p) => pattern_vars(p, bound),
+        PtVar(v) => bound.push(*v),
+    }
+}
+
+/// Whether part of `ty` is unknown, after an error or at a hole, so that errors
+/// about it follow from another error or from the hole.
+fn erroneous(ty: &Type) -> bool {
+    match &**ty {
+        TyTuple(items) => items.iter().any(erroneous),
+        TyWith(l, r) | TyArrow(l, r) | TyQual(_, l, r) => {
+            erroneous(l) || erroneous(r)
+        }
+        TyRecord(fields, tail) | TyVariant(fields, tail) => {
+            fields.iter().map(|(_, t)| t).chain(tail).any(erroneous)
+        }
+        TyVar(_) => false,
+        TyForall(_, x) | TyRec(_, x) | TyExists(_, x) => erroneous(x),
+        TyHole | TyError => true,
+    }
+}
+
+fn assert_app(fun: Type, arg: Type) -> TypeResult {
     match (*fun).clone() {
-        TyArrow(from, to) if from == arg => to.into(),
-        TyArrow(from, to) => TypeckResult::new(to, NotEqual(from, arg)),
-        _ => TypeckResult::item(NotAFunction(fun)),
+        TyArrow(from, to) if equal(&from, &arg) => to.into(),
+        TyArrow(from, to) => TypeResult::new(to, NotEqual(from, arg)),
+        _ => TypeResult::item(NotAFunction(fun)),
     }
 }
 
-fn assert_ty_app(fun: Type, arg: Type) -> TypeckResult {
+fn assert_ty_app(fun: Type, arg: Type) -> TypeResult {
     match (*fun).clone() {
-        TyForall(var, inner) => subst_type(inner, arg, var).into(),
-        _ => TypeckResult::item(NotAForall(fun)),
+        TyForall(var, inner) => match instance_error(&inner, var, &arg) {
+            Some(err) => TypeResult::item(err),
+            None => subst_type(inner, arg, var).into(),
+        },
+        _ => TypeResult::item(NotAForall(fun)),
+    }
+}
+
+/// Finds what goes wrong when `var` is instantiated with `arg` in `ty`. Where
+/// `var` is the tail of a row, `arg` must be a row of the same sort without
+/// the labels already in front of it.
+fn instance_error(ty: &Type, var: Var, arg: &Type) -> Option<TypeckError> {
+    match &**ty {
+        TyTuple(items) => {
+            items.iter().find_map(|t| instance_error(t, var, arg))
+        }
+        TyWith(l, r) | TyArrow(l, r) | TyQual(_, l, r) => {
+            instance_error(l, var, arg).or_else(|| instance_error(r, var, arg))
+        }
+        TyRecord(fields, tail) | TyVariant(fields, tail) => {
+            let here = match tail.as_deref() {
+                Some(TyVar(v)) if *v == var => {
+                    let row = match &**ty {
+                        TyRecord(_, _) => {
+                            ty::record(fields.clone(), Some(arg.clone()))
+                        }
+                        _ => ty::variant(fields.clone(), Some(arg.clone())),
+                    };
+                    match &*row {
+                        TyRecord(fields, tail) | TyVariant(fields, tail) => {
+                            row_error(fields, tail)
+                        }
+                        _ => None,
+                    }
+                }
+                _ => None,
+            };
+            here.or_else(|| {
+                fields
+                    .iter()
+                    .map(|(_, t)| t)
+                    .chain(tail)
+                    .find_map(|t| instance_error(t, var, arg))
+            })
+        }
+        TyForall(n, _) | TyRec(n, _) | TyExists(n, _) if *n == var => None,
+        TyForall(_, x) | TyRec(_, x) | TyExists(_, x) => {
+            instance_error(x, var, arg)
+        }
+        TyHole | TyVar(_) | TyError => None,
+    }
+}
+
+/// Checks that the sorted `fields` of a record or variant type have distinct
+/// labels, and that its `tail` can stand for more of them.
+fn row_error(
+    fields: &[(String, Type)],
+    tail: &Option<Type>,
+) -> Option<TypeckError> {
+    if let Some(pair) = fields.windows(2).find(|pair| pair[0].0 == pair[1].0) {
+        return Some(DuplicateField(pair[0].0.clone()));
+    }
+    match tail.as_deref() {
+        None | Some(TyHole | TyVar(_) | TyError) => None,
+        Some(_) => tail.clone().map(NotARow),
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{
+        erase::*,
+        eval::{eval, Budget, CallByValue},
+        ident::identify,
+        parser::parse,
+    };
+
+    fn checked(input: &str) -> Result<Term, TypeckErrors> {
+        let (term, mut names, _) =
+            identify(parse(input).into_result().unwrap())
+                .into_result()
+                .unwrap();
+        typeck(term, &mut names).map(|(term, _)| term)
+    }
+
+    fn holes(input: &str) -> Vec<String> {
+        let (term, mut names, _) =
+            identify(parse(input).into_result().unwrap())
+                .into_result()
+                .unwrap();
+        let (_, holes) = typeck(term, &mut names).unwrap();
+        holes.iter().map(|hole| hole.pprint(&names)).collect()
+    }
 
     #[test]
     fn simple_typeck() {
-        assert_eq!(typeck(de::abs(0, ty::unit(), de::var(0))), Ok(()));
+        let mut names = Names::default();
+        let term = de::abs(names.push("x".into()), ty::unit(), de::var(0));
+        assert_eq!(typeck(term.clone(), &mut names), Ok((term, vec![])));
+    }
+
+    #[test]
+    fn dictionary_passing() {
+        let term = checked(include_str!("../examples/classes.od")).unwrap();
+        assert_eq!(
+            eval(erase(&term), CallByValue, Budget::default()).unwrap(),
+            erase(&de::unit())
+        );
+    }
+
+    #[test]
+    fn class_errors() {
+        let class = "class Show a = show : a -> (); ";
+        let unit = "instance Show () = show = x: () -> x; ";
+        let missing = checked(&format!("{}show [()] ()", class));
+        assert!(matches!(missing.unwrap_err()[0], NoInstance(_, _)));
+        let overlap = checked(&format!("{}{}{}()", class, unit, unit));
+        assert!(matches!(
+            overlap.unwrap_err()[0],
+            OverlappingInstances(_, _, _)
+        ));
+        let class = "class Eq a = (eq : a -> (); neq : a -> ()); ";
+        let unit = "instance Eq () = eq = x: () -> x; ";
+        let partial = checked(&format!("{}{}()", class, unit));
+        assert!(matches!(partial.unwrap_err()[0], MissingMethod(_, _, _)));
+    }
+
+    #[test]
+    fn polymorphic_methods() {
+        let class = "class Const a = konst : a -> b => b -> a; ";
+        let unit = "instance Const () = konst = x: () -> c => _: c -> x; ";
+        let term =
+            checked(&format!("{}{}konst [()] () [((),)] ((),)", class, unit));
+        assert_eq!(
+            eval(erase(&term.unwrap()), CallByValue, Budget::default())
+                .unwrap(),
+            erase(&de::unit())
+        );
+        let wrong = "instance Const () = konst = x: () -> c => y: c -> y; ";
+        let wrong = checked(&format!("{}{}()", class, wrong));
+        assert!(matches!(wrong.unwrap_err()[0], NotEqual(_, _)));
+    }
+
+    #[test]
+    fn thunks() {
+        let term = checked("(f: { () } -> f()) {\n    ()\n    ()\n}");
+        assert_eq!(
+            eval(erase(&term.unwrap()), CallByValue, Budget::default())
+                .unwrap(),
+            erase(&de::unit())
+        );
+        assert!(checked("(f: { () } -> f) { x: () -> x }").is_err());
+    }
+
+    #[test]
+    fn let_bindings() {
+        let src = "let f = x: () ->\n    let y: () = x\n    y\nf ()";
+        assert_eq!(
+            eval(
+                erase(&checked(src).unwrap()),
+                CallByValue,
+                Budget::default()
+            )
+            .unwrap(),
+            erase(&de::unit())
+        );
+        assert!(checked("let x: () -> () = (); ()").is_err());
+        assert!(checked("let () = x: () -> x; ()").is_err());
+    }
+
+    #[test]
+    fn tuples() {
+        let swap = "let swap = (x: (), y: ((),)) -> (y, x); ";
+        let term = checked(&format!("{}(swap ((), ((),))).0.0", swap));
+        assert_eq!(
+            eval(erase(&term.unwrap()), CallByValue, Budget::default())
+                .unwrap(),
+            erase(&de::unit())
+        );
+        let src = "let (a, (b, _)) = ((), ((), ())); b";
+        assert_eq!(
+            eval(
+                erase(&checked(src).unwrap()),
+                CallByValue,
+                Budget::default()
+            )
+            .unwrap(),
+            erase(&de::unit())
+        );
+        let wrong = checked("let (a, b) = ((),); a").unwrap_err();
+        assert!(matches!(wrong[0], NotATuple(_, 2)));
+        let wrong = checked("((), ()).2").unwrap_err();
+        assert!(matches!(wrong[0], NoComponent(_, 2)));
+    }
+
+    #[test]
+    fn additive_pairs() {
+        let ty = "{ () } & { ((),) }";
+        let choice = "({ () } & { ((),) })";
+        let left = checked(&format!("((f &): {} -> f()) {}", ty, choice));
+        assert_eq!(
+            eval(erase(&left.unwrap()), CallByValue, Budget::default())
+                .unwrap(),
+            erase(&de::unit())
+        );
+        let right = checked(&format!("((& f): {} -> f()) {}", ty, choice));
+        assert_eq!(
+            eval(erase(&right.unwrap()), CallByValue, Budget::default())
+                .unwrap(),
+            erase(&de::tuple(vec![de::unit()]))
+        );
+        let wrong = checked("let (a &) = (); a").unwrap_err();
+        assert!(matches!(wrong[0], NotAWith(_)));
+    }
+
+    #[test]
+    fn recursion() {
+        let term = checked(include_str!("../examples/recursion.od"));
+        assert_eq!(
+            eval(erase(&term.unwrap()), CallByValue, Budget::default())
+                .unwrap(),
+            erase(&de::unit())
+        );
+        let missing = checked("let rec f: _ = x: () -> f x; ()").unwrap_err();
+        assert!(matches!(missing[0], MissingAnnotation(_)));
+        let wrong = checked("fold [()] ()").unwrap_err();
+        assert!(matches!(wrong[0], NotRecursive(_)));
+    }
+
+    #[test]
+    fn data_declarations() {
+        let list = "data List a = [Nil : (), Cons : (a, List a)]\n";
+        let src = "let rec last: a => a -> List a -> a =
+    a => x: a -> xs: List a -> case (unfold xs) (
+        Nil _ -> x
+        Cons (y, rest) -> last [a] y rest
+    )
+let xs: List (List ()) = Cons [List ()] (Nil [()] (), Nil [List ()] ())
+last [List ()] (Cons [()] ((), Nil [()] ())) xs";
+        let term = checked(&format!("{}{}", list, src));
+        let value = eval(erase(&term.unwrap()), CallByValue, Budget::default());
+        assert!(matches!(&*value.unwrap(), EInj(label, _) if label == "Nil"));
+        let wrong = checked(&format!("{}Cons [()] ((), ())", list));
+        assert!(matches!(wrong.unwrap_err()[0], NotEqual(_, _)));
+    }
+
+    #[test]
+    fn existentials() {
+        let term = checked(include_str!("../examples/existentials.od"));
+        assert_eq!(
+            eval(erase(&term.unwrap()), CallByValue, Budget::default())
+                .unwrap(),
+            erase(&de::unit())
+        );
+        let pack = "pack [(), ((), x: () -> x)] as exists c => (c, c -> c)";
+        let escape = checked(&format!("unpack [c, (x, _)] = {}; x", pack));
+        assert!(matches!(escape.unwrap_err()[0], EscapingType(_, _)));
+        let wrong = checked("unpack [c, x] = (); x").unwrap_err();
+        assert!(matches!(wrong[0], NotAnExists(_)));
+    }
+
+    #[test]
+    fn rows() {
+        let term = checked(include_str!("../examples/records.od"));
+        let unit = de::unit();
+        let expected =
+            erase(&de::tuple(vec![unit.clone(), unit.clone(), unit]));
+        assert_eq!(
+            eval(erase(&term.unwrap()), CallByValue, Budget::default())
+                .unwrap(),
+            expected
+        );
+        let missing = checked("{ name = () }.age").unwrap_err();
+        assert!(matches!(missing[0], NoField(_, _)));
+        let twice = checked("{ a = (), b = ((),), a = ((), ()) }.a");
+        assert!(matches!(twice.unwrap_err()[0], DuplicateField(_)));
+        let get = "let getName = r => x: { name : () | r } -> x.name; ";
+        let arrow = checked(&format!("{}getName [() -> ()]", get));
+        assert!(matches!(arrow.unwrap_err()[0], NotARow(_)));
+        let again = checked(&format!("{}getName [{{ name : ((),) }}]", get));
+        assert!(matches!(again.unwrap_err()[0], DuplicateField(_)));
+        let variant = "let f = r => x: [A : () | r] -> x; f [{ b : () }]";
+        assert!(matches!(checked(variant).unwrap_err()[0], NotARow(_)));
+        let tail = checked("x: { a : () | () -> () } -> x").unwrap_err();
+        assert!(matches!(tail[0], NotARow(_)));
+        let open = checked("r => x: [A : () | r] -> case x (A y -> y)");
+        assert!(matches!(open.unwrap_err()[0], NonExhaustive(_)));
+        let partial = "x: [A : (), B : ()] -> case x (A y -> y)";
+        assert!(matches!(
+            checked(partial).unwrap_err()[0],
+            MissingCase(_, _)
+        ));
+        let class = "class C a = c : a -> (); ";
+        let open =
+            "instance r => C { a : () | r } = c = x: { a : () | r } -> x.a; ";
+        let closed = "instance C { a : (), b : () } = c = x: { a : (), b : () } -> x.a; ";
+        let overlap = checked(&format!("{}{}{}()", class, open, closed));
+        assert!(matches!(
+            overlap.unwrap_err()[0],
+            OverlappingInstances(_, _, _)
+        ));
+    }
+
+    #[test]
+    fn typed_holes() {
+        let app = holes("x: () -> f: (() -> ()) -> f ?arg");
+        assert_eq!(app, vec!["Hole ?arg : ()\n  x : ()\n  f : () -> ()"]);
+        let scoped = holes("let y: () = (x: () -> x) (); (_, (z: () -> ?z))");
+        assert_eq!(
+            scoped,
+            vec!["Hole _ : _\n  y : ()", "Hole ?z : _\n  y : ()\n  z : ()"]
+        );
+        assert!(holes("x: () -> x").is_empty());
+        let poly = holes("r => x: r -> ?h");
+        assert_eq!(poly, vec!["Hole ?h : _\n  r : type\n  x : r"]);
+    }
+
+    #[test]
+    fn holes_under_binders() {
+        let body = holes("let f: () -> () = x: () -> ?h; f");
+        assert_eq!(body, vec!["Hole ?h : ()\n  x : ()"]);
+        let poly = holes("let g: a => a -> a = b => x: b -> ?k; g");
+        assert_eq!(poly, vec!["Hole ?k : b\n  b : type\n  x : b"]);
+        let partial = holes("let p: () -> ((), ()) = x: () -> (x, ?h); p");
+        assert_eq!(partial, vec!["Hole ?h : _\n  x : ()"]);
+        assert_eq!(holes("(?f ()).0"), vec!["Hole ?f : _"]);
+    }
+
+    #[test]
+    fn scopes() {
+        let mut names = Names::default();
+        let (a, x) = (names.push("a".into()), names.push("x".into()));
+        let ty_as_term = typeck(de::ty_abs(a, de::var(a)), &mut names);
+        assert!(matches!(ty_as_term.unwrap_err()[0], NotATerm(_)));
+        let term = de::abs(a, ty::unit(), de::abs(x, ty::var(a), de::var(x)));
+        let term_as_ty = typeck(term, &mut names);
+        assert!(matches!(term_as_ty.unwrap_err()[0], NotAType(_)));
+        assert!(checked("a => x: (b => a -> b) -> x").is_ok());
+    }
+
+    #[test]
+    fn unbound_invariant() {
+        let mut names = Names::default();
+        let x = names.push("x".into());
+        let unbound = typeck(de::var(x), &mut names).unwrap_err();
+        assert_eq!(unbound[0], UnboundVariable(x));
+    }
+
+    #[test]
+    fn error_recovery() {
+        let parsed = parse("(pack [()] as exists a => a, y)");
+        assert_eq!(parsed.collect.to_string().lines().count(), 1);
+        let MultiResult { result, collect } = identify(parsed.result);
+        assert_eq!(collect.to_string().lines().count(), 1);
+        let (term, mut names, _) = result;
+        assert!(typeck(term, &mut names).is_ok());
+        let input = "let z: () = unknown (); (z.1, {a = z}.b ())";
+        let MultiResult { result, collect } = identify(parse(input).result);
+        assert_eq!(collect.to_string().lines().count(), 1);
+        let (term, mut names, _) = result;
+        let errors = typeck(term, &mut names).unwrap_err();
+        assert_eq!(errors.len(), 2);
+        let parsed = parse("let a = ( -> ); (x: () -> x) () ()");
+        assert_eq!(parsed.collect.to_string().lines().count(), 1);
+        let (term, mut names, _) =
+            identify(parsed.result).into_result().unwrap();
+        let errors = typeck(term, &mut names).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(errors[0], NotAFunction(_)));
+        for input in [
+            "instance C () = m = (); ()",
+            "class D a = n : a; instance C () = m = (); ()",
+            "let f: C () => () = (); f",
+        ] {
+            let MultiResult { result, collect } = identify(parse(input).result);
+            let errors = collect.to_string();
+            assert_eq!(errors.lines().count(), 1);
+            assert!(errors.ends_with("Unbound constructor name: C\n"));
+            let (term, mut names, _) = result;
+            assert!(typeck(term, &mut names).is_ok());
+        }
+    }
+
+    #[test]
+    fn deep_terms() {
+        let depth = 1_000_000;
+        let mut names = Names::default();
+        let (f, x) = (names.push("f".into()), names.push("x".into()));
+        let mut body = de::unit();
+        for _ in 0..depth {
+            body = de::app(de::var(f), body);
+        }
+        let term = de::app(
+            de::abs(f, ty::arr(ty::unit(), ty::unit()), body),
+            de::abs(x, ty::unit(), de::var(x)),
+        );
+        let (term, holes) = typeck(term, &mut names).unwrap();
+        assert!(holes.is_empty());
+        let printed = term.pprint(&names);
+        assert!(printed.starts_with(r"(\f: () -> (). f (f ("));
+        let tail = format!(r"f (){} \x: (). x", ")".repeat(depth));
+        assert!(printed.ends_with(&tail));
+        assert_eq!(printed.matches("f (").count(), depth);
     }
 }
@@ -0,0 +1,755 @@
+use std::{mem, rc::Rc};
+
+use thiserror::Error;
+
+use crate::{erase::*, eval::Budget, prelude::*, syntax::*};
+
+/// Compiles an erased term to code for the bytecode machine.
+pub fn compile(term: &Erased) -> Rc<Code> {
+    Compiler::default().compile(term)
+}
+
+/// Runs compiled code and reads the value back into a term.
+pub fn run(code: &Rc<Code>, budget: Budget) -> Result<Erased, Stopped> {
+    Machine::new(budget).run(code).map(|value| quote(&value))
+}
+
+/// The machine ran out of budget or reached a hole. Compiled code cannot be
+/// read back, so unlike evaluation there is no partial result.
+#[derive(Debug, Error)]
+pub enum Stopped {
+    #[error("Evaluation did not finish after {0} instructions")]
+    OutOfBudget(usize),
+    #[error("Evaluation reached the hole ?{0}")]
+    Hole(String),
+    #[error("Evaluation reached a hole")]
+    AnonymousHole,
+}
+
+/// The instructions of a function body, a lazy side of a pair, a recursive
+/// definition or the whole program.
+pub struct Code {
+    instrs: Vec<Instr>,
+    /// The free variables of the source and where closures take them from.
+    captures: Vec<(Var, Slot)>,
+    /// What the code was compiled from, to read closures back.
+    source: Erased,
+}
+
+#[derive(Clone, Copy)]
+enum Slot {
+    Local(usize),
+    Captured(usize),
+}
+
+/// Instructions work on a stack of operands, and on the local variables and
+/// captured values of the current frame.
+enum Instr {
+    Load(Slot),
+    /// Pops an operand into a new local variable.
+    Bind,
+    /// Drops the given number of innermost local variables.
+    Unbind(usize),
+    Pop,
+    Tuple(usize),
+    /// Pops a tuple and pushes its items, the first one on top.
+    Untuple,
+    Proj(usize),
+    Record(Vec<Rc<str>>),
+    Field(Rc<str>),
+    Update(Vec<Rc<str>>),
+    Inj(Rc<str>),
+    /// Pops an injection, pushes its payload and jumps to the arm for its
+    /// label.
+    Case(Vec<(Rc<str>, usize)>),
+    Jump(usize),
+    Closure(Rc<Code>),
+    With(Rc<Code>, Rc<Code>),
+    /// Builds a recursive definition and unrolls it.
+    Fix(Rc<Code>),
+    /// Pops a recursive definition and runs its body, with the definition
+    /// bound to itself.
+    Unroll,
+    Call,
+    TailCall,
+    /// Pops a lazy pair and runs one of its sides.
+    Left,
+    Right,
+    Return,
+    /// Stops the machine at a hole with the given name.
+    Hole(Option<String>),
+}
+
+type Value = Rc<ValueData>;
+
+#[allow(clippy::enum_variant_names)]
+enum ValueData {
+    VTuple(Vec<Value>),
+    VRecord(Vec<(Rc<str>, Value)>),
+    VInj(Rc<str>, Value),
+    VClosure(Rc<Code>, Vec<Value>),
+    VWith(Value, Value),
+    VFix(Rc<Code>, Vec<Value>),
+}
+
+use ValueData::*;
+
+impl Drop for ValueData {
+    fn drop(&mut self) {
+        let mut garbage = vec![];
+        take_children(self, &mut garbage);
+        while let Some(value) = garbage.pop() {
+            if let Ok(mut value) = Rc::try_unwrap(value) {
+                take_children(&mut value, &mut garbage);
+            }
+        }
+    }
+}
+
+/// Unlinks the values held by `value`, so that long chains of them are freed
+/// in a loop rather than by recursive drops.
+fn take_children(value: &mut ValueData, garbage: &mut Vec<Value>) {
+    match value {
+        VTuple(items) | VClosure(_, items) | VFix(_, items) => {
+            garbage.append(items)
+        }
+        VRecord(fields) => {
+            garbage.extend(fields.drain(..).map(|(_, value)| value))
+        }
+        VInj(_, x) => garbage.push(mem::replace(x, unit())),
+        VWith(l, r) => {
+            garbage.push(mem::replace(l, unit()));
+            garbage.push(mem::replace(r, unit()));
+        }
+    }
+}
+
+fn unit() -> Value {
+    Rc::new(VTuple(vec![]))
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Value,
+    /// Bound by a recursive definition, which is unrolled on every use.
+    Fix,
+}
+
+/// A function being compiled.
+struct Function {
+    instrs: Vec<Instr>,
+    locals: Vec<(Var, Kind)>,
+    captures: Vec<(Var, Kind, Slot)>,
+    /// Jump targets, by label.
+    labels: Vec<usize>,
+    source: Erased,
+}
+
+enum Task<'t> {
+    Compile(&'t Erased, bool),
+    Bind(&'t Pattern),
+    Unbind(usize),
+    Emit(Instr),
+    Label(usize),
+    Enter(Erased, Option<(Var, Kind)>),
+    Exit,
+    Closure,
+    With,
+    Fix,
+}
+
+/// Compiles terms with a stack of tasks rather than by recursion. Jumps are
+/// emitted to labels, which are resolved once the function is finished.
+#[derive(Default)]
+struct Compiler {
+    functions: Vec<Function>,
+    finished: Vec<Rc<Code>>,
+}
+
+impl Compiler {
+    fn compile(mut self, term: &Erased) -> Rc<Code> {
+        let mut tasks = vec![
+            Task::Exit,
+            Task::Compile(term, true),
+            Task::Enter(term.clone(), None),
+        ];
+        while let Some(task) = tasks.pop() {
+            match task {
+                Task::Compile(term, tail) => self.term(term, tail, &mut tasks),
+                Task::Bind(pattern) => self.pattern(pattern, &mut tasks),
+                Task::Unbind(count) => {
+                    let locals = &mut self.function().locals;
+                    locals.truncate(locals.len() - count);
+                    self.emit(Instr::Unbind(count))
+                }
+                Task::Emit(instr) => self.emit(instr),
+                Task::Label(label) => {
+                    let function = self.function();
+                    function.labels[label] = function.instrs.len()
+                }
+                Task::Enter(source, param) => self.functions.push(Function {
+                    instrs: vec![],
+                    locals: param.into_iter().collect(),
+                    captures: vec![],
+                    labels: vec![],
+                    source,
+                }),
+                Task::Exit => self.exit(),
+                Task::Closure => {
+                    let code = self.finished.pop().unwrap();
+                    self.emit(Instr::Closure(code))
+                }
+                Task::With => {
+                    let r = self.finished.pop().unwrap();
+                    let l = self.finished.pop().unwrap();
+                    self.emit(Instr::With(l, r))
+                }
+                Task::Fix => {
+                    let code = self.finished.pop().unwrap();
+                    self.emit(Instr::Fix(code))
+                }
+            }
+        }
+        self.finished.pop().unwrap()
+    }
+
+    fn term<'t>(
+        &mut self,
+        term: &'t Erased,
+        tail: bool,
+        tasks: &mut Vec<Task<'t>>,
+    ) {
+        let mut then = vec![];
+        match &**term {
+            ETuple(items) => {
+                then.extend(items.iter().map(|x| Task::Compile(x, false)));
+                then.push(Task::Emit(Instr::Tuple(items.len())))
+            }
+            EProj(x, index) => {
+                then.push(Task::Compile(x, false));
+                then.push(Task::Emit(Instr::Proj(*index)))
+            }
+            EWith(l, r) => then.extend(vec![
+                Task::Enter(l.clone(), None),
+                Task::Compile(l, true),
+                Task::Exit,
+                Task::Enter(r.clone(), None),
+                Task::Compile(r, true),
+                Task::Exit,
+                Task::With,
+            ]),
+            ERecord(fields) => {
+                then.extend(
+                    fields.iter().map(|(_, x)| Task::Compile(x, false)),
+                );
+                then.push(Task::Emit(Instr::Record(labels(fields))))
+            }
+            EField(x, l) => {
+                then.push(Task::Compile(x, false));
+                then.push(Task::Emit(Instr::Field(l.as_str().into())))
+            }
+            EUpdate(x, fields) => {
+                then.push(Task::Compile(x, false));
+                then.extend(
+                    fields.iter().map(|(_, y)| Task::Compile(y, false)),
+                );
+                then.push(Task::Emit(Instr::Update(labels(fields))))
+            }
+            EInj(l, x) => {
+                then.push(Task::Compile(x, false));
+                then.push(Task::Emit(Instr::Inj(l.as_str().into())))
+            }
+            ECase(x, arms) => {
+                let end = self.label();
+                let mut table = vec![];
+                let mut bodies = vec![];
+                for (l, p, y) in arms {
+                    let label = self.label();
+                    table.push((l.as_str().into(), label));
+                    bodies.extend(vec![
+                        Task::Label(label),
+                        Task::Bind(p),
+                        Task::Compile(y, tail),
+                        Task::Unbind(arity(p)),
+                        Task::Emit(Instr::Jump(end)),
+                    ]);
+                }
+                then.push(Task::Compile(x, false));
+                then.push(Task::Emit(Instr::Case(table)));
+                then.extend(bodies);
+                then.push(Task::Label(end))
+            }
+            EVar(var) => {
+                let (slot, kind) = self.resolve(*var);
+                then.push(Task::Emit(Instr::Load(slot)));
+                if kind == Kind::Fix {
+                    then.push(Task::Emit(Instr::Unroll))
+                }
+            }
+            EAbs(v, y) => then.extend(vec![
+                Task::Enter(term.clone(), Some((*v, Kind::Value))),
+                Task::Compile(y, true),
+                Task::Exit,
+                Task::Closure,
+            ]),
+            EApp(f, x) => then.extend(vec![
+                Task::Compile(f, false),
+                Task::Compile(x, false),
+                Task::Emit(if tail { Instr::TailCall } else { Instr::Call }),
+            ]),
+            ELet(p, x, y) => then.extend(vec![
+                Task::Compile(x, false),
+                Task::Bind(p),
+                Task::Compile(y, tail),
+                Task::Unbind(arity(p)),
+            ]),
+            EFix(f, x) => then.extend(vec![
+                Task::Enter(term.clone(), Some((*f, Kind::Fix))),
+                Task::Compile(x, true),
+                Task::Exit,
+                Task::Fix,
+            ]),
+            EHole(name) => then.push(Task::Emit(Instr::Hole(name.clone()))),
+        }
+        tasks.extend(then.into_iter().rev())
+    }
+
+    /// Binds the operand on top of the stack to the variables of `pattern`.
+    fn pattern<'t>(&mut self, pattern: &'t Pattern, tasks: &mut Vec<Task<'t>>) {
+        match pattern {
+            PtVar(var) => {
+                self.function().locals.push((*var, Kind::Value));
+                self.emit(Instr::Bind)
+            }
+            PtWild => self.emit(Instr::Pop),
+            PtTuple(items) => {
+                self.emit(Instr::Untuple);
+                tasks.extend(items.iter().rev().map(Task::Bind))
+            }
+            PtLeft(p) => {
+                self.emit(Instr::Left);
+                tasks.push(Task::Bind(p))
+            }
+            PtRight(p) => {
+                self.emit(Instr::Right);
+                tasks.push(Task::Bind(p))
+            }
+        }
+    }
+
+    /// Finds where `var` is bound, capturing it in every function between
+    /// the binding and the current one.
+    fn resolve(&mut self, var: Var) -> (Slot, Kind) {
+        let mut depth = self.functions.len() - 1;
+        let (mut slot, kind) = loop {
+            let function = &self.functions[depth];
+            let mut locals = function.locals.iter();
+            if let Some(i) = locals.rposition(|(v, _)| *v == var) {
+                break (Slot::Local(i), function.locals[i].1);
+            }
+            let mut captures = function.captures.iter();
+            if let Some(i) = captures.position(|(v, _, _)| *v == var) {
+                break (Slot::Captured(i), function.captures[i].1);
+            }
+            depth -= 1;
+        };
+        for function in &mut self.functions[depth + 1..] {
+            function.captures.push((var, kind, slot));
+            slot = Slot::Captured(function.captures.len() - 1);
+        }
+        (slot, kind)
+    }
+
+    fn exit(&mut self) {
+        let mut function = self.functions.pop().unwrap();
+        function.instrs.push(Instr::Return);
+        let labels = function.labels;
+        for instr in &mut function.instrs {
+            match instr {
+                Instr::Jump(target) => *target = labels[*target],
+                Instr::Case(table) => {
+                    for (_, target) in table {
+                        *target = labels[*target]
+                    }
+                }
+                _ => {}
+            }
+        }
+        let captures = function.captures.into_iter();
+        self.finished.push(Rc::new(Code {
+            instrs: function.instrs,
+            captures: captures.map(|(var, _, slot)| (var, slot)).collect(),
+            source: function.source,
+        }))
+    }
+
+    fn label(&mut self) -> usize {
+        let labels = &mut self.function().labels;
+        labels.push(0);
+        labels.len() - 1
+    }
+
+    fn emit(&mut self, instr: Instr) {
+        self.function().instrs.push(instr)
+    }
+
+    fn function(&mut self) -> &mut Function {
+        self.functions.last_mut().unwrap()
+    }
+}
+
+fn labels(fields: &[(String, Erased)]) -> Vec<Rc<str>> {
+    fields
+        .iter()
+        .map(|(label, _)| label.as_str().into())
+        .collect()
+}
+
+fn arity(pattern: &Pattern) -> usize {
+    match pattern {
+        PtVar(_) => 1,
+        PtWild => 0,
+        PtTuple(items) => items.iter().map(arity).sum(),
+        PtLeft(p) | PtRight(p) => arity(p),
+    }
+}
+
+struct Frame {
+    code: Rc<Code>,
+    pc: usize,
+    /// The closure or recursive definition being run, holding the captured
+    /// values.
+    closure: Value,
+    locals: Vec<Value>,
+}
+
+impl Frame {
+    fn new(closure: Value, locals: Vec<Value>) -> Self {
+        let code = match &*closure {
+            VClosure(code, _) | VFix(code, _) => code.clone(),
+            _ => unreachable!(),
+        };
+        Self {
+            code,
+            pc: 0,
+            closure,
+            locals,
+        }
+    }
+
+    fn load(&self, slot: Slot) -> Value {
+        match (slot, &*self.closure) {
+            (Slot::Local(i), _) => self.locals[i].clone(),
+            (Slot::Captured(i), VClosure(_, captured) | VFix(_, captured)) => {
+                captured[i].clone()
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn captures(&self, code: &Code) -> Vec<Value> {
+        code.captures
+            .iter()
+            .map(|(_, slot)| self.load(*slot))
+            .collect()
+    }
+}
+
+struct Machine {
+    budget: Budget,
+    steps: usize,
+    stack: Vec<Value>,
+    frames: Vec<Frame>,
+}
+
+impl Machine {
+    fn new(budget: Budget) -> Self {
+        Self {
+            budget,
+            steps: 0,
+            stack: vec![],
+            frames: vec![],
+        }
+    }
+
+    fn run(&mut self, code: &Rc<Code>) -> Result<Value, Stopped> {
+        let program = Rc::new(VClosure(code.clone(), vec![]));
+        let mut frame = Frame::new(program, vec![]);
+        loop {
+            if self.budget.exhausted(self.steps) {
+                return Err(Stopped::OutOfBudget(self.steps));
+            }
+            self.steps += 1;
+            let code = frame.code.clone();
+            frame.pc += 1;
+            match &code.instrs[frame.pc - 1] {
+                Instr::Load(slot) => self.stack.push(frame.load(*slot)),
+                Instr::Bind => frame.locals.push(self.pop()),
+                Instr::Unbind(count) => {
+                    frame.locals.truncate(frame.locals.len() - count)
+                }
+                Instr::Pop => {
+                    self.pop();
+                }
+                Instr::Tuple(len) => {
+                    let items = self.stack.split_off(self.stack.len() - len);
+                    self.stack.push(Rc::new(VTuple(items)))
+                }
+                Instr::Untuple => match &*self.pop() {
+                    VTuple(items) => {
+                        self.stack.extend(items.iter().rev().cloned())
+                    }
+                    _ => unreachable!(),
+                },
+                Instr::Proj(index) => match &*self.pop() {
+                    VTuple(items) => self.stack.push(items[*index].clone()),
+                    _ => unreachable!(),
+                },
+                Instr::Record(labels) => {
+                    let values =
+                        self.stack.split_off(self.stack.len() - labels.len());
+                    let fields = labels.iter().cloned().zip(values).collect();
+                    self.stack.push(Rc::new(VRecord(fields)))
+                }
+                Instr::Field(label) => match &*self.pop() {
+                    VRecord(fields) => self.stack.push(field(fields, label)),
+                    _ => unreachable!(),
+                },
+                Instr::Update(labels) => {
+                    let values =
+                        self.stack.split_off(self.stack.len() - labels.len());
+                    let mut fields = match &*self.pop() {
+                        VRecord(fields) => fields.clone(),
+                        _ => unreachable!(),
+                    };
+                    for (label, value) in labels.iter().zip(values) {
+                        if let Some(field) =
+                            fields.iter_mut().find(|(l, _)| l == label)
+                        {
+                            field.1 = value;
+                        }
+                    }
+                    self.stack.push(Rc::new(VRecord(fields)))
+                }
+                Instr::Inj(label) => {
+                    let x = self.pop();
+                    self.stack.push(Rc::new(VInj(label.clone(), x)))
+                }
+                Instr::Case(table) => match &*self.pop() {
+                    VInj(label, x) => {
+                        self.stack.push(x.clone());
+                        let arm = table.iter().find(|(l, _)| l == label);
+                        frame.pc = arm.unwrap().1
+                    }
+                    _ => unreachable!(),
+                },
+                Instr::Jump(target) => frame.pc = *target,
+                Instr::Closure(code) => {
+                    let captured = frame.captures(code);
+                    self.stack.push(Rc::new(VClosure(code.clone(), captured)))
+                }
+                Instr::With(l, r) => {
+                    let l = Rc::new(VClosure(l.clone(), frame.captures(l)));
+                    let r = Rc::new(VClosure(r.clone(), frame.captures(r)));
+                    self.stack.push(Rc::new(VWith(l, r)))
+                }
+                Instr::Fix(code) => {
+                    let fix = Rc::new(VFix(code.clone(), frame.captures(code)));
+                    self.call(&mut frame, fix.clone(), vec![fix])
+                }
+                Instr::Unroll => {
+                    let fix = self.pop();
+                    self.call(&mut frame, fix.clone(), vec![fix])
+                }
+                Instr::Call => {
+                    let x = self.pop();
+                    let f = self.pop();
+                    self.call(&mut frame, f, vec![x])
+                }
+                Instr::TailCall => {
+                    let x = self.pop();
+                    frame = Frame::new(self.pop(), vec![x])
+                }
+                Instr::Left => match &*self.pop() {
+                    VWith(l, _) => self.call(&mut frame, l.clone(), vec![]),
+                    _ => unreachable!(),
+                },
+                Instr::Right => match &*self.pop() {
+                    VWith(_, r) => self.call(&mut frame, r.clone(), vec![]),
+                    _ => unreachable!(),
+                },
+                Instr::Return => match self.frames.pop() {
+                    Some(caller) => frame = caller,
+                    None => return Ok(self.pop()),
+                },
+                Instr::Hole(Some(name)) => {
+                    return Err(Stopped::Hole(name.clone()))
+                }
+                Instr::Hole(None) => return Err(Stopped::AnonymousHole),
+            }
+        }
+    }
+
+    fn call(&mut self, frame: &mut Frame, callee: Value, locals: Vec<Value>) {
+        let callee = Frame::new(callee, locals);
+        self.frames.push(mem::replace(frame, callee))
+    }
+
+    fn pop(&mut self) -> Value {
+        self.stack.pop().unwrap()
+    }
+}
+
+fn field(fields: &[(Rc<str>, Value)], label: &str) -> Value {
+    let (_, value) = fields.iter().find(|(l, _)| &**l == label).unwrap();
+    value.clone()
+}
+
+/// Reads a value back into a term. Closures are read back as the source they
+/// were compiled from, with their captured values substituted.
+fn quote(value: &Value) -> Erased {
+    enum Task<'v> {
+        Value(&'v Value),
+        Build(&'v Value),
+    }
+    let mut tasks = vec![Task::Value(value)];
+    let mut done: Vec<Erased> = vec![];
+    while let Some(task) = tasks.pop() {
+        match task {
+            Task::Value(value) => {
+                tasks.push(Task::Build(value));
+                match &**value {
+                    VTuple(items) | VClosure(_, items) | VFix(_, items) => {
+                        tasks.extend(items.iter().rev().map(Task::Value))
+                    }
+                    VRecord(fields) => tasks.extend(
+                        fields.iter().rev().map(|(_, x)| Task::Value(x)),
+                    ),
+                    VInj(_, x) => tasks.push(Task::Value(x)),
+                    VWith(l, r) => {
+                        tasks.extend(vec![Task::Value(r), Task::Value(l)])
+                    }
+                }
+            }
+            Task::Build(value) => {
+                let term = match &**value {
+                    VTuple(items) => {
+                        ETuple(done.split_off(done.len() - items.len())).into()
+                    }
+                    VRecord(fields) => {
+                        let values = done.split_off(done.len() - fields.len());
+                        let labels = fields.iter().map(|(l, _)| l.to_string());
+                        ERecord(labels.zip(values).collect()).into()
+                    }
+                    VInj(label, _) => {
+                        let x = done.pop().unwrap();
+                        EInj(label.to_string(), x).into()
+                    }
+                    VClosure(code, captured) | VFix(code, captured) => {
+                        let values =
+                            done.split_off(done.len() - captured.len());
+                        let vars = code.captures.iter().map(|(var, _)| *var);
+                        vars.zip(values).fold(
+                            code.source.clone(),
+                            |source, (var, value)| subst(value, source, var),
+                        )
+                    }
+                    VWith(_, _) => {
+                        let r = done.pop().unwrap();
+                        EWith(done.pop().unwrap(), r).into()
+                    }
+                };
+                done.push(term)
+            }
+        }
+    }
+    done.pop().unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+    use crate::{
+        erase::erase,
+        eval::{eval, CallByValue},
+        testing::{checked, church, programs},
+    };
+
+    fn machine(term: &Term) -> Erased {
+        run(&compile(&erase(term)), Budget::default()).unwrap()
+    }
+
+    #[test]
+    fn agrees_with_eval() {
+        for (term, _) in programs() {
+            let value = eval(erase(&term), CallByValue, Budget::default());
+            assert_eq!(machine(&term), value.unwrap());
+        }
+    }
+
+    #[test]
+    #[ignore]
+    fn bench_church_numerals() {
+        for n in [10, 40, 160] {
+            let term = church(n, "mul n n [()] (x: () -> x) ()");
+            let start = Instant::now();
+            let evaluated = eval(erase(&term), CallByValue, Budget::default());
+            let eval_time = start.elapsed();
+            let start = Instant::now();
+            let code = compile(&erase(&term));
+            let compile_time = start.elapsed();
+            let start = Instant::now();
+            let value = run(&code, Budget::default());
+            let run_time = start.elapsed();
+            assert_eq!(value.unwrap(), evaluated.unwrap());
+            println!(
+                "church {:>3}^2: eval {:>10.2?}, compile {:>10.2?}, run {:>10.2?}",
+                n, eval_time, compile_time, run_time
+            );
+        }
+    }
+
+    #[test]
+    fn deep_terms() {
+        let depth = 1_000_000;
+        let mut names = Names::default();
+        let (f, x) = (names.push("f".into()), names.push("x".into()));
+        let mut body = de::unit();
+        for _ in 0..depth {
+            body = de::app(de::var(f), de::tuple(vec![body]));
+        }
+        let apply = de::app(
+            de::abs(f, ty::hole(), body),
+            de::abs(x, ty::hole(), de::var(x)),
+        );
+        let mut value = machine(&apply);
+        for _ in 0..depth {
+            value = match &*value {
+                ETuple(items) if items.len() == 1 => items[0].clone(),
+                _ => panic!("expected a single-element tuple"),
+            };
+        }
+        assert_eq!(value, ETuple(vec![]).into());
+    }
+
+    #[test]
+    fn budgets() {
+        let term = checked("let rec loop: () -> () = x: () -> loop x; loop ()");
+        let budget = Budget {
+            steps: Some(1000),
+            interrupt: None,
+        };
+        let code = compile(&erase(&term));
+        let err = run(&code, budget).unwrap_err();
+        assert!(matches!(err, Stopped::OutOfBudget(1000)));
+    }
+
+    #[test]
+    fn holes() {
+        let code = compile(&erase(&checked("let f = x: () -> x; f ?later")));
+        let err = run(&code, Budget::default()).unwrap_err();
+        assert!(matches!(err, Stopped::Hole(name) if name == "later"));
+    }
+}